@@ -0,0 +1,302 @@
+extern crate jokrey_utilities;
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+
+use jokrey_utilities::encoding::tag_based::bytes::libae::LIbae;
+use jokrey_utilities::encoding::tag_based::bytes::libae::LIbaeTraits;
+use jokrey_utilities::encoding::tag_based::bytes::remote::rbae_mcnp_causes::DEFAULT_SERVER_PORT;
+use jokrey_utilities::encoding::tag_based::bytes::remote::rbae_server::RbaeServer;
+use jokrey_utilities::encoding::tag_based::bytes::ubae::Ubae;
+use jokrey_utilities::encoding::tag_based::bytes::ubae::UbaeTraits;
+use jokrey_utilities::encoding::tag_based::bytes::ubae_directory_encoder;
+use jokrey_utilities::encoding::tag_based::bytes::ubae_directory_encoder::OverwritePolicy;
+use jokrey_utilities::encoding::tag_based::bytes::ubae_directory_encoder::PathValidation;
+use jokrey_utilities::encoding::tag_based::bytes::ubae_directory_encoder::HASH_TAG_PREFIX;
+use jokrey_utilities::encoding::tag_based::bytes::ubae_directory_encoder::METADATA_TAG_PREFIX;
+use jokrey_utilities::encoding::tag_based::bytes::ubae_tar;
+use jokrey_utilities::transparent_storage::bytes::file_storage_system::FileStorageSystem;
+
+const USAGE:&str = "usage: ubae <command> [arguments]
+
+commands:
+  ls FILE                      lists the tags (and entry lengths) of the container
+  cat FILE TAG                 writes the entry of TAG to stdout
+  put FILE TAG SOURCE          stores the content of SOURCE (- for stdin) under TAG, replacing an existing entry
+  rm FILE TAG                  deletes the entry of TAG
+  pack [--dedup] [--threads N] DIR FILE
+                               encodes the directory DIR into the new container FILE
+  unpack [--strict] [--special-mode-bits] [--threads N] FILE DIR
+                               decodes the directory container FILE into the new directory DIR
+                               (setuid, setgid and sticky bits only with --special-mode-bits)
+  export-tar FILE TAR          writes the directory container FILE as the tar stream TAR (- for stdout)
+  import-tar TAR FILE          reads the tar stream TAR (- for stdin) into the new directory container FILE
+  fsck FILE                    checks that the container is decodable (and the content of a directory container)
+  serve FILE [PORT]            serves the container as an rbae server (default port 59183)";
+
+fn main() {
+    let args:Vec<String> = env::args().skip(1).collect();
+    let args:Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let result = match args.split_first() {
+        Some((&"ls", &[file])) => ls(file),
+        Some((&"cat", &[file, tag])) => cat(file, tag),
+        Some((&"put", &[file, tag, source])) => put(file, tag, source),
+        Some((&"rm", &[file, tag])) => rm(file, tag),
+        Some((&"pack", rest)) => pack(rest),
+        Some((&"unpack", rest)) => unpack(rest),
+        Some((&"export-tar", &[file, tar])) => export_tar(file, tar),
+        Some((&"import-tar", &[tar, file])) => import_tar(tar, file),
+        Some((&"fsck", &[file])) => fsck(file),
+        Some((&"serve", &[file])) => serve(file, &DEFAULT_SERVER_PORT.to_string()),
+        Some((&"serve", &[file, port])) => serve(file, port),
+        Some((&"help", _)) | Some((&"--help", _)) | Some((&"-h", _)) => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        _ => Err(CliError::Usage)
+    };
+
+    match result {
+        Ok(_) => {},
+        Err(CliError::Usage) => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+        Err(CliError::Failed(message)) => {
+            eprintln!("ubae: {}", message);
+            process::exit(1);
+        }
+    }
+}
+
+enum CliError {
+    Usage,
+    Failed(String)
+}
+impl<E:std::fmt::Display> From<E> for CliError {
+    fn from(e:E) -> Self {
+        CliError::Failed(e.to_string())
+    }
+}
+
+fn open_existing(file:&str) -> Result<FileStorageSystem, CliError> {
+    if !Path::new(file).is_file() {
+        return Err(CliError::Failed(format!("{} is not a file", file)));
+    }
+    Ok(FileStorageSystem::create_leave_source_intact(file))
+}
+
+fn ls(file:&str) -> Result<(), CliError> {
+    let mut libae = LIbae::new(open_existing(file)?);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    while let Some(tag) = libae.li_try_decode_single()? {
+        let length = libae.li_try_skip_single()?.ok_or("truncated container: tag without entry")?;
+        writeln!(out, "{}\t{}", length, String::from_utf8_lossy(&tag).escape_debug())?;
+    }
+    Ok(())
+}
+
+fn cat(file:&str, tag:&str) -> Result<(), CliError> {
+    let mut ubae = Ubae::new(open_existing(file)?);
+    match ubae.get_entry_as_stream(tag)? {
+        Some((mut stream, _)) => {
+            let stdout = io::stdout();
+            io::copy(&mut stream, &mut stdout.lock())?;
+            Ok(())
+        },
+        None => Err(CliError::Failed(format!("no entry with tag {:?}", tag)))
+    }
+}
+
+fn put(file:&str, tag:&str, source:&str) -> Result<(), CliError> {
+    let mut ubae = Ubae::new(FileStorageSystem::create_leave_source_intact(file));
+    if source == "-" {
+        let mut content = Vec::new();
+        io::stdin().read_to_end(&mut content)?;
+        ubae.add_entry(tag, &content)?;
+    } else {
+        let mut source_file = File::open(source)?;
+        let length = source_file.metadata()?.len() as i64;
+        ubae.add_entry_from_stream(tag, &mut source_file, length)?;
+    }
+    Ok(())
+}
+
+fn rm(file:&str, tag:&str) -> Result<(), CliError> {
+    let mut ubae = Ubae::new(open_existing(file)?);
+    if ubae.delete_entry_noreturn(tag)? {
+        Ok(())
+    } else {
+        Err(CliError::Failed(format!("no entry with tag {:?}", tag)))
+    }
+}
+
+//splits off the flags (and the values of the flags in with_value) from the positional arguments
+fn parse_flags<'a>(args:&[&'a str], with_value:&[&str]) -> Result<(Vec<(&'a str, Option<&'a str>)>, Vec<&'a str>), CliError> {
+    let mut flags = Vec::new();
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg);
+        } else if with_value.contains(&arg) {
+            flags.push((arg, Some(*args.next().ok_or(CliError::Usage)?)));
+        } else {
+            flags.push((arg, None));
+        }
+    }
+    Ok((flags, positional))
+}
+
+fn threads_flag(flags:&[(&str, Option<&str>)]) -> Result<usize, CliError> {
+    match flags.iter().find(|(flag, _)| *flag == "--threads") {
+        Some((_, Some(threads))) => threads.parse().map_err(|_| CliError::Failed(format!("invalid number of threads: {}", threads))),
+        _ => Ok(1)
+    }
+}
+
+fn pack(args:&[&str]) -> Result<(), CliError> {
+    let (flags, positional) = parse_flags(args, &["--threads"])?;
+    let (dir, file) = match positional[..] {
+        [dir, file] => (dir, file),
+        _ => return Err(CliError::Usage)
+    };
+    let mut deduplicate = false;
+    for (flag, _) in &flags {
+        match *flag {
+            "--dedup" => deduplicate = true,
+            "--threads" => {},
+            _ => return Err(CliError::Usage)
+        }
+    }
+    if !Path::new(dir).is_dir() {
+        return Err(CliError::Failed(format!("{} is not a directory", dir)));
+    }
+    if Path::new(file).exists() {
+        return Err(CliError::Failed(format!("{} already exists", file)));
+    }
+
+    let report = ubae_directory_encoder::encode_with_report(dir, file, threads_flag(&flags)?, deduplicate, None);
+    finish_report(&report, false)
+}
+
+fn unpack(args:&[&str]) -> Result<(), CliError> {
+    let (flags, positional) = parse_flags(args, &["--threads"])?;
+    let (file, dir) = match positional[..] {
+        [file, dir] => (file, dir),
+        _ => return Err(CliError::Usage)
+    };
+    let mut path_validation = PathValidation::Normalize;
+    let mut special_mode_bits = false;
+    for (flag, _) in &flags {
+        match *flag {
+            "--strict" => path_validation = PathValidation::Strict,
+            "--special-mode-bits" => special_mode_bits = true,
+            "--threads" => {},
+            _ => return Err(CliError::Usage)
+        }
+    }
+    open_existing(file)?;
+    if Path::new(dir).exists() {
+        return Err(CliError::Failed(format!("{} already exists", dir)));
+    }
+
+    let report = ubae_directory_encoder::extract_with_report(file, dir, None, OverwritePolicy::Overwrite, path_validation, special_mode_bits, threads_flag(&flags)?, None);
+    finish_report(&report, false)
+}
+
+fn export_tar(file:&str, tar:&str) -> Result<(), CliError> {
+    open_existing(file)?;
+    let report = if tar == "-" {
+        let stdout = io::stdout();
+        ubae_tar::export_tar(file, stdout.lock())?.1
+    } else {
+        if Path::new(tar).exists() {
+            return Err(CliError::Failed(format!("{} already exists", tar)));
+        }
+        let (mut tar_file, report) = ubae_tar::export_tar(file, io::BufWriter::new(File::create(tar)?))?;
+        tar_file.flush()?;
+        report
+    };
+    finish_report(&report, tar == "-")
+}
+
+fn import_tar(tar:&str, file:&str) -> Result<(), CliError> {
+    let report = if tar == "-" {
+        let stdin = io::stdin();
+        ubae_tar::import_tar(stdin.lock(), file)?
+    } else {
+        ubae_tar::import_tar(io::BufReader::new(File::open(tar)?), file)?
+    };
+    finish_report(&report, false)
+}
+
+//the summary goes to stderr if stdout is taken (by a tar stream)
+fn finish_report(report:&ubae_directory_encoder::DirectoryReport, summary_to_stderr:bool) -> Result<(), CliError> {
+    for (path, e) in &report.failed {
+        eprintln!("{}: {}", path, e);
+    }
+    if summary_to_stderr {
+        eprintln!("{}", report);
+    } else {
+        println!("{}", report);
+    }
+    if report.is_success() {
+        Ok(())
+    } else {
+        Err(CliError::Failed(format!("{} paths failed", report.error_count())))
+    }
+}
+
+fn fsck(file:&str) -> Result<(), CliError> {
+    let mut libae = LIbae::new(open_existing(file)?);
+    let mut tags = HashSet::new();
+    let mut problems = Vec::new();
+    let mut is_directory_container = false;
+    while let Some(tag) = libae.li_try_decode_single()? {
+        if libae.li_try_skip_single()?.is_none() {
+            problems.push(format!("{:?}: tag without entry (truncated container)", String::from_utf8_lossy(&tag)));
+            break
+        }
+        match String::from_utf8(tag) {
+            Ok(tag) => {
+                is_directory_container |= tag.starts_with(METADATA_TAG_PREFIX) || tag.starts_with(HASH_TAG_PREFIX);
+                if !tags.insert(tag.clone()) {
+                    problems.push(format!("{:?}: duplicate tag", tag));
+                }
+            },
+            Err(e) => problems.push(format!("{:?}: tag is not valid utf8", String::from_utf8_lossy(e.as_bytes())))
+        }
+    }
+
+    if is_directory_container && problems.is_empty() {
+        for (path, problem) in ubae_directory_encoder::verify(file)? {
+            problems.push(format!("{}: {}", path, problem));
+        }
+    }
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("{} entries, no problems found", tags.len());
+        Ok(())
+    } else {
+        Err(CliError::Failed(format!("{} problems found", problems.len())))
+    }
+}
+
+fn serve(file:&str, port:&str) -> Result<(), CliError> {
+    let port:u16 = port.parse().map_err(|_| CliError::Failed(format!("invalid port: {}", port)))?;
+    let server = RbaeServer::new_rbae(port, Ubae::new(FileStorageSystem::create_leave_source_intact(file)));
+    println!("serving {} on port {}", file, port);
+    server.run_logic_loop();
+    Ok(())
+}
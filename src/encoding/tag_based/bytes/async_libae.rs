@@ -0,0 +1,189 @@
+extern crate tokio;
+
+use std::cmp;
+
+use self::tokio::io::AsyncRead;
+use self::tokio::io::AsyncWrite;
+
+use crate::transparent_storage::async_storage::AsyncStorageSystem;
+use crate::transparent_storage::StorageSystemError;
+
+use super::libae::get_length_indicator_for;
+use super::libae::li_chunk_from_cache;
+use super::libae::LIEncoding;
+use super::libae::MAX_LENGTH_INDICATOR_SIZE;
+use super::libae::VARINT_ENCODING_HEADER;
+
+/// Async counterpart of LIbaeTraits.
+///   Only the fallible(try) flavour of decoding exists, Ok(None) means the content ended cleanly.
+///   Instead of a substream, chunks can be copied into any AsyncWrite (li_decode_single_into).
+#[allow(async_fn_in_trait)]
+pub trait AsyncLIbaeTraits {
+    async fn set_content(&mut self, bytes : &[u8]) -> Result<(), StorageSystemError>;
+    async fn get_content(&mut self) -> Result<Vec<u8>, StorageSystemError>;
+
+    async fn li_encode_single(&mut self, bytes : &[u8]) -> Result<(), StorageSystemError>;
+    async fn li_encode_single_stream<R:AsyncRead + Unpin + ?Sized>(&mut self, stream : &mut R, stream_length:i64) -> Result<(), StorageSystemError>;
+
+    async fn li_decode_single(&mut self) -> Result<Option<Vec<u8>>, StorageSystemError>;
+    /// copies the next chunk into target, returns the chunks length
+    async fn li_decode_single_into<W:AsyncWrite + Unpin + ?Sized>(&mut self, target:&mut W) -> Result<Option<i64>, StorageSystemError>;
+
+    fn reset_read_pointer(&mut self);
+
+    async fn li_delete_single(&mut self) -> Result<Option<Vec<u8>>, StorageSystemError>;
+    async fn li_skip_single(&mut self) -> Result<Option<i64>, StorageSystemError>;
+
+    /// decodes the remaining chunks (like LIbae, call reset_read_pointer first to decode all of them)
+    async fn li_decode_all(&mut self) -> Result<Vec<Vec<u8>>, StorageSystemError>;
+}
+
+/// Async LIbae over an AsyncStorageSystem.
+///   Reads and writes exactly what LIbae reads and writes, so content can be shared between the two.
+pub struct AsyncLIbae<T:AsyncStorageSystem> {
+    read_pointer:i64,
+    encoding:LIEncoding,
+    pub storage_system:T // public so that the user can still directly access the storage system
+}
+impl<T:AsyncStorageSystem> AsyncLIbae<T> {
+    /// Uses the encoding of the existing content in the storage system. (see LIbae::new)
+    pub async fn new(mut storagesystem:T) -> Result<AsyncLIbae<T>, StorageSystemError> {
+        let encoding = detect_in(&mut storagesystem).await?;
+        Ok(AsyncLIbae {
+            read_pointer:encoding.header_length(),
+            encoding,
+            storage_system: storagesystem,
+        })
+    }
+
+    /// Writes the header of the given encoding if the storage system is empty.
+    ///   Fails if the storage system already contains content of a different encoding.
+    pub async fn new_with_encoding(mut storagesystem:T, encoding:LIEncoding) -> Result<AsyncLIbae<T>, StorageSystemError> {
+        if storagesystem.content_size().await? == 0 {
+            write_header(&mut storagesystem, encoding).await?;
+        } else if detect_in(&mut storagesystem).await? != encoding {
+            return Err(StorageSystemError::new(&format!("existing content is not {:?} encoded", encoding)));
+        }
+        Ok(AsyncLIbae {
+            read_pointer:encoding.header_length(),
+            encoding,
+            storage_system: storagesystem,
+        })
+    }
+
+    pub fn encoding(&self) -> LIEncoding {
+        self.encoding
+    }
+
+    pub fn manually_get_read_pointer(&self) -> i64 {
+        self.read_pointer
+    }
+
+    //async version of libae::get_start_and_end_index_of_next_li_chunk
+    async fn next_li_chunk(&mut self) -> Result<Option<(i64, i64)>, StorageSystemError> {
+        let start_index = self.read_pointer;
+        let content_size = self.storage_system.content_size().await?;
+        if start_index >= content_size {
+            return Ok(None);
+        }
+        let cache = self.storage_system.subarray(start_index, cmp::min(start_index + MAX_LENGTH_INDICATOR_SIZE as i64, content_size)).await?;
+        li_chunk_from_cache(start_index, content_size, &cache, self.encoding).map(Some)
+    }
+}
+
+impl<T:AsyncStorageSystem> AsyncLIbaeTraits for AsyncLIbae<T> {
+    /// The encoding is re-detected from the new content, empty content keeps the current encoding.
+    async fn set_content(&mut self, bytes: &[u8]) -> Result<(), StorageSystemError> {
+        self.storage_system.set_content(bytes).await?;
+        if bytes.is_empty() {
+            write_header(&mut self.storage_system, self.encoding).await?;
+        } else {
+            self.encoding = LIEncoding::detect(Some(bytes[0]));
+        }
+        self.reset_read_pointer();
+        Ok(())
+    }
+    async fn get_content(&mut self) -> Result<Vec<u8>, StorageSystemError> {
+        self.storage_system.get_content().await
+    }
+
+    async fn li_encode_single(&mut self, bytes: &[u8]) -> Result<(), StorageSystemError> {
+        self.storage_system.append(&get_length_indicator_for(bytes.len() as i64, self.encoding)).await?;
+        self.storage_system.append(bytes).await
+    }
+
+    async fn li_encode_single_stream<R:AsyncRead + Unpin + ?Sized>(&mut self, stream: &mut R, stream_length: i64) -> Result<(), StorageSystemError> {
+        if stream_length < 0 {
+            return Err(StorageSystemError::new("negative stream length"));
+        }
+        self.storage_system.append(&get_length_indicator_for(stream_length, self.encoding)).await?;
+        self.storage_system.append_stream(stream, stream_length).await
+    }
+
+    async fn li_decode_single(&mut self) -> Result<Option<Vec<u8>>, StorageSystemError> {
+        match self.next_li_chunk().await? {
+            None => Ok(None),
+            Some((start, end)) => {
+                self.read_pointer = end;
+                Ok(Some(self.storage_system.subarray(start, end).await?))
+            }
+        }
+    }
+
+    async fn li_decode_single_into<W:AsyncWrite + Unpin + ?Sized>(&mut self, target: &mut W) -> Result<Option<i64>, StorageSystemError> {
+        match self.next_li_chunk().await? {
+            None => Ok(None),
+            Some((start, end)) => {
+                self.read_pointer = end;
+                self.storage_system.copy_range_to(start, end, target).await?;
+                Ok(Some(end - start))
+            }
+        }
+    }
+
+    fn reset_read_pointer(&mut self) {
+        self.read_pointer = self.encoding.header_length();
+    }
+
+    /// Decodes and deletes the chunk at the read pointer.
+    async fn li_delete_single(&mut self) -> Result<Option<Vec<u8>>, StorageSystemError> {
+        match self.next_li_chunk().await? {
+            None => Ok(None),
+            Some((start, end)) => {
+                let decoded = self.storage_system.subarray(start, end).await?;
+                self.storage_system.delete(self.read_pointer, end).await?;
+                Ok(Some(decoded))
+            }
+        }
+    }
+
+    async fn li_skip_single(&mut self) -> Result<Option<i64>, StorageSystemError> {
+        match self.next_li_chunk().await? {
+            None => Ok(None),
+            Some((start, end)) => {
+                self.read_pointer = end;
+                Ok(Some(end - start))
+            }
+        }
+    }
+
+    async fn li_decode_all(&mut self) -> Result<Vec<Vec<u8>>, StorageSystemError> {
+        let mut decoded = Vec::new();
+        while let Some(chunk) = self.li_decode_single().await? {
+            decoded.push(chunk);
+        }
+        Ok(decoded)
+    }
+}
+
+async fn detect_in<T:AsyncStorageSystem>(storage_system:&mut T) -> Result<LIEncoding, StorageSystemError> {
+    let first_bytes = storage_system.subarray(0, 1).await?;
+    Ok(LIEncoding::detect(first_bytes.first().cloned()))
+}
+
+async fn write_header<T:AsyncStorageSystem>(storage_system:&mut T, encoding:LIEncoding) -> Result<(), StorageSystemError> {
+    match encoding {
+        LIEncoding::LeadingByte => Ok(()),
+        LIEncoding::Varint => storage_system.append(&[VARINT_ENCODING_HEADER]).await,
+    }
+}
@@ -0,0 +1,119 @@
+extern crate tokio;
+
+use std::env;
+use std::fs;
+
+use crate::encoding::tag_based::bytes::async_libae::AsyncLIbae;
+use crate::encoding::tag_based::bytes::async_libae::AsyncLIbaeTraits;
+use crate::encoding::tag_based::bytes::async_ubae::AsyncUbae;
+use crate::encoding::tag_based::bytes::async_ubae::AsyncUbaeTraits;
+use crate::encoding::tag_based::bytes::libae::LIEncoding;
+use crate::encoding::tag_based::bytes::libae::LIbae;
+use crate::encoding::tag_based::bytes::libae::LIbaeTraits;
+use crate::encoding::tag_based::bytes::ubae::Ubae;
+use crate::encoding::tag_based::bytes::ubae::UbaeTraits;
+use crate::transparent_storage::async_storage::AsyncStorageSystem;
+use crate::transparent_storage::bytes::async_stream_storage_system::AsyncStreamStorageSystem;
+use crate::transparent_storage::bytes::vec_storage_system::VecStorageSystem;
+
+#[tokio::test]
+async fn test_async_li_encoding_matches_sync() {
+    for encoding in [LIEncoding::LeadingByte, LIEncoding::Varint] {
+        let mut libae = AsyncLIbae::new_with_encoding(AsyncStreamStorageSystem::new_in_memory(), encoding).await.unwrap();
+        let mut sync_libae = LIbae::new_with_encoding(VecStorageSystem::new_empty(), encoding).unwrap();
+
+        let chunks:Vec<Vec<u8>> = vec![vec![], vec![1, 2, 3], vec![7; 300], vec![9; 70_000]];
+        for chunk in &chunks {
+            libae.li_encode_single(chunk).await.unwrap();
+            sync_libae.li_encode_single(chunk).unwrap();
+        }
+        let mut stream:&[u8] = &[5, 5, 5];
+        libae.li_encode_single_stream(&mut stream, 5).await.unwrap(); //short stream is padded
+        sync_libae.li_encode_single(&[5, 5, 5, 0, 0]).unwrap();
+
+        assert_eq!(sync_libae.get_content().unwrap(), libae.get_content().await.unwrap());
+
+        assert_eq!(Some(0), libae.li_skip_single().await.unwrap());
+        assert_eq!(Some(vec![1, 2, 3]), libae.li_decode_single().await.unwrap());
+        let mut copied = Vec::new();
+        assert_eq!(Some(300), libae.li_decode_single_into(&mut copied).await.unwrap());
+        assert_eq!(vec![7; 300], copied);
+        assert_eq!(Some(vec![9; 70_000]), libae.li_decode_single().await.unwrap());
+        assert_eq!(Some(vec![5, 5, 5, 0, 0]), libae.li_decode_single().await.unwrap());
+        assert_eq!(None, libae.li_decode_single().await.unwrap());
+
+        libae.reset_read_pointer();
+        assert_eq!(Some(vec![]), libae.li_delete_single().await.unwrap());
+        assert_eq!(Some(vec![1, 2, 3]), libae.li_decode_single().await.unwrap());
+        assert_eq!(3, libae.li_decode_all().await.unwrap().len()); //only the remaining ones, like LIbae
+        assert!(libae.li_decode_all().await.unwrap().is_empty());
+        libae.reset_read_pointer();
+        assert_eq!(4, libae.li_decode_all().await.unwrap().len());
+
+        //content written by the sync implementation is read by the async one
+        let content = sync_libae.get_content().unwrap();
+        let mut storage = AsyncStreamStorageSystem::new_in_memory();
+        storage.set_content(&content).await.unwrap();
+        let mut reread = AsyncLIbae::new(storage).await.unwrap();
+        assert_eq!(encoding, reread.encoding());
+        assert_eq!(5, reread.li_decode_all().await.unwrap().len());
+    }
+}
+
+#[tokio::test]
+async fn test_async_li_decoding_truncated() {
+    let mut libae = AsyncLIbae::new(AsyncStreamStorageSystem::new_in_memory()).await.unwrap();
+    libae.li_encode_single(&[1, 2, 3, 4]).await.unwrap();
+    let mut content = libae.get_content().await.unwrap();
+    content.pop();
+    libae.set_content(&content).await.unwrap();
+    assert!(libae.li_decode_single().await.is_err());
+}
+
+#[tokio::test]
+async fn test_async_ubae() {
+    let mut ubae = AsyncUbae::ram().await;
+    ubae.add_entry("a", &[1, 2]).await.unwrap();
+    ubae.add_entry("b", &[3; 1000]).await.unwrap();
+    let mut stream:&[u8] = &[4, 4, 4, 4];
+    ubae.add_entry_from_stream("c", &mut stream, 4).await.unwrap();
+    ubae.add_entry("a", &[5]).await.unwrap(); //replaces
+
+    assert_eq!(vec!["b", "c", "a"], ubae.get_tags().await.unwrap());
+    assert!(ubae.tag_exists("c").await.unwrap());
+    assert!(!ubae.tag_exists("d").await.unwrap());
+    assert_eq!(1000, ubae.tag_length("b").await.unwrap());
+    assert_eq!(-1, ubae.tag_length("d").await.unwrap());
+    assert_eq!(Some(vec![5]), ubae.get_entry("a").await.unwrap());
+    assert_eq!(None, ubae.get_entry("d").await.unwrap());
+
+    let mut copied = Vec::new();
+    assert_eq!(Some(4), ubae.get_entry_into("c", &mut copied).await.unwrap());
+    assert_eq!(vec![4, 4, 4, 4], copied);
+
+    assert_eq!(Some(vec![3; 1000]), ubae.delete_entry("b").await.unwrap());
+    assert!(ubae.delete_entry_noreturn("c").await.unwrap());
+    assert!(!ubae.delete_entry_noreturn("c").await.unwrap());
+    assert_eq!(vec!["a"], ubae.get_tags().await.unwrap());
+
+    //same bytes as the sync implementation
+    let mut sync_ubae = Ubae::new(VecStorageSystem::new_empty());
+    sync_ubae.add_entry("a", &[5]).unwrap();
+    assert_eq!(sync_ubae.get_content().unwrap(), ubae.get_content().await.unwrap());
+}
+
+#[tokio::test]
+async fn test_async_ubae_file() {
+    let path = env::temp_dir().join("crate_async_ubae_test.ubae");
+    let _ = fs::remove_file(&path);
+    {
+        let mut ubae = AsyncUbae::open(&path).await.unwrap();
+        ubae.add_entry("tag", b"content").await.unwrap();
+        ubae.add_entry("other", b"more content").await.unwrap();
+        ubae.delete_entry_noreturn("tag").await.unwrap();
+    }
+    let mut reopened = AsyncUbae::open(&path).await.unwrap();
+    assert_eq!(vec!["other"], reopened.get_tags().await.unwrap());
+    assert_eq!(Some(b"more content".to_vec()), reopened.get_entry("other").await.unwrap());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,185 @@
+extern crate tokio;
+
+use std::io::Cursor;
+
+use self::tokio::fs::File;
+use self::tokio::io::AsyncRead;
+use self::tokio::io::AsyncWrite;
+
+use crate::transparent_storage::async_storage::AsyncStorageSystem;
+use crate::transparent_storage::bytes::async_stream_storage_system::AsyncStreamStorageSystem;
+use crate::transparent_storage::StorageSystemError;
+
+use super::async_libae::AsyncLIbae;
+use super::async_libae::AsyncLIbaeTraits;
+use super::libae::LIEncoding;
+
+/// Async counterpart of UbaeTraits.
+///   Entries are streamed out by copying them into an AsyncWrite (get_entry_into) instead of a Substream.
+#[allow(async_fn_in_trait)]
+pub trait AsyncUbaeTraits {
+    async fn set_content(&mut self, bytes : &[u8]) -> Result<(), StorageSystemError>;
+    async fn get_content(&mut self) -> Result<Vec<u8>, StorageSystemError>;
+
+    async fn get_tags(&mut self) -> Result<Vec<String>, StorageSystemError>;
+    async fn tag_exists(&mut self, tag:&str) -> Result<bool, StorageSystemError>;
+    async fn tag_length(&mut self, tag:&str) -> Result<i64, StorageSystemError>;
+    async fn get_entry(&mut self, tag:&str) -> Result<Option<Vec<u8>>, StorageSystemError>;
+    async fn get_entry_into<W:AsyncWrite + Unpin + ?Sized>(&mut self, tag:&str, target:&mut W) -> Result<Option<i64>, StorageSystemError>;
+    async fn delete_entry(&mut self, tag:&str) -> Result<Option<Vec<u8>>, StorageSystemError>;
+    async fn delete_entry_noreturn(&mut self, tag:&str) -> Result<bool, StorageSystemError>;
+
+    async fn add_entry(&mut self, tag:&str, content:&[u8]) -> Result<(), StorageSystemError>;
+    async fn add_entry_nocheck(&mut self, tag:&str, content:&[u8]) -> Result<(), StorageSystemError>;
+    async fn add_entry_from_stream<R:AsyncRead + Unpin + ?Sized>(&mut self, tag:&str, stream : &mut R, stream_length:i64) -> Result<(), StorageSystemError>;
+    async fn add_entry_from_stream_nocheck<R:AsyncRead + Unpin + ?Sized>(&mut self, tag:&str, stream : &mut R, stream_length:i64) -> Result<(), StorageSystemError>;
+}
+
+/// Async Ubae, same format and semantics as Ubae.
+///   Unlike Ubae, undecodable content is reported as an error instead of being treated like the end of the content.
+pub struct AsyncUbae<T:AsyncStorageSystem> {
+    libae:AsyncLIbae<T>
+}
+impl<T:AsyncStorageSystem> AsyncUbae<T> {
+    pub async fn new(storagesystem:T) -> Result<AsyncUbae<T>, StorageSystemError> {
+        Ok(AsyncUbae {
+            libae:AsyncLIbae::new(storagesystem).await?
+        })
+    }
+
+    /// see Ubae::new_with_encoding
+    pub async fn new_with_encoding(storagesystem:T, encoding:LIEncoding) -> Result<AsyncUbae<T>, StorageSystemError> {
+        Ok(AsyncUbae {
+            libae:AsyncLIbae::new_with_encoding(storagesystem, encoding).await?
+        })
+    }
+
+    pub fn storage_system(&mut self) -> &mut T {
+        &mut self.libae.storage_system
+    }
+
+    //positions the read pointer at the start of the entry content and returns the start of the tag, if the tag exists
+    async fn seek_to_entry(&mut self, tag:&str) -> Result<Option<i64>, StorageSystemError> {
+        self.libae.reset_read_pointer();
+        let search_tag_as_bytes = tag.as_bytes(); //&str guarantees utf8
+
+        let mut last_read_pointer = self.libae.manually_get_read_pointer();
+        while let Some(decoded_tag) = self.libae.li_decode_single().await? {
+            if search_tag_as_bytes==&decoded_tag[..] {
+                return Ok(Some(last_read_pointer))
+            }
+            if self.libae.li_skip_single().await?.is_none() {
+                return Err(StorageSystemError::new("truncated ubae data: tag without entry"))
+            }
+            last_read_pointer = self.libae.manually_get_read_pointer();
+        }
+        Ok(None)
+    }
+}
+impl AsyncUbae<AsyncStreamStorageSystem<File>> {
+    /// Opens(or creates) the file at path as an ubae system, existing content is kept.
+    pub async fn open<P:AsRef<std::path::Path>>(path:P) -> Result<AsyncUbae<AsyncStreamStorageSystem<File>>, StorageSystemError> {
+        AsyncUbae::new(AsyncStreamStorageSystem::open_leave_source_intact(path).await?).await
+    }
+}
+impl AsyncUbae<AsyncStreamStorageSystem<Cursor<Vec<u8>>>> {
+    pub async fn ram() -> AsyncUbae<AsyncStreamStorageSystem<Cursor<Vec<u8>>>> {
+        AsyncUbae::new(AsyncStreamStorageSystem::new_in_memory()).await.expect("in memory storage cannot fail")
+    }
+}
+
+impl<T:AsyncStorageSystem> AsyncUbaeTraits for AsyncUbae<T> {
+    async fn set_content(&mut self, bytes: &[u8]) -> Result<(), StorageSystemError> {
+        self.libae.set_content(bytes).await
+    }
+    async fn get_content(&mut self) -> Result<Vec<u8>, StorageSystemError> {
+        self.libae.get_content().await
+    }
+
+    async fn get_tags(&mut self) -> Result<Vec<String>, StorageSystemError> {
+        self.libae.reset_read_pointer();
+        let mut tags:Vec<String> = Vec::new();
+
+        while let Some(decoded_tag) = self.libae.li_decode_single().await? {
+            if self.libae.li_skip_single().await?.is_none() {
+                return Err(StorageSystemError::new("truncated ubae data: tag without entry"))
+            }
+            match String::from_utf8(decoded_tag) {
+                Ok(tag) => tags.push(tag),
+                Err(_) => return Err(StorageSystemError::new("corrupt ubae data: tag is not valid utf8"))
+            }
+        }
+        Ok(tags)
+    }
+
+    async fn tag_exists(&mut self, tag: &str) -> Result<bool, StorageSystemError> {
+        Ok(self.seek_to_entry(tag).await?.is_some())
+    }
+
+    /// Returns -1 if the tag does not exist (like Ubae)
+    async fn tag_length(&mut self, tag: &str) -> Result<i64, StorageSystemError> {
+        if self.seek_to_entry(tag).await?.is_some() {
+            if let Some(length) = self.libae.li_skip_single().await? {
+                return Ok(length)
+            }
+        }
+        Ok(-1)
+    }
+
+    async fn get_entry(&mut self, tag: &str) -> Result<Option<Vec<u8>>, StorageSystemError> {
+        if self.seek_to_entry(tag).await?.is_some() {
+            return self.libae.li_decode_single().await
+        }
+        Ok(None)
+    }
+
+    /// Copies the entry into target, returns the length of the entry or None if the tag does not exist.
+    async fn get_entry_into<W:AsyncWrite + Unpin + ?Sized>(&mut self, tag: &str, target: &mut W) -> Result<Option<i64>, StorageSystemError> {
+        if self.seek_to_entry(tag).await?.is_some() {
+            return self.libae.li_decode_single_into(target).await
+        }
+        Ok(None)
+    }
+
+    async fn delete_entry(&mut self, tag: &str) -> Result<Option<Vec<u8>>, StorageSystemError> {
+        if let Some(entry_start) = self.seek_to_entry(tag).await? {
+            let toreturn = self.libae.li_decode_single().await?;
+            let cur_rp = self.libae.manually_get_read_pointer();
+            self.libae.storage_system.delete(entry_start, cur_rp).await?;
+            return Ok(toreturn)
+        }
+        Ok(None)
+    }
+
+    async fn delete_entry_noreturn(&mut self, tag: &str) -> Result<bool, StorageSystemError> {
+        if let Some(entry_start) = self.seek_to_entry(tag).await? {
+            self.libae.li_skip_single().await?;
+            let cur_rp = self.libae.manually_get_read_pointer();
+            self.libae.storage_system.delete(entry_start, cur_rp).await?;
+            return Ok(true)
+        }
+        Ok(false)
+    }
+
+    /// If an entry with the specified tag is already in the system it is DELETED and replaced.
+    async fn add_entry(&mut self, tag: &str, content: &[u8]) -> Result<(), StorageSystemError> {
+        self.delete_entry_noreturn(tag).await?;
+        self.add_entry_nocheck(tag, content).await
+    }
+
+    /// The caller ensures that the tag does not yet exist within the system. (see Ubae::add_entry_nocheck)
+    async fn add_entry_nocheck(&mut self, tag: &str, content: &[u8]) -> Result<(), StorageSystemError> {
+        self.libae.li_encode_single(tag.as_bytes()).await?;
+        self.libae.li_encode_single(content).await
+    }
+
+    async fn add_entry_from_stream<R:AsyncRead + Unpin + ?Sized>(&mut self, tag: &str, stream: &mut R, stream_length: i64) -> Result<(), StorageSystemError> {
+        self.delete_entry_noreturn(tag).await?;
+        self.add_entry_from_stream_nocheck(tag, stream, stream_length).await
+    }
+
+    async fn add_entry_from_stream_nocheck<R:AsyncRead + Unpin + ?Sized>(&mut self, tag: &str, stream: &mut R, stream_length: i64) -> Result<(), StorageSystemError> {
+        self.libae.li_encode_single(tag.as_bytes()).await?;
+        self.libae.li_encode_single_stream(stream, stream_length).await
+    }
+}
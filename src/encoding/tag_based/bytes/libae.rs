@@ -5,7 +5,7 @@ use std::fs::File;
 use std::io::Cursor;
 use std::io::Read;
 
-use crate::transparent_storage::{StorageSystem, StorageSystemError};
+use crate::transparent_storage::{PositionalStorageSystem, StorageSystem, StorageSystemError};
 use crate::transparent_storage::bytes::vec_storage_system::VecStorageSystem;
 use crate::transparent_storage::Substream;
 
@@ -21,60 +21,163 @@ pub trait LIbaeTraits {
     fn li_decode_single(&mut self) -> Option<Vec<u8>>;
     fn li_decode_single_stream(&mut self) -> Option<(Substream<File>, i64)>;
 
+    /// Same as li_decode_single, but distinguishes the end of the data from failure.
+    ///   Ok(None) is only returned if the read pointer sits exactly at the end of the content.
+    ///   Truncated or otherwise corrupt chunks and internal storage errors are returned as Err.
+    fn li_try_decode_single(&mut self) -> Result<Option<Vec<u8>>, StorageSystemError>;
+    /// Same as li_decode_single_stream, but distinguishes the end of the data from failure. (see li_try_decode_single)
+    fn li_try_decode_single_stream(&mut self) -> Result<Option<(Substream<File>, i64)>, StorageSystemError>;
+
     fn reset_read_pointer(&mut self);
 
     fn li_delete_single(&mut self) -> Option<Vec<u8>>;
     fn li_skip_single(&mut self) -> i64;
+    /// Same as li_skip_single, but distinguishes the end of the data(Ok(None)) from failure(Err).
+    ///   Returns the size of the skipped chunk otherwise.
+    fn li_try_skip_single(&mut self) -> Result<Option<i64>, StorageSystemError>;
 
     fn li_decode_all(&mut self) -> Vec<Vec<u8>>;
+    /// Decodes all remaining chunks, fails if any of them cannot be decoded.
+    fn li_try_decode_all(&mut self) -> Result<Vec<Vec<u8>>, StorageSystemError>;
 }
 
 
+/// Describes how the length of each chunk is encoded.
+///   Both encodings can be decoded by any LIbae, the encoding is detected from the first byte of the content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LIEncoding {
+    /// The original encoding: a single byte holding the number of length bytes(0-8), followed by the big endian length.
+    ///   Content starts with the first chunk directly, there is no header.
+    LeadingByte,
+    /// LEB128 varint lengths: 7 bits per byte, least significant group first, high bit set on all but the last byte.
+    ///   Content starts with the VARINT_ENCODING_HEADER byte. A chunk of less than 128 bytes only requires a single length byte.
+    Varint,
+}
+
+/// First byte of varint encoded content.
+///   The first byte of LeadingByte content is always within 0-8, so any header byte >= 0x80 can be told apart.
+///   The lower bits are reserved as a version number(varint is version 1).
+pub const VARINT_ENCODING_HEADER:u8 = 0x81;
+
+impl LIEncoding {
+    /// Number of bytes at the start of the content that are not part of any chunk.
+    pub fn header_length(&self) -> i64 {
+        match self {
+            LIEncoding::LeadingByte => 0,
+            LIEncoding::Varint => 1,
+        }
+    }
+
+    /// Detects the encoding from the first byte of the content. Content without a known header is LeadingByte.
+    pub fn detect(first_byte:Option<u8>) -> LIEncoding {
+        match first_byte {
+            Some(VARINT_ENCODING_HEADER) => LIEncoding::Varint,
+            _ => LIEncoding::LeadingByte,
+        }
+    }
+
+    fn detect_in(storage_system:&mut dyn StorageSystem) -> Result<LIEncoding, StorageSystemError> {
+        if storage_system.content_size()? == 0 {
+            Ok(LIEncoding::LeadingByte)
+        } else {
+            Ok(LIEncoding::detect(storage_system.subarray(0, 1)?.first().cloned()))
+        }
+    }
+}
+
 pub struct LIbae<T:StorageSystem> {
     read_pointer: i64,
+    encoding: LIEncoding,
+    iteration_failed: bool, // set once the iterator returned an error, so that it does not return the same error forever.
     pub storage_system:T // public so that the user can still directly access the storage system
                          // for example to get it's raw(encoded) size or content. Or if it is a File of sort to close it.
                          // All not strictly functionality of libae
 }
 impl<T:StorageSystem> LIbae<T> {
-    pub fn new(storagesystem:T) -> LIbae<T> {
+    /// Uses the encoding of the existing content in the storage system.
+    ///   Empty content will be encoded using the original LeadingByte encoding, to stay readable by older implementations.
+    pub fn new(mut storagesystem:T) -> LIbae<T> {
+        let encoding = LIEncoding::detect_in(&mut storagesystem).unwrap_or(LIEncoding::LeadingByte);
         return LIbae {
-            read_pointer:0,
+            read_pointer:encoding.header_length(),
+            encoding,
+            iteration_failed:false,
             storage_system: storagesystem,
         }
     }
 
+    /// Writes the header of the given encoding if the storage system is empty.
+    ///   Fails if the storage system already contains content of a different encoding.
+    pub fn new_with_encoding(mut storagesystem:T, encoding:LIEncoding) -> Result<LIbae<T>, StorageSystemError> {
+        if storagesystem.content_size()? == 0 {
+            write_header(&mut storagesystem, encoding)?;
+        } else if LIEncoding::detect_in(&mut storagesystem)? != encoding {
+            return Err(StorageSystemError::new(&format!("existing content is not {:?} encoded", encoding)));
+        }
+        Ok(LIbae {
+            read_pointer:encoding.header_length(),
+            encoding,
+            iteration_failed:false,
+            storage_system: storagesystem,
+        })
+    }
+
+    pub fn encoding(&self) -> LIEncoding {
+        self.encoding
+    }
+
     pub fn manually_get_read_pointer(&self) -> i64 {
         return self.read_pointer
     }
 }
 impl LIbae<VecStorageSystem> {
     pub fn ram() -> LIbae<VecStorageSystem> {
-        return LIbae {
-            read_pointer:0,
-            storage_system: VecStorageSystem::new_empty(),
-        }
+        return LIbae::new(VecStorageSystem::new_empty())
+    }
+    pub fn ram_varint() -> LIbae<VecStorageSystem> {
+        LIbae::new_with_encoding(VecStorageSystem::new_empty(), LIEncoding::Varint).expect("vec storage system cannot fail")
     }
 }
 
+///Iterates over the remaining chunks.
+///  A corrupt or truncated chunk is returned as an error once, after that the iteration ends.
+///  The iteration can be restarted using reset_read_pointer.
 impl<T:StorageSystem> Iterator for LIbae<T> {
-    type Item = Vec<u8>;
+    type Item = Result<Vec<u8>, StorageSystemError>;
 
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        self.li_decode_single()
+        if self.iteration_failed {
+            return None
+        }
+        match self.li_try_decode_single() {
+            Ok(decoded) => decoded.map(Ok),
+            Err(e) => {
+                self.iteration_failed = true;
+                Some(Err(e))
+            }
+        }
     }
 }
 
 impl<T:StorageSystem> LIbaeTraits for LIbae<T> {
+    /// The encoding is re-detected from the new content.
+    ///   Setting empty content keeps the current encoding, i.e. a varint libae remains varint encoded.
     fn set_content(&mut self, bytes: &[u8]) -> Result<(), StorageSystemError> {
-        return self.storage_system.set_content(bytes)
+        self.storage_system.set_content(bytes)?;
+        if bytes.is_empty() {
+            write_header(&mut self.storage_system, self.encoding)?;
+        } else {
+            self.encoding = LIEncoding::detect(Some(bytes[0]));
+        }
+        self.reset_read_pointer();
+        Ok(())
     }
     fn get_content(&mut self) -> Result<Vec<u8>, StorageSystemError> {
         self.storage_system.get_content()
     }
 
     fn li_encode_single(&mut self, bytes: &[u8]) -> Result<(), StorageSystemError> {
-        match self.storage_system.append(&get_length_indicator_for(bytes.len() as i64)[..]) {
+        match self.storage_system.append(&get_length_indicator_for(bytes.len() as i64, self.encoding)[..]) {
             Err(e) => {return Err(e)},
             Ok(_) => {
                 return self.storage_system.append(bytes)
@@ -83,49 +186,48 @@ impl<T:StorageSystem> LIbaeTraits for LIbae<T> {
     }
 
     fn li_encode_single_stream(&mut self, stream: &mut dyn Read, stream_length: i64) -> Result<(), StorageSystemError> {
-        println!("li_encode_single_stream");
-        self.storage_system.append(&get_length_indicator_for(stream_length)[..])?;
-        println!("aft append li");
+        self.storage_system.append(&get_length_indicator_for(stream_length, self.encoding)[..])?;
         self.storage_system.append_stream(stream, stream_length)
     }
 
     fn reset_read_pointer(&mut self) {
-        (self.read_pointer) = 0;
+        (self.read_pointer) = self.encoding.header_length();
+        self.iteration_failed = false;
     }
 
     fn li_decode_single(&mut self) -> Option<Vec<u8>> {
-        match get_start_and_end_index_of_next_li_chunk(self.read_pointer, &mut self.storage_system) {
-            None => return None,
+        self.li_try_decode_single().unwrap_or(None)
+    }
+
+    fn li_decode_single_stream(&mut self) -> Option<(Substream<File>, i64)> {
+        self.li_try_decode_single_stream().unwrap_or(None)
+    }
+
+    fn li_try_decode_single(&mut self) -> Result<Option<Vec<u8>>, StorageSystemError> {
+        match get_start_and_end_index_of_next_li_chunk(self.read_pointer, &mut self.storage_system, self.encoding)? {
+            None => Ok(None),
             Some(start_end) => {
-                match self.storage_system.subarray(start_end.0, start_end.1) {
-                    Err(_) => return None,
-                    Ok(decoded) => {
-                        self.read_pointer = start_end.1;
-                        return Some(decoded)
-                    }
-                }
+                let decoded = self.storage_system.subarray(start_end.0, start_end.1)?;
+                self.read_pointer = start_end.1;
+                Ok(Some(decoded))
             }
         }
     }
 
-    fn li_decode_single_stream(&mut self) -> Option<(Substream<File>, i64)> {
-        match get_start_and_end_index_of_next_li_chunk(self.read_pointer, &mut self.storage_system) {
-            None => return None,
+    fn li_try_decode_single_stream(&mut self) -> Result<Option<(Substream<File>, i64)>, StorageSystemError> {
+        match get_start_and_end_index_of_next_li_chunk(self.read_pointer, &mut self.storage_system, self.encoding)? {
+            None => Ok(None),
             Some(start_end) => {
-                match self.storage_system.substream(start_end.0, start_end.1) {
-                    Err(_) => return None,
-                    Ok(stream) => {
-                        self.read_pointer = start_end.1;
-                        let stream_length = start_end.1 - start_end.0;
-                        return Some((stream, stream_length));
-                    }
-                }
+                let stream = self.storage_system.substream(start_end.0, start_end.1)?;
+                self.read_pointer = start_end.1;
+                let stream_length = start_end.1 - start_end.0;
+                Ok(Some((stream, stream_length)))
             }
         }
     }
 
     fn li_delete_single(&mut self) -> Option<Vec<u8>> {
-        match get_start_and_end_index_of_next_li_chunk(self.read_pointer, &mut self.storage_system) {
+        match get_start_and_end_index_of_next_li_chunk(self.read_pointer, &mut self.storage_system, self.encoding).unwrap_or(None) {
             None => None,
             Some(start_end) => {
                 if let Ok(decoded) = self.storage_system.subarray(start_end.0, start_end.1) {
@@ -139,11 +241,18 @@ impl<T:StorageSystem> LIbaeTraits for LIbae<T> {
     }
 
     fn li_skip_single(&mut self) -> i64 {
-        match get_start_and_end_index_of_next_li_chunk(self.read_pointer, &mut self.storage_system) {
-            None => return -1,
+        match self.li_try_skip_single() {
+            Ok(Some(skipped_length)) => skipped_length,
+            _ => -1
+        }
+    }
+
+    fn li_try_skip_single(&mut self) -> Result<Option<i64>, StorageSystemError> {
+        match get_start_and_end_index_of_next_li_chunk(self.read_pointer, &mut self.storage_system, self.encoding)? {
+            None => Ok(None),
             Some(start_end) => {
                 self.read_pointer = start_end.1;
-                return start_end.1-start_end.0;
+                Ok(Some(start_end.1-start_end.0))
             }
         }
     }
@@ -157,6 +266,16 @@ impl<T:StorageSystem> LIbaeTraits for LIbae<T> {
 
         return all;
     }
+
+    fn li_try_decode_all(&mut self) -> Result<Vec<Vec<u8>>, StorageSystemError> {
+        let mut all = Vec::new();
+
+        while let Some(single) = self.li_try_decode_single()? {
+            all.push(single);
+        }
+
+        Ok(all)
+    }
 }
 
 
@@ -164,37 +283,130 @@ impl<T:StorageSystem> LIbaeTraits for LIbae<T> {
 
 //actual LIBAE FUNCTIONALITY
 
-fn get_length_indicator_for(length:i64) -> Vec<u8> {
-    let mut li_bytes = get_minimal_bytes(length); //cannot be more than 8 in size.
-    let leading_li = li_bytes.len() as u8; //cast possible because it cannot be more than 8 anyways.
-    let mut li_bytes_with_leading_li = vec![leading_li];
-    li_bytes_with_leading_li.append(&mut li_bytes);
-    return li_bytes_with_leading_li;
-}
-
-fn get_start_and_end_index_of_next_li_chunk(start_index:i64, storage_system:&mut dyn StorageSystem) -> Option<(i64, i64)> {
-    if let Ok(content_size) = storage_system.content_size() { //threating content_size error as "last element reached"
-        let mut i = start_index;
-        if i + 1 > content_size {
-            return None;
-        }
-        let content_size = content_size;
-        return match storage_system.subarray(i, i + 9) { //cache maximum number of required bytes. (to minimize possibly slow subarray calls)
-            Err(_) => None,
-            Ok(cache) => {
-                let leading_li = cache[0] as i64;
-
-                let length_indicator = &cache[1..(1 + leading_li) as usize];
-                let length_indicator_as_int = get_int(length_indicator);
-                if length_indicator_as_int == -1 || i + length_indicator_as_int > content_size {
-                    return None;
+/// The maximum number of bytes a length indicator can occupy in any encoding.
+pub(crate) const MAX_LENGTH_INDICATOR_SIZE:usize = 9;
+
+fn write_header(storage_system:&mut dyn StorageSystem, encoding:LIEncoding) -> Result<(), StorageSystemError> {
+    match encoding {
+        LIEncoding::LeadingByte => Ok(()),
+        LIEncoding::Varint => storage_system.append(&[VARINT_ENCODING_HEADER]),
+    }
+}
+
+pub(crate) fn get_length_indicator_for(length:i64, encoding:LIEncoding) -> Vec<u8> {
+    match encoding {
+        LIEncoding::LeadingByte => {
+            let mut li_bytes = get_minimal_bytes(length); //cannot be more than 8 in size.
+            let leading_li = li_bytes.len() as u8; //cast possible because it cannot be more than 8 anyways.
+            let mut li_bytes_with_leading_li = vec![leading_li];
+            li_bytes_with_leading_li.append(&mut li_bytes);
+            li_bytes_with_leading_li
+        },
+        LIEncoding::Varint => get_varint_for(length),
+    }
+}
+
+/// Result of parsing the length indicator at the start of a byte slice.
+#[derive(Debug, PartialEq)]
+pub(crate) enum LengthIndicator {
+    /// number of bytes the length indicator occupies and the length of the following chunk
+    Complete(usize, i64),
+    /// the slice ends before the length indicator does
+    Incomplete,
+    Corrupt(&'static str),
+}
+
+pub(crate) fn parse_length_indicator(cache:&[u8], encoding:LIEncoding) -> LengthIndicator {
+    match encoding {
+        LIEncoding::LeadingByte => {
+            if cache.is_empty() {
+                return LengthIndicator::Incomplete;
+            }
+            let leading_li = cache[0] as usize;
+            if leading_li > 8 {
+                LengthIndicator::Corrupt("leading length indicator byte larger than 8")
+            } else if cache.len() < 1 + leading_li {
+                LengthIndicator::Incomplete
+            } else {
+                match get_int(&cache[1..1 + leading_li]) {
+                    length if length < 0 => LengthIndicator::Corrupt("negative chunk length"),
+                    length => LengthIndicator::Complete(1 + leading_li, length)
+                }
+            }
+        },
+        LIEncoding::Varint => {
+            let mut length = 0u64;
+            for (i, byte) in cache.iter().enumerate() {
+                if i >= MAX_LENGTH_INDICATOR_SIZE { // 9*7 = 63 bits, all a non negative i64 can hold
+                    return LengthIndicator::Corrupt("varint length indicator longer than 9 bytes");
                 }
-                i += leading_li + 1; //to skip all the li information.
-                return Some((i, i + length_indicator_as_int));
+                length |= ((byte & 0x7F) as u64) << (7 * i);
+                if byte & 0x80 == 0 {
+                    return LengthIndicator::Complete(i + 1, length as i64);
+                }
+            }
+            if cache.len() >= MAX_LENGTH_INDICATOR_SIZE { //the continuation bit is still set on the last byte it may have
+                LengthIndicator::Corrupt("varint length indicator longer than 9 bytes")
+            } else {
+                LengthIndicator::Incomplete
             }
+        },
+    }
+}
+
+//returns Ok(None) only if start_index is exactly at(or behind) the end of the content.
+//  a chunk whose length indicator or content is cut off by the end of the content is reported as corrupt.
+fn get_start_and_end_index_of_next_li_chunk(start_index:i64, storage_system:&mut dyn StorageSystem, encoding:LIEncoding) -> Result<Option<(i64, i64)>, StorageSystemError> {
+    let content_size = storage_system.content_size()?;
+    if start_index >= content_size {
+        return Ok(None);
+    }
+    //cache maximum number of required bytes. (to minimize possibly slow subarray calls)
+    //  never request bytes behind the end, not every storage system handles that gracefully
+    let cache = storage_system.subarray(start_index, cmp::min(start_index + MAX_LENGTH_INDICATOR_SIZE as i64, content_size))?;
+    li_chunk_from_cache(start_index, content_size, &cache, encoding).map(Some)
+}
+
+/// Same as get_start_and_end_index_of_next_li_chunk, but reads positionally. So it does not require exclusive access.
+pub(crate) fn get_start_and_end_index_of_li_chunk_at<T:PositionalStorageSystem + ?Sized>(start_index:i64, storage_system:&T, encoding:LIEncoding) -> Result<Option<(i64, i64)>, StorageSystemError> {
+    let content_size = storage_system.content_size()?;
+    if start_index >= content_size {
+        return Ok(None);
+    }
+    let cache = storage_system.read_at(start_index, cmp::min(start_index + MAX_LENGTH_INDICATOR_SIZE as i64, content_size))?;
+    li_chunk_from_cache(start_index, content_size, &cache, encoding).map(Some)
+}
+
+/// Locates the chunk whose length indicator starts at start_index. cache has to contain the bytes from start_index on (at least the entire length indicator, if the content has that many).
+pub(crate) fn li_chunk_from_cache(start_index:i64, content_size:i64, cache:&[u8], encoding:LIEncoding) -> Result<(i64, i64), StorageSystemError> {
+    match parse_length_indicator(cache, encoding) {
+        LengthIndicator::Incomplete =>
+            Err(StorageSystemError::new(&format!("truncated libae data at {}: length indicator cut off", start_index))),
+        LengthIndicator::Corrupt(reason) =>
+            Err(StorageSystemError::new(&format!("corrupt libae data at {}: {}", start_index, reason))),
+        LengthIndicator::Complete(li_size, chunk_length) => {
+            let chunk_start = start_index + li_size as i64;
+            match chunk_start.checked_add(chunk_length) {
+                Some(chunk_end) if chunk_end <= content_size => Ok((chunk_start, chunk_end)),
+                _ => Err(StorageSystemError::new(&format!("truncated libae data at {}: chunk announces {} bytes, but only {} remain",
+                                                       start_index, chunk_length, content_size - chunk_start)))
+            }
+        }
+    }
+}
+
+fn get_varint_for(length:i64) -> Vec<u8> {// least significant group first
+    let mut remaining = length as u64;
+    let mut bytes = Vec::with_capacity(2);
+    loop {
+        let group = (remaining & 0x7F) as u8;
+        remaining >>= 7;
+        if remaining == 0 {
+            bytes.push(group);
+            return bytes;
         }
+        bytes.push(group | 0x80);
     }
-    return None;
 }
 
 fn get_int(bytearr:&[u8]) -> i64 {// big-endian
@@ -0,0 +1,249 @@
+use std::cmp;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use super::libae::get_length_indicator_for;
+use super::libae::parse_length_indicator;
+use super::libae::LengthIndicator;
+use super::libae::LIEncoding;
+use super::libae::VARINT_ENCODING_HEADER;
+
+/// Write only libae encoder.
+///   Writes the encoded chunks directly into any io::Write (a socket, a pipe, a compressor, ...)
+///   Does not require seeking or a content size, so it cannot delete or decode anything.
+///   The output is byte for byte identical to what a LIbae with the same encoding stores.
+pub struct LIbaeWriter<W:Write> {
+    writer:W,
+    encoding:LIEncoding
+}
+impl<W:Write> LIbaeWriter<W> {
+    /// Uses the original(LeadingByte) encoding, no header is written.
+    pub fn new(writer:W) -> LIbaeWriter<W> {
+        LIbaeWriter {
+            writer,
+            encoding:LIEncoding::LeadingByte
+        }
+    }
+    /// Immediately writes the header of the encoding (if it has one).
+    pub fn new_with_encoding(mut writer:W, encoding:LIEncoding) -> io::Result<LIbaeWriter<W>> {
+        if encoding == LIEncoding::Varint {
+            writer.write_all(&[VARINT_ENCODING_HEADER])?;
+        }
+        Ok(LIbaeWriter {
+            writer,
+            encoding
+        })
+    }
+
+    pub fn li_encode_single(&mut self, bytes:&[u8]) -> io::Result<()> {
+        self.writer.write_all(&get_length_indicator_for(bytes.len() as i64, self.encoding))?;
+        self.writer.write_all(bytes)
+    }
+
+    /// Copies exactly stream_length bytes from stream.
+    ///   If the stream ends early the remaining bytes are padded with 0's, so that the output stays decodable.
+    ///   An UnexpectedEof error is returned in that case, after the padding was written.
+    pub fn li_encode_single_stream(&mut self, stream:&mut dyn Read, stream_length:i64) -> io::Result<()> {
+        if stream_length < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "negative stream length"));
+        }
+        self.writer.write_all(&get_length_indicator_for(stream_length, self.encoding))?;
+        let copied = io::copy(&mut stream.take(stream_length as u64), &mut self.writer)?;
+        if copied < stream_length as u64 {
+            io::copy(&mut io::repeat(0).take(stream_length as u64 - copied), &mut self.writer)?;
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("stream ended after {} of {} bytes, padded the rest", copied, stream_length)));
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn encoding(&self) -> LIEncoding {
+        self.encoding
+    }
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+
+
+/// Read only libae decoder.
+///   Reads chunks in sequence from any io::Read, without seeking. The encoding is detected from the first byte.
+///   Length indicators are read byte by byte, so wrapping unbuffered readers in a BufReader is recommended.
+pub struct LIbaeReader<R:Read> {
+    reader:R,
+    encoding:Option<LIEncoding>,   //None until the first byte was read
+    peeked:Option<u8>,             //first byte of content without a header - it is already part of the first length indicator
+    remaining_in_chunk:u64,        //bytes of the last chunk stream that have not been read, skipped before the next chunk
+    iteration_failed:bool
+}
+impl<R:Read> LIbaeReader<R> {
+    pub fn new(reader:R) -> LIbaeReader<R> {
+        LIbaeReader {
+            reader,
+            encoding:None,
+            peeked:None,
+            remaining_in_chunk:0,
+            iteration_failed:false
+        }
+    }
+
+    /// Returns the encoding of the content, reading the first byte if that has not happened yet.
+    ///   Empty content is considered LeadingByte encoded.
+    pub fn encoding(&mut self) -> io::Result<LIEncoding> {
+        if let Some(encoding) = self.encoding {
+            return Ok(encoding)
+        }
+        let first_byte = self.read_byte()?;
+        let encoding = LIEncoding::detect(first_byte);
+        if encoding.header_length() == 0 {
+            self.peeked = first_byte;
+        }
+        self.encoding = Some(encoding);
+        Ok(encoding)
+    }
+
+    /// Reads the next chunk.
+    ///   Ok(None) indicates that the content ended cleanly at a chunk boundary.
+    ///   If it ends within a chunk an UnexpectedEof error is returned, corrupt length indicators are reported as InvalidData.
+    pub fn li_decode_single(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.read_chunk_length()? {
+            None => Ok(None),
+            Some(chunk_length) => {
+                // not allocating chunk_length up front - a corrupt length indicator should not allocate gigabytes
+                let mut decoded = Vec::with_capacity(cmp::min(chunk_length, 8192) as usize);
+                (&mut self.reader).take(chunk_length).read_to_end(&mut decoded)?;
+                if (decoded.len() as u64) < chunk_length {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("truncated libae data: chunk announces {} bytes, but only {} remain", chunk_length, decoded.len())));
+                }
+                Ok(Some(decoded))
+            }
+        }
+    }
+
+    /// Returns a stream over the next chunk, and the chunks length.
+    ///   Whatever is not read from the stream is skipped when the next chunk is requested.
+    pub fn li_decode_single_stream(&mut self) -> io::Result<Option<(LIbaeChunkReader<'_, R>, i64)>> {
+        match self.read_chunk_length()? {
+            None => Ok(None),
+            Some(chunk_length) => {
+                self.remaining_in_chunk = chunk_length;
+                Ok(Some((LIbaeChunkReader { libae:self }, chunk_length as i64)))
+            }
+        }
+    }
+
+    /// Jumps over the next chunk. Returns the size of the skipped chunk(chunk size, NOT bytes skipped)
+    pub fn li_skip_single(&mut self) -> io::Result<Option<i64>> {
+        match self.read_chunk_length()? {
+            None => Ok(None),
+            Some(chunk_length) => {
+                self.remaining_in_chunk = chunk_length;
+                self.skip_remaining_in_chunk()?;
+                Ok(Some(chunk_length as i64))
+            }
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_chunk_length(&mut self) -> io::Result<Option<u64>> {
+        self.skip_remaining_in_chunk()?;
+        let encoding = self.encoding()?;
+
+        let mut length_indicator = Vec::with_capacity(2);
+        loop {
+            let next = match self.peeked.take() {
+                Some(peeked) => Some(peeked),
+                None => self.read_byte()?
+            };
+            match next {
+                None if length_indicator.is_empty() => return Ok(None),
+                None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated libae data: length indicator cut off")),
+                Some(byte) => length_indicator.push(byte)
+            }
+            match parse_length_indicator(&length_indicator, encoding) {
+                LengthIndicator::Incomplete => continue,
+                LengthIndicator::Corrupt(reason) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("corrupt libae data: {}", reason))),
+                LengthIndicator::Complete(_, chunk_length) => return Ok(Some(chunk_length as u64))
+            }
+        }
+    }
+
+    fn skip_remaining_in_chunk(&mut self) -> io::Result<()> {
+        if self.remaining_in_chunk > 0 {
+            let to_skip = self.remaining_in_chunk;
+            let skipped = io::copy(&mut (&mut self.reader).take(to_skip), &mut io::sink())?;
+            self.remaining_in_chunk = 0;
+            if skipped < to_skip {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated libae data: content ended within a chunk"));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0u8; 1];
+        loop {
+            match self.reader.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buf[0])),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            }
+        }
+    }
+}
+
+///Iterates over the remaining chunks.
+///  An error is returned once, after that the iteration ends.
+impl<R:Read> Iterator for LIbaeReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.iteration_failed {
+            return None
+        }
+        match self.li_decode_single() {
+            Ok(decoded) => decoded.map(Ok),
+            Err(e) => {
+                self.iteration_failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Reads a single chunk from a LIbaeReader. Ends at the end of the chunk.
+pub struct LIbaeChunkReader<'a, R:Read> {
+    libae:&'a mut LIbaeReader<R>
+}
+impl<R:Read> Read for LIbaeChunkReader<'_, R> {
+    fn read(&mut self, buf:&mut [u8]) -> io::Result<usize> {
+        if self.libae.remaining_in_chunk == 0 || buf.is_empty() {
+            return Ok(0)
+        }
+        let max_len = cmp::min(buf.len() as u64, self.libae.remaining_in_chunk) as usize;
+        let read = self.libae.reader.read(&mut buf[..max_len])?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated libae data: content ended within a chunk"));
+        }
+        self.libae.remaining_in_chunk -= read as u64;
+        Ok(read)
+    }
+}
@@ -2,9 +2,19 @@
 
 
 pub mod libae;
+pub mod libae_stream;
 pub mod ubae;
+pub mod shared_ubae;
+#[cfg(feature = "async")]
+pub mod async_libae;
+#[cfg(feature = "async")]
+pub mod async_ubae;
 pub mod ubae_directory_encoder;
+pub mod ubae_archive_fs;
+pub mod ubae_tar;
 pub mod remote;
 
 #[cfg(test)]
-mod tests;
\ No newline at end of file
+mod tests;
+#[cfg(all(test, feature = "async"))]
+mod async_tests;
\ No newline at end of file
@@ -224,8 +224,8 @@ impl UbaeTraits<TcpStream> for Arbae {
     }
 
     /// same as add_entry, but reads the entry from the provided stream.
-    ///   if stream is not of stream length behaviour is mostly undefined.
-    ///   Though the system will try not to break because of it.
+    ///   If stream ends before stream_length (or fails), the server adds nothing, but the connection is shut down (see send_variable_chunk_from_stream),
+    ///   so this and any later request fails.
     fn add_entry_from_stream(&mut self, tag: &str, stream: &mut dyn Read, stream_length: i64) -> Result<(), StorageSystemError> {
         self.client.send_cause(rbae_mcnp_causes::ADD_ENTRY_BYTE_ARR)?;
 
@@ -7,6 +7,7 @@ use std::str;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::MutexGuard;
+use std::sync::RwLockWriteGuard;
 use std::thread;
 
 use crate::encoding::tag_based::bytes::libae::LIbae;
@@ -173,8 +174,7 @@ impl ArbaeServer {
             let password_received = authentication_helper::aes_crt_np_128_decrypt(&encrypted_password, &exchanged_key, &nonce);
             match cause {
                 arbae_mcnp_causes::LOGIN_CAUSE => {
-                    let mut locked_ubae:MutexGuard<Ubae<FileStorageSystem>> = self.ubae_clone_lock();
-                    let password_on_file = locked_ubae.get_entry(&password_store_tag_for_user_name)?;
+                    let password_on_file = self.shared_ubae().get_entry(&password_store_tag_for_user_name)?; //read only, does not block other readers
                     if let Some(password_on_file) = password_on_file {
                         if password_received == password_on_file {
                             connection.send_fixed_chunk_u8(arbae_mcnp_causes::LOGIN_SUCCESSFUL as u8)?;
@@ -195,7 +195,7 @@ impl ArbaeServer {
                     }
                 },
                 arbae_mcnp_causes::REGISTER_CAUSE => {
-                    let mut locked_ubae:MutexGuard<Ubae<FileStorageSystem>> = self.ubae_clone_lock();
+                    let mut locked_ubae:RwLockWriteGuard<Ubae<FileStorageSystem>> = self.ubae_clone_lock(); //exclusive, the check and the add have to be atomic
                     let password_on_file = locked_ubae.get_entry(&password_store_tag_for_user_name)?;
                     match password_on_file {
                         None => {
@@ -227,10 +227,7 @@ impl ArbaeServer {
                     }
                 },
                 rbae_mcnp_causes::INITIAL_CONNECTION_CAUSE__IS_OBSERVER => {
-                    let password_on_file = {
-                        let mut locked_ubae:MutexGuard<Ubae<FileStorageSystem>> = self.ubae_clone_lock();
-                        locked_ubae.get_entry(&password_store_tag_for_user_name)?
-                    };
+                    let password_on_file = self.shared_ubae().get_entry(&password_store_tag_for_user_name)?;
                     if let Some(password_on_file) = password_on_file {
                         if password_received == password_on_file {
                             connection.send_fixed_chunk_u8(arbae_mcnp_causes::LOGIN_SUCCESSFUL as u8)?;
@@ -8,34 +8,40 @@ use crate::encoding::tag_based::bytes::ubae::UbaeTraits;
 use crate::network::mcnp::mcnp_client::McnpClient;
 use crate::network::mcnp::mcnp_connection::McnpConnection;
 use crate::network::mcnp::mcnp_connection::McnpConnectionTraits;
+use crate::network::mcnp::mcnp_connection::McnpTransport;
 use crate::transparent_storage::bytes::vec_storage_system::VecStorageSystem;
 use crate::transparent_storage::StorageSystemError;
 use crate::transparent_storage::Substream;
 
 use super::rbae_mcnp_causes;
 
-pub struct Rbae {
+pub struct Rbae<S:McnpTransport = TcpStream> {
     ///public to allow direct communication with the server. Obviously this should only be used if one knows exactly what is happening
     /// In any case any communication has to start with a custom cause that does not conflict with any of the already assigned causes.
     /// If a client does custom communication - then the coder has to add an inverse matching communication pattern on server side
     ///   (via add_cause_handler)
-    pub client:McnpConnection
+    pub client:McnpConnection<S>
 }
 impl Rbae {
     /// Creates a new ubae system with the provided storage system.
     pub fn new(addr:&str, port:u16) -> Rbae {
-        let mut client = McnpClient::new(addr, port);
-        client.send_cause(rbae_mcnp_causes::INITIAL_CONNECTION_CAUSE__IS_CLIENT).expect("Initialization failed.");
-        Rbae {
+        Rbae::new_from_connection(McnpClient::new(addr, port)).expect("Initialization failed.")
+    }
+}
+impl<S:McnpTransport> Rbae<S> {
+    /// Uses an already established connection to the server, for example a tls connection (see McnpClient::new_tls).
+    pub fn new_from_connection(mut client:McnpConnection<S>) -> Result<Rbae<S>, StorageSystemError> {
+        client.send_cause(rbae_mcnp_causes::INITIAL_CONNECTION_CAUSE__IS_CLIENT)?;
+        Ok(Rbae {
             client
-        }
+        })
     }
 }
 
 //note on using StorageSystemError's to propagate mcnp network error's to the user:
 //    it is fine, because mcnp is essentially the internal storage system
 //    it just works at a different level due to thread and process safety and consistency requirements.
-impl UbaeTraits<TcpStream> for Rbae {
+impl<S:McnpTransport> UbaeTraits<S> for Rbae<S> {
     /// hands set_content calls through to underlying storage system.
     fn set_content(&mut self, bytes: &[u8]) -> Result<(), StorageSystemError> {
         self.client.send_cause(rbae_mcnp_causes::SET_CONTENT)?;
@@ -115,7 +121,7 @@ impl UbaeTraits<TcpStream> for Rbae {
     /// Retrieves the entry with the specified tag as a stream.
     ///    if the underlying storage is altered this might affect what can be read from the stream
     /// Will return None if the tag does not point to an entry within the system
-    fn get_entry_as_stream(&mut self, tag: &str) -> Result<Option<(Substream<TcpStream>, i64)>, StorageSystemError> {
+    fn get_entry_as_stream(&mut self, tag: &str) -> Result<Option<(Substream<S>, i64)>, StorageSystemError> {
         self.client.send_cause(rbae_mcnp_causes::GET_ENTRY_BYTE_ARR)?;
         self.client.send_variable_chunk(tag.as_bytes())?;
         let entry_stream = self.client.read_variable_chunk_as_stream()?;
@@ -177,8 +183,8 @@ impl UbaeTraits<TcpStream> for Rbae {
     }
 
     /// same as add_entry, but reads the entry from the provided stream.
-    ///   if stream is not of stream length behaviour is mostly undefined.
-    ///   Though the system will try not to break because of it.
+    ///   If stream ends before stream_length (or fails), the server adds nothing, but the connection is shut down (see send_variable_chunk_from_stream),
+    ///   so this and any later request fails.
     fn add_entry_from_stream(&mut self, tag: &str, stream: &mut dyn Read, stream_length: i64) -> Result<(), StorageSystemError> {
         self.client.send_cause(rbae_mcnp_causes::ADD_ENTRY_BYTE_ARR)?;
         self.client.send_variable_chunk(tag.as_bytes())?;
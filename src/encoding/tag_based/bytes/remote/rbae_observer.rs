@@ -2,11 +2,18 @@ use std::io;
 
 use crate::encoding::tag_based::bytes::remote::rbae_mcnp_causes;
 use crate::network::mcnp::mcnp_client::McnpClient;
+use crate::network::mcnp::mcnp_connection::McnpConnection;
 use crate::network::mcnp::mcnp_connection::McnpConnectionTraits;
+use crate::network::mcnp::mcnp_connection::McnpTransport;
 
 pub fn new_remote_update_callback_receiver(addr:&str, port:u16,
                                            update_add:fn(tag:String), update_remove:fn(tag:String), update_set_content:fn()) -> Result<(), io::Error> {
-    let mut client = McnpClient::new(addr, port);
+    new_remote_update_callback_receiver_over(McnpClient::new(addr, port), update_add, update_remove, update_set_content)
+}
+
+/// Like new_remote_update_callback_receiver, over an already established connection (for example a tls connection, see McnpClient::new_tls).
+pub fn new_remote_update_callback_receiver_over<S:McnpTransport>(mut client:McnpConnection<S>,
+                                           update_add:fn(tag:String), update_remove:fn(tag:String), update_set_content:fn()) -> Result<(), io::Error> {
     client.send_cause(rbae_mcnp_causes::INITIAL_CONNECTION_CAUSE__IS_OBSERVER)?;
 
     loop {
@@ -1,21 +1,35 @@
 use std;
 use std::collections::HashMap;
 use std::fs::File;
+use std::io;
 use std::io::Read;
+use std::net::TcpStream;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
 use std::sync::MutexGuard;
+use std::sync::RwLockReadGuard;
+use std::sync::RwLockWriteGuard;
 use std::thread;
+use std::time::Duration;
 
 use crate::encoding::tag_based::bytes::libae::LIbae;
 use crate::encoding::tag_based::bytes::libae::LIbaeTraits;
 use crate::encoding::tag_based::bytes::remote::rbae_mcnp_causes;
+use crate::encoding::tag_based::bytes::shared_ubae::SharedUbae;
 use crate::encoding::tag_based::bytes::ubae::Ubae;
 use crate::encoding::tag_based::bytes::ubae::UbaeTraits;
+use crate::network::mcnp::mcnp_connection::is_timeout;
 use crate::network::mcnp::mcnp_connection::McnpConnection;
 use crate::network::mcnp::mcnp_connection::McnpConnectionTraits;
+use crate::network::mcnp::mcnp_connection::McnpTimeouts;
+use crate::network::mcnp::mcnp_connection::DEFAULT_MAX_CHUNK_LENGTH;
+use crate::network::mcnp::mcnp_connection::McnpTransport;
 use crate::network::mcnp::mcnp_server::McnpServer;
+#[cfg(feature = "tls")]
+use crate::network::mcnp::mcnp_tls::rustls::ServerConfig;
+#[cfg(feature = "tls")]
+use crate::network::mcnp::mcnp_tls::TlsStream;
 use crate::transparent_storage::bytes::file_storage_system::FileStorageSystem;
 use crate::transparent_storage::bytes::vec_storage_system::VecStorageSystem;
 use crate::transparent_storage::StorageSystemError;
@@ -24,7 +38,11 @@ use crate::transparent_storage::Substream;
 pub struct RbaeServer<O, S>
     where O: std::marker::Send + Clone + PartialEq<O> {
     port:u16,
-    ubae:Arc<Mutex<Ubae<FileStorageSystem>>>,
+    max_connections:Option<usize>,
+    timeouts:McnpTimeouts,
+    keepalive:Option<Duration>,
+    max_chunk_length:u64,
+    ubae:SharedUbae<FileStorageSystem>,
 
     observers:Arc<Mutex<Vec<O>>>,  //it looks ugly, but it actually is rather nice
     cause_handlers:Arc<Mutex<HashMap<i32, fn(&mut RbaeServer<O,S>, &mut S) -> Result<(), StorageSystemError>>>>
@@ -34,14 +52,51 @@ impl <O: 'static, S: 'static> RbaeServer<O, S>
     pub fn new_without_cause_handlers(port:u16, ubae:Ubae<FileStorageSystem>) -> RbaeServer<O, S> {
         RbaeServer {
             port,
-            ubae: Arc::new(Mutex::new(ubae)),
+            max_connections: None,
+            timeouts: McnpTimeouts::default(),
+            keepalive: None,
+            max_chunk_length: DEFAULT_MAX_CHUNK_LENGTH,
+            ubae: SharedUbae::new(ubae),
             observers: Arc::new(Mutex::new(Vec::new())),
             cause_handlers: Arc::new(Mutex::new(HashMap::new()))
         }
     }
 
-    pub fn ubae_clone_lock(&mut self) -> MutexGuard<Ubae<FileStorageSystem>> {
-        return self.ubae.lock().expect("obtaining lock failed");
+    /// Limits how many clients are served at the same time (default unlimited), further clients wait until one disconnects.
+    ///   See McnpServerBuilder::max_connections.
+    pub fn with_max_connections(mut self, max_connections:usize) -> RbaeServer<O, S> {
+        self.max_connections = Some(max_connections);
+        self
+    }
+    /// Closes connections of clients that stall in the middle of a request or stay idle for too long (default never), see McnpServerBuilder::timeouts.
+    ///   Observer connections only ever get written to, so only the write timeout applies to them.
+    pub fn with_timeouts(mut self, timeouts:McnpTimeouts) -> RbaeServer<O, S> {
+        self.timeouts = timeouts;
+        self
+    }
+    /// The longest chunk (e.g. an entry added with add_entry) read into memory, see McnpServerBuilder::max_chunk_length.
+    ///   A client sending a longer one is disconnected. Entries added as a stream (add_entry_from_stream_nocheck) are not limited.
+    pub fn with_max_chunk_length(mut self, max_chunk_length:u64) -> RbaeServer<O, S> {
+        self.max_chunk_length = max_chunk_length;
+        self
+    }
+    /// See McnpServerBuilder::keepalive.
+    pub fn with_keepalive(mut self, time:Duration) -> RbaeServer<O, S> {
+        self.keepalive = Some(time);
+        self
+    }
+
+    /// Exclusive access, blocks all readers. Required to alter the system or for atomic sequences of operations.
+    pub fn ubae_clone_lock(&mut self) -> RwLockWriteGuard<'_, Ubae<FileStorageSystem>> {
+        self.ubae.write()
+    }
+    /// Shared access, other readers are not blocked.
+    pub fn ubae_read_lock(&self) -> RwLockReadGuard<'_, Ubae<FileStorageSystem>> {
+        self.ubae.read()
+    }
+    /// Handle to the underlying ubae. Reads through it run concurrently with other reads.
+    pub fn shared_ubae(&self) -> SharedUbae<FileStorageSystem> {
+        self.ubae.clone()
     }
     pub fn get_observers_cloned(&self) -> Arc<Mutex<Vec<O>>> {
         self.observers.clone()
@@ -68,34 +123,42 @@ impl <O: 'static, S: 'static> RbaeServer<O, S>
 
     pub fn general_run_logic_loop/*<O: 'static, S: 'static>*/(self, handle_new_connection:fn(&mut RbaeServer<O, S>, McnpConnection))
         where O: std::marker::Send + Clone + PartialEq<O> {
-        let mcnp_server = McnpServer::new(self.port);
+        self.general_run_logic_loop_over(Ok, handle_new_connection)
+    }
+
+    /// Like general_run_logic_loop, but establish turns each accepted socket into the transport of the connection (e.g. a tls session).
+    ///   It runs in the thread of the connection, connections it fails for are dropped.
+    pub fn general_run_logic_loop_over<T, E>(self, establish:E, handle_new_connection:fn(&mut RbaeServer<O, S>, McnpConnection<T>))
+        where T:'static + McnpTransport, E:'static + Fn(TcpStream) -> io::Result<T> + Clone + Send {
+        let mut builder = McnpServer::builder().port(self.port).timeouts(self.timeouts);
+        if let Some(max_connections) = self.max_connections {
+            builder = builder.max_connections(max_connections);
+        }
+        if let Some(keepalive) = self.keepalive {
+            builder = builder.keepalive(keepalive);
+        }
+        let mcnp_server = builder.build().expect("could not bind server socket");
 
-        let mut total_number_of_connections = 0;
         let connection_count = Arc::new(AtomicUsize::new(0));
-        loop {
-            let new_con = mcnp_server.server_socket.accept();
-            match new_con {
-                Err(e) => println!("couldn't get client: {:?}", e),
-                Ok((stream, addr)) => {
-                    total_number_of_connections+=1;
-                    println!("new connection(number {}) from: {} - spawning thread to handle", total_number_of_connections, addr);
-
-                    let mut alias = self.clone();
-                    let connection_count_clone = connection_count.clone();
-                    thread::Builder::new().name(format!("thread <connecting to: {}>", addr.to_string())).spawn(move || {
-                        connection_count_clone.fetch_add(1, Ordering::Relaxed);
-                        println!("spawned thread. number of currently connected clients: {}", connection_count_clone.load(Ordering::Relaxed));
-
-                        //important bit
-                        handle_new_connection(&mut alias, McnpConnection::new_from_stream(stream));
-
-                        connection_count_clone.fetch_sub(1, Ordering::Relaxed);
-                        println!("thread finished. number of currently connected clients: {}", connection_count_clone.load(Ordering::Relaxed));
-
-                    }).expect("spawing thread failed. wow this is really going downhill now.....");
-                }
+        mcnp_server.run_accept_loop(move |stream, addr| {
+            let mut alias = self.clone();
+            connection_count.fetch_add(1, Ordering::Relaxed);
+            println!("handling connection from {}. number of currently connected clients: {}", addr, connection_count.load(Ordering::Relaxed));
+
+            //important bit
+            match establish(stream).and_then(|transport| {
+                let mut connection = McnpConnection::new_from_stream(transport);
+                connection.set_timeouts(alias.timeouts)?;
+                connection.set_max_chunk_length(alias.max_chunk_length);
+                Ok(connection)
+            }) {
+                Ok(connection) => handle_new_connection(&mut alias, connection),
+                Err(e) => println!("establishing connection with {} failed: {}", addr, e)
             }
-        }
+
+            connection_count.fetch_sub(1, Ordering::Relaxed);
+            println!("connection finished. number of currently connected clients: {}", connection_count.load(Ordering::Relaxed));
+        })
     }
 
     pub fn handle_request_by(&mut self, cause:i32, state:&mut S) -> Result<(), StorageSystemError> {
@@ -115,6 +178,10 @@ impl<O, S> Clone for RbaeServer<O, S>
     fn clone(&self) -> Self {
         RbaeServer {
             port:self.port,
+            max_connections:self.max_connections,
+            timeouts:self.timeouts,
+            keepalive:self.keepalive,
+            max_chunk_length:self.max_chunk_length,
             ubae:self.ubae.clone(),
             observers:self.observers.clone(),
             cause_handlers:self.cause_handlers.clone()
@@ -128,8 +195,8 @@ impl<O, S> Clone for RbaeServer<O, S>
 
 
 
-impl<S> RbaeServer<ObserverConnection, S> {
-    pub fn add_observing_client(&mut self, connection:McnpConnection) {
+impl<T:McnpTransport, S> RbaeServer<ObserverConnection<T>, S> {
+    pub fn add_observing_client(&mut self, connection:McnpConnection<T>) {
         let mut obs = self.observers.lock().unwrap();
         let con_id = ObserverConnection::get_uid(&obs);
         obs.push(ObserverConnection::new(connection, con_id));
@@ -138,6 +205,29 @@ impl<S> RbaeServer<ObserverConnection, S> {
 
 impl RbaeServer<ObserverConnection, McnpConnection> {
     pub fn new_rbae(port:u16, ubae:Ubae<FileStorageSystem>) -> RbaeServer<ObserverConnection, McnpConnection> {
+        RbaeServer::with_rbae_cause_handlers(port, ubae)
+    }
+
+    pub fn run_logic_loop(self) {
+        self.general_run_logic_loop(RbaeServer::handle_new_connection);
+    }
+}
+
+#[cfg(feature = "tls")]
+impl RbaeServer<ObserverConnection<TlsStream>, McnpConnection<TlsStream>> {
+    /// A server whose clients and observers connect over tls (see McnpClient::new_tls and Rbae::new_from_connection).
+    pub fn new_tls_rbae(port:u16, ubae:Ubae<FileStorageSystem>) -> RbaeServer<ObserverConnection<TlsStream>, McnpConnection<TlsStream>> {
+        RbaeServer::with_rbae_cause_handlers(port, ubae)
+    }
+
+    /// Runs the server with the certificate and key from config.
+    pub fn run_tls_logic_loop(self, config:Arc<ServerConfig>) {
+        self.general_run_logic_loop_over(move |stream| TlsStream::accept(stream, config.clone()), RbaeServer::handle_new_connection);
+    }
+}
+
+impl<T:'static + McnpTransport> RbaeServer<ObserverConnection<T>, McnpConnection<T>> {
+    fn with_rbae_cause_handlers(port:u16, ubae:Ubae<FileStorageSystem>) -> RbaeServer<ObserverConnection<T>, McnpConnection<T>> {
         let server = RbaeServer::new_without_cause_handlers(port, ubae);
         server.add_cause_handler(rbae_mcnp_causes::ADD_ENTRY_BYTE_ARR, RbaeServer::handle_add_entry_byte_arr_by);
         server.add_cause_handler(rbae_mcnp_causes::ADD_ENTRY_BYTE_ARR_NOCHECK, RbaeServer::handle_add_entry_byte_arr_nocheck_by);
@@ -153,11 +243,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
         server
     }
 
-    pub fn run_logic_loop(self) {
-        self.general_run_logic_loop(RbaeServer::handle_new_connection);
-    }
-
-    fn handle_new_connection(&mut self, mut connection:McnpConnection) {
+    fn handle_new_connection(&mut self, mut connection:McnpConnection<T>) {
         match connection.read_cause() {
             Ok(rbae_mcnp_causes::INITIAL_CONNECTION_CAUSE__IS_OBSERVER) => {
 
@@ -168,13 +254,22 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
 
                 loop {
                     match connection.read_cause() {
+                        Err(ref e) if is_timeout(e) => {
+                            println!("client timed out (idle or stalled) - closing connection");
+                            break
+                        },
                         Err(_) => break, //indicates eof or error, hopefully eof
                         Ok(cause) => {
 //                          println!("cause: {}", cause);
                             match self.handle_request_by(cause, &mut connection) {
                                 Err(e) => {
-                                    println!("Error reading cause: {:?}", e)
+                                    println!("Error reading cause: {:?}", e);
                                     //don't break here. just because one error occured, does not mean one will always occur from now on
+                                    //unless the rest of a chunk over the limit is still to be read, the client is out of sync then
+                                    if let Some(length) = connection.pending_oversized_chunk() {
+                                        println!("client sent a chunk of {} bytes (over the limit) - closing connection", length);
+                                        break
+                                    }
                                 },
                                 Ok(_) => {}
                             }
@@ -195,7 +290,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
 
 
 
-    fn handle_add_entry_byte_arr_by(&mut self, connection:&mut McnpConnection) -> Result<(), StorageSystemError> {
+    fn handle_add_entry_byte_arr_by(&mut self, connection:&mut McnpConnection<T>) -> Result<(), StorageSystemError> {
         let tag = String::from_utf8(connection.read_variable_chunk()?).unwrap();
 
         //todo, this doesn't work for unknown reasons:
@@ -228,7 +323,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
 
     //todo succeptible to a denial of service attack.
 //todo The server can be endlessly blocked should someone send a stream with mcnp length indication n, but only supply m(where m < n) bytes without closing the stream
-    fn handle_add_entry_byte_arr_nocheck_by(&mut self, connection:&mut McnpConnection) -> Result<(), StorageSystemError> {
+    fn handle_add_entry_byte_arr_nocheck_by(&mut self, connection:&mut McnpConnection<T>) -> Result<(), StorageSystemError> {
         let tag = String::from_utf8(connection.read_variable_chunk()?).unwrap();
 
 
@@ -258,7 +353,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
         }
     }
 
-    fn handle_get_entry_byte_arr_by(&mut self, connection:&mut McnpConnection) -> Result<(), StorageSystemError> {
+    fn handle_get_entry_byte_arr_by(&mut self, connection:&mut McnpConnection<T>) -> Result<(), StorageSystemError> {
         let tag = String::from_utf8(connection.read_variable_chunk()?).unwrap();
 
         match self.get_entry_as_stream(&tag) {
@@ -272,7 +367,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
         Ok(())
     }
 
-    fn handle_delete_entry_byte_arr_by(&mut self, connection:&mut McnpConnection) -> Result<(), StorageSystemError> {
+    fn handle_delete_entry_byte_arr_by(&mut self, connection:&mut McnpConnection<T>) -> Result<(), StorageSystemError> {
         let tag = String::from_utf8(connection.read_variable_chunk()?).unwrap();
 
         match self.delete_entry(&tag) {
@@ -289,7 +384,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
         Ok(())
     }
 
-    fn handle_delete_entry_noreturn_by(&mut self, connection:&mut McnpConnection) -> Result<(), StorageSystemError> {
+    fn handle_delete_entry_noreturn_by(&mut self, connection:&mut McnpConnection<T>) -> Result<(), StorageSystemError> {
         let tag = String::from_utf8(connection.read_variable_chunk()?).unwrap();
 
         match self.delete_entry_noreturn(&tag) {
@@ -306,7 +401,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
         Ok(())
     }
 
-    fn handle_exists_by(&mut self, connection:&mut McnpConnection) -> Result<(), StorageSystemError> {
+    fn handle_exists_by(&mut self, connection:&mut McnpConnection<T>) -> Result<(), StorageSystemError> {
         let tag = String::from_utf8(connection.read_variable_chunk()?).unwrap();
 
         match self.tag_exists(&tag) {
@@ -320,7 +415,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
         Ok(())
     }
 
-    fn handle_get_tags_by(&mut self, connection:&mut McnpConnection) -> Result<(), StorageSystemError> {
+    fn handle_get_tags_by(&mut self, connection:&mut McnpConnection<T>) -> Result<(), StorageSystemError> {
         let mut libae = LIbae::new(VecStorageSystem::new_empty());
 
         let get_tags = self.get_tags()?;
@@ -332,7 +427,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
         Ok(())
     }
 
-    fn handle_length_by(&mut self, connection:&mut McnpConnection) -> Result<(), StorageSystemError> {
+    fn handle_length_by(&mut self, connection:&mut McnpConnection<T>) -> Result<(), StorageSystemError> {
         let tag = String::from_utf8(connection.read_variable_chunk()?).unwrap();
 
         match self.tag_length(&tag) {
@@ -347,7 +442,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
         }
     }
 
-    fn handle_set_content_by(&mut self, connection:&mut McnpConnection) -> Result<(), StorageSystemError> {
+    fn handle_set_content_by(&mut self, connection:&mut McnpConnection<T>) -> Result<(), StorageSystemError> {
         let new_content = connection.read_variable_chunk()?;
 
         match self.set_content(&new_content) {
@@ -363,7 +458,7 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
         }
     }
 
-    fn handle_get_content_by(&mut self, connection:&mut McnpConnection) -> Result<(), StorageSystemError> {
+    fn handle_get_content_by(&mut self, connection:&mut McnpConnection<T>) -> Result<(), StorageSystemError> {
         let content = self.get_content()?;
         connection.send_variable_chunk(&content)?;
         Ok(())
@@ -421,19 +516,19 @@ impl RbaeServer<ObserverConnection, McnpConnection> {
     }
 }
 
-pub struct ObserverConnection {
-    con:Arc<Mutex<McnpConnection>>,
+pub struct ObserverConnection<T:McnpTransport = TcpStream> {
+    con:Arc<Mutex<McnpConnection<T>>>,
     con_id:usize
 }
-impl ObserverConnection {
-    pub fn new(con:McnpConnection, con_id:usize) -> ObserverConnection {
+impl<T:McnpTransport> ObserverConnection<T> {
+    pub fn new(con:McnpConnection<T>, con_id:usize) -> ObserverConnection<T> {
         ObserverConnection {
             con:Arc::new(Mutex::new(con)),
             con_id
         }
     }
 
-    pub fn get_uid(observers:&[ObserverConnection]) -> usize {
+    pub fn get_uid(observers:&[ObserverConnection<T>]) -> usize {
         if observers.len() == 0 {
             0
         } else {
@@ -448,16 +543,16 @@ impl ObserverConnection {
             return uid;
         }
     }
-    pub fn lock(&self) -> MutexGuard<McnpConnection> {
+    pub fn lock(&self) -> MutexGuard<McnpConnection<T>> {
         self.con.lock().expect("obtaining lock for connection failed")
     }
 }
-impl PartialEq<ObserverConnection> for ObserverConnection {
-    fn eq(&self, other: &ObserverConnection) -> bool {
+impl<T:McnpTransport> PartialEq<ObserverConnection<T>> for ObserverConnection<T> {
+    fn eq(&self, other: &ObserverConnection<T>) -> bool {
         self.con_id == other.con_id
     }
 }
-impl Clone for ObserverConnection {
+impl<T:McnpTransport> Clone for ObserverConnection<T> {
     fn clone(&self) -> Self {
         ObserverConnection {
             con:self.con.clone(),
@@ -470,83 +565,58 @@ impl Clone for ObserverConnection {
 //a wrapper to use the ubae traits directly on the server.
 //it is absolutly required because it additionally provides the thread safety needed.
 //     itself required by the consistency constraint of libae and ubae itself
+//   reads share the lock, writes are exclusive (see SharedUbae)
 impl<O, S> UbaeTraits<File> for RbaeServer<O, S>
     where O: std::marker::Send + Clone + PartialEq<O> {
     fn set_content(&mut self, bytes: &[u8]) -> Result<(), StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.set_content(bytes)
+        self.ubae.set_content(bytes)
     }
 
     fn get_content(&mut self) -> Result<Vec<u8>, StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.get_content()
+        self.ubae.get_content()
     }
 
     fn get_tags(&mut self) -> Result<Vec<String>, StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.get_tags()
+        self.ubae.get_tags()
     }
 
     fn tag_exists(&mut self, tag: &str) -> Result<bool, StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.tag_exists(tag)
+        self.ubae.tag_exists(tag)
     }
 
     fn tag_length(&mut self, tag: &str) -> Result<i64, StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.tag_length(tag)
+        self.ubae.tag_length(tag)
     }
 
     fn get_entry(&mut self, tag: &str) -> Result<Option<Vec<u8>>, StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.get_entry(tag)
+        self.ubae.get_entry(tag)
     }
 
     fn get_entry_as_stream(&mut self, tag: &str) -> Result<Option<(Substream<File>, i64)>, StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.get_entry_as_stream(tag)
+        self.ubae.get_entry_as_stream(tag)
     }
 
     fn delete_entry(&mut self, tag: &str) -> Result<Option<Vec<u8>>, StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.delete_entry(tag)
+        self.ubae.delete_entry(tag)
     }
 
     fn delete_entry_noreturn(&mut self, tag: &str) -> Result<bool, StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.delete_entry_noreturn(tag)
+        self.ubae.delete_entry_noreturn(tag)
     }
 
     fn add_entry(&mut self, tag: &str, content: &[u8]) -> Result<(), StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.add_entry(tag, content)
+        self.ubae.add_entry(tag, content)
     }
 
     fn add_entry_nocheck(&mut self, tag: &str, content: &[u8]) -> Result<(), StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.add_entry_nocheck(tag, content)
+        self.ubae.add_entry_nocheck(tag, content)
     }
 
     fn add_entry_from_stream(&mut self, tag: &str, stream: &mut dyn Read, stream_length: i64) -> Result<(), StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.add_entry_from_stream(tag, stream, stream_length)
+        self.ubae.add_entry_from_stream(tag, stream, stream_length)
     }
 
     fn add_entry_from_stream_nocheck(&mut self, tag: &str, stream: &mut dyn Read, stream_length: i64) -> Result<(), StorageSystemError> {
-        let ubae = self.ubae.clone();
-        let mut locked_ubae = ubae.lock().unwrap();
-        locked_ubae.add_entry_from_stream_nocheck(tag, stream, stream_length)
+        self.ubae.add_entry_from_stream_nocheck(tag, stream, stream_length)
     }
 }
\ No newline at end of file
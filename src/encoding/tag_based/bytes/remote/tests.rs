@@ -72,6 +72,16 @@ fn rbae_test() {
         //client sees server
         assert_eq!(val1, rbae.get_entry("test").unwrap().unwrap());
 
+        //a stream shorter than announced adds nothing, the connection is shut down (the server's answer tells when it is done)
+        let mut aborted = Rbae::new("127.0.0.1", PORT);
+        assert!(aborted.add_entry_from_stream("short", &mut &val1[..5], val1.len() as i64).is_err());
+        assert!(aborted.client.read_fixed_chunk_u8().is_err()); //the server could not read the chunk and closed the connection
+        let mut aborted = Rbae::new("127.0.0.1", PORT);
+        assert!(aborted.add_entry_from_stream_nocheck("short", &mut &val1[..5], val1.len() as i64).is_err());
+        assert_eq!(rbae_mcnp_causes::ERROR as u8, aborted.client.read_fixed_chunk_u8().unwrap()); //streamed into the storage, then removed again
+        assert_eq!(false, rbae.tag_exists("short").unwrap());
+        assert_eq!(val1, rbae.get_entry("test").unwrap().unwrap());
+
         //client can send independant from server communication:
         rbae.client.send_cause(667).unwrap();
         rbae.client.send_variable_chunk("whats up. I am a client.".as_bytes()).unwrap();
@@ -103,4 +113,29 @@ fn rbae_test() {
     }
 
 //    _joinhandle.join().expect("woah. this never failed before");//if left out the thread is automatically dropped. Which can be cool, but also annoying.
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn rbae_tls_test() {
+    use crate::network::mcnp::mcnp_client::McnpClient;
+    use crate::network::mcnp::tests::self_signed_tls_configs;
+
+    const PORT:u16 = 17733;
+    let (server_config, client_config) = self_signed_tls_configs("localhost");
+    let rbae_storage_file = env::temp_dir().join(format!("rbae_tls_storage_{}", std::process::id()));
+    let rbae_server = rbae_server::RbaeServer::new_tls_rbae(PORT, Ubae::new(FileStorageSystem::create_leave_source_intact(rbae_storage_file.to_str().unwrap())));
+    thread::spawn(move || rbae_server.run_tls_logic_loop(server_config));
+    thread::sleep(Duration::from_millis(500));
+
+    let mut rbae = Rbae::new_from_connection(McnpClient::new_tls("127.0.0.1", PORT, client_config, "localhost").unwrap()).unwrap();
+    rbae.set_content(&[]).unwrap();
+    let val = vec![9u8; 50_000];
+    rbae.add_entry_nocheck("tag", &val).unwrap();
+    assert_eq!(val, rbae.get_entry("tag").unwrap().unwrap());
+    assert_eq!(vec!["tag".to_string()], rbae.get_tags().unwrap());
+    assert!(rbae.delete_entry_noreturn("tag").unwrap());
+
+    drop(rbae);
+    std::fs::remove_file(&rbae_storage_file).unwrap();
+}
@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+use std::sync::RwLockWriteGuard;
+
+use crate::transparent_storage::PositionalStorageSystem;
+use crate::transparent_storage::StorageSystemError;
+use crate::transparent_storage::Substream;
+
+use super::libae::get_start_and_end_index_of_li_chunk_at;
+use super::ubae::Ubae;
+use super::ubae::UbaeTraits;
+
+/// Thread safe handle to an ubae system. Cloning it yields another handle to the same system.
+///   Any number of readers can access the system at the same time, they only use positional reads(pread) on the storage system.
+///   Writers (set_content, add_entry*, delete_entry*) are serialized and exclude all readers while they run.
+///
+///   Unlike Ubae, undecodable content is reported as an error instead of being treated like the end of the content.
+pub struct SharedUbae<T:PositionalStorageSystem> {
+    ubae:Arc<RwLock<Ubae<T>>>
+}
+impl<T:PositionalStorageSystem> SharedUbae<T> {
+    pub fn new(ubae:Ubae<T>) -> SharedUbae<T> {
+        SharedUbae {
+            ubae:Arc::new(RwLock::new(ubae))
+        }
+    }
+
+    /// Locks the system for reading. Other readers are not blocked.
+    pub fn read(&self) -> RwLockReadGuard<'_, Ubae<T>> {
+        self.ubae.read().expect("obtaining read lock failed")
+    }
+    /// Locks the system exclusively. Required for anything that alters it, or a sequence of operations that has to be atomic.
+    pub fn write(&self) -> RwLockWriteGuard<'_, Ubae<T>> {
+        self.ubae.write().expect("obtaining write lock failed")
+    }
+
+    pub fn get_content(&self) -> Result<Vec<u8>, StorageSystemError> {
+        let locked_ubae = self.read();
+        let storage_system = locked_ubae.storage_system();
+        storage_system.read_at(0, storage_system.content_size()?)
+    }
+
+    pub fn get_tags(&self) -> Result<Vec<String>, StorageSystemError> {
+        let locked_ubae = self.read();
+        let mut tags:Vec<String> = Vec::new();
+        for_each_entry(&locked_ubae, |tag, _, _| {
+            tags.push(tag_to_string(tag)?);
+            Ok(false)
+        })?;
+        Ok(tags)
+    }
+
+    pub fn tag_exists(&self, tag:&str) -> Result<bool, StorageSystemError> {
+        Ok(find_entry(&self.read(), tag)?.is_some())
+    }
+
+    /// Returns -1 if the tag does not exist (like Ubae)
+    pub fn tag_length(&self, tag:&str) -> Result<i64, StorageSystemError> {
+        match find_entry(&self.read(), tag)? {
+            Some((content_start, content_end)) => Ok(content_end - content_start),
+            None => Ok(-1)
+        }
+    }
+
+    pub fn get_entry(&self, tag:&str) -> Result<Option<Vec<u8>>, StorageSystemError> {
+        let locked_ubae = self.read();
+        match find_entry(&locked_ubae, tag)? {
+            Some((content_start, content_end)) => Ok(Some(locked_ubae.storage_system().read_at(content_start, content_end)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// The stream reads without holding the lock,
+    ///   so an entry deleted or replaced while it is being read yields whatever is at that position afterwards.
+    pub fn get_entry_as_stream(&self, tag:&str) -> Result<Option<(Substream<File>, i64)>, StorageSystemError> {
+        let locked_ubae = self.read();
+        match find_entry(&locked_ubae, tag)? {
+            Some((content_start, content_end)) =>
+                Ok(Some((locked_ubae.storage_system().substream(content_start, content_end)?, content_end - content_start))),
+            None => Ok(None)
+        }
+    }
+}
+impl<T:PositionalStorageSystem> Clone for SharedUbae<T> {
+    fn clone(&self) -> Self {
+        SharedUbae {
+            ubae:self.ubae.clone()
+        }
+    }
+}
+
+//calls consumer with (tag, content start, content end) for each entry, until it returns true
+fn for_each_entry<T, F>(ubae:&Ubae<T>, mut consumer:F) -> Result<(), StorageSystemError>
+    where T:PositionalStorageSystem, F:FnMut(&[u8], i64, i64) -> Result<bool, StorageSystemError> {
+    let storage_system = ubae.storage_system();
+    let encoding = ubae.encoding();
+    let mut read_pointer = encoding.header_length();
+    while let Some((tag_start, tag_end)) = get_start_and_end_index_of_li_chunk_at(read_pointer, storage_system, encoding)? {
+        let (content_start, content_end) = match get_start_and_end_index_of_li_chunk_at(tag_end, storage_system, encoding)? {
+            Some(content) => content,
+            None => return Err(StorageSystemError::new("truncated ubae data: tag without entry"))
+        };
+        if consumer(&storage_system.read_at(tag_start, tag_end)?, content_start, content_end)? {
+            break
+        }
+        read_pointer = content_end;
+    }
+    Ok(())
+}
+
+//returns (content start, content end) of the entry with the given tag
+fn find_entry<T:PositionalStorageSystem>(ubae:&Ubae<T>, tag:&str) -> Result<Option<(i64, i64)>, StorageSystemError> {
+    let search_tag_as_bytes = tag.as_bytes(); //&str guarantees utf8
+    let mut found = None;
+    for_each_entry(ubae, |decoded_tag, content_start, content_end| {
+        if search_tag_as_bytes == decoded_tag {
+            found = Some((content_start, content_end));
+        }
+        Ok(found.is_some())
+    })?;
+    Ok(found)
+}
+
+fn tag_to_string(tag:&[u8]) -> Result<String, StorageSystemError> {
+    String::from_utf8(tag.to_vec()).map_err(|_| StorageSystemError::new("corrupt ubae data: tag is not valid utf8"))
+}
+
+/// Reads take the shared lock, writes the exclusive one.
+impl<T:PositionalStorageSystem> UbaeTraits<File> for SharedUbae<T> {
+    fn set_content(&mut self, bytes: &[u8]) -> Result<(), StorageSystemError> {
+        self.write().set_content(bytes)
+    }
+    fn get_content(&mut self) -> Result<Vec<u8>, StorageSystemError> {
+        SharedUbae::get_content(self)
+    }
+
+    fn get_tags(&mut self) -> Result<Vec<String>, StorageSystemError> {
+        SharedUbae::get_tags(self)
+    }
+    fn tag_exists(&mut self, tag: &str) -> Result<bool, StorageSystemError> {
+        SharedUbae::tag_exists(self, tag)
+    }
+    fn tag_length(&mut self, tag: &str) -> Result<i64, StorageSystemError> {
+        SharedUbae::tag_length(self, tag)
+    }
+    fn get_entry(&mut self, tag: &str) -> Result<Option<Vec<u8>>, StorageSystemError> {
+        SharedUbae::get_entry(self, tag)
+    }
+    fn get_entry_as_stream(&mut self, tag: &str) -> Result<Option<(Substream<File>, i64)>, StorageSystemError> {
+        SharedUbae::get_entry_as_stream(self, tag)
+    }
+
+    fn delete_entry(&mut self, tag: &str) -> Result<Option<Vec<u8>>, StorageSystemError> {
+        self.write().delete_entry(tag)
+    }
+    fn delete_entry_noreturn(&mut self, tag: &str) -> Result<bool, StorageSystemError> {
+        self.write().delete_entry_noreturn(tag)
+    }
+
+    fn add_entry(&mut self, tag: &str, content: &[u8]) -> Result<(), StorageSystemError> {
+        self.write().add_entry(tag, content)
+    }
+    fn add_entry_nocheck(&mut self, tag: &str, content: &[u8]) -> Result<(), StorageSystemError> {
+        self.write().add_entry_nocheck(tag, content)
+    }
+    fn add_entry_from_stream(&mut self, tag: &str, stream: &mut dyn Read, stream_length: i64) -> Result<(), StorageSystemError> {
+        self.write().add_entry_from_stream(tag, stream, stream_length)
+    }
+    fn add_entry_from_stream_nocheck(&mut self, tag: &str, stream: &mut dyn Read, stream_length: i64) -> Result<(), StorageSystemError> {
+        self.write().add_entry_from_stream_nocheck(tag, stream, stream_length)
+    }
+}
@@ -5,10 +5,20 @@ use std::io;
 use std::io::BufReader;
 use std::io::Read;
 use std::io::Write;
+use std::ops::Deref;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
 
+use crate::encoding::tag_based::bytes::libae::LIEncoding;
 use crate::encoding::tag_based::bytes::libae::LIbae;
 use crate::encoding::tag_based::bytes::libae::LIbaeTraits;
+use crate::encoding::tag_based::bytes::libae::VARINT_ENCODING_HEADER;
+use crate::encoding::tag_based::bytes::libae_stream::LIbaeReader;
+use crate::encoding::tag_based::bytes::libae_stream::LIbaeWriter;
+use crate::encoding::tag_based::bytes::shared_ubae::SharedUbae;
 use crate::encoding::tag_based::bytes::ubae::Ubae;
 use crate::encoding::tag_based::bytes::ubae::UbaeTraits;
 use crate::encoding::tag_based::bytes::ubae_directory_encoder;
@@ -16,6 +26,7 @@ use crate::time_keeper::TimeKeeper;
 use crate::transparent_storage::bytes::file_storage_system::FileStorageSystem;
 use crate::transparent_storage::bytes::vec_storage_system::VecStorageSystem;
 use crate::transparent_storage::StorageSystem;
+use crate::transparent_storage::StorageSystemError;
 
 #[test]
 fn test_li_encoding() {
@@ -60,6 +71,183 @@ fn test_li_encoding() {
     time_keeper.println_set_mark("li decoding and assertions took");
 }
 
+#[test]
+fn test_li_decoding_truncated() {
+    let mut libae = LIbae::ram();
+    libae.li_encode_single(&[1, 2, 3]).expect("error encoding single vec 1");
+    libae.li_encode_single(&vec![20;300]).expect("error encoding single vec 2");
+    let complete = libae.get_content().unwrap();
+
+    //end of data is not an error
+    assert_eq!(vec![vec![1, 2, 3], vec![20;300]], libae.li_try_decode_all().unwrap());
+    assert_eq!(None, libae.li_try_decode_single().unwrap());
+    assert_eq!(None, libae.li_try_skip_single().unwrap());
+
+    //content cut off
+    libae.set_content(&complete[..complete.len()-1]).unwrap();
+    libae.reset_read_pointer();
+    assert_eq!(vec![1, 2, 3], libae.li_try_decode_single().unwrap().unwrap());
+    assert!(libae.li_try_decode_single().is_err());
+    assert!(libae.li_try_skip_single().is_err());
+    assert_eq!(None, libae.li_decode_single()); //the old api cannot tell the difference
+    assert_eq!(-1, libae.li_skip_single());
+
+    //length indicator cut off (vec 2 requires 2 length indicator bytes)
+    libae.set_content(&complete[..5 + 2]).unwrap();
+    libae.reset_read_pointer();
+    assert_eq!(Some(3), libae.li_try_skip_single().unwrap());
+    assert!(libae.li_try_decode_single().is_err());
+
+    //leading length indicator byte larger than 8
+    libae.set_content(&[9, 0, 0, 0, 0, 0, 0, 0, 0, 1, 5]).unwrap();
+    libae.reset_read_pointer();
+    assert!(libae.li_try_decode_single().is_err());
+
+    //the iterator surfaces the error exactly once
+    libae.set_content(&complete[..complete.len()-1]).unwrap();
+    libae.reset_read_pointer();
+    let iterated:Vec<Result<Vec<u8>, StorageSystemError>> = (&mut libae).collect();
+    assert_eq!(2, iterated.len());
+    assert_eq!(vec![1, 2, 3], *iterated[0].as_ref().unwrap());
+    assert!(iterated[1].is_err());
+    assert!(libae.next().is_none());
+    libae.reset_read_pointer();
+    assert_eq!(vec![1, 2, 3], libae.next().unwrap().unwrap());
+}
+
+#[test]
+fn test_li_varint_encoding() {
+    let lengths = vec![0usize, 1, 127, 128, 300, 16383, 16384, 100000];
+    let mut libae = LIbae::ram_varint();
+    assert_eq!(LIEncoding::Varint, libae.encoding());
+    for length in &lengths {
+        libae.li_encode_single(&vec![7;*length]).expect("error encoding single vec");
+    }
+    for length in &lengths {
+        assert_eq!(vec![7;*length], libae.li_try_decode_single().unwrap().unwrap());
+    }
+    assert_eq!(None, libae.li_try_decode_single().unwrap());
+
+    //detected on read, without being told
+    let encoded = libae.get_content().unwrap();
+    assert_eq!(VARINT_ENCODING_HEADER, encoded[0]);
+    let mut storage = VecStorageSystem::new_empty();
+    storage.set_content(&encoded).unwrap();
+    let mut detected = LIbae::new(storage);
+    assert_eq!(LIEncoding::Varint, detected.encoding());
+    assert_eq!(lengths.len(), detected.li_try_decode_all().unwrap().len());
+
+    //old content keeps working
+    let mut legacy = LIbae::ram();
+    legacy.li_encode_single(&[1, 2, 3]).unwrap();
+    let mut storage = VecStorageSystem::new_empty();
+    storage.set_content(&legacy.get_content().unwrap()).unwrap();
+    let mut detected = LIbae::new(storage);
+    assert_eq!(LIEncoding::LeadingByte, detected.encoding());
+    assert_eq!(vec![1, 2, 3], detected.li_try_decode_single().unwrap().unwrap());
+    assert!(LIbae::new_with_encoding(detected.storage_system, LIEncoding::Varint).is_err());
+
+    //tiny chunks are cheaper
+    let mut legacy = LIbae::ram();
+    let mut varint = LIbae::ram_varint();
+    for _ in 0..1000 {
+        legacy.li_encode_single(&[1, 2, 3]).unwrap();
+        varint.li_encode_single(&[1, 2, 3]).unwrap();
+    }
+    assert_eq!(5000, legacy.storage_system.content_size().unwrap());
+    assert_eq!(4001, varint.storage_system.content_size().unwrap());
+
+    //clearing keeps the encoding
+    varint.set_content(&[]).unwrap();
+    assert_eq!(LIEncoding::Varint, varint.encoding());
+    varint.li_encode_single(&[4]).unwrap();
+    assert_eq!(vec![VARINT_ENCODING_HEADER, 1, 4], varint.get_content().unwrap());
+
+    //truncated varint length indicator (300 requires 2 bytes)
+    varint.set_content(&[VARINT_ENCODING_HEADER, 0b1010_1100]).unwrap();
+    assert!(varint.li_try_decode_single().unwrap_err().to_string().contains("truncated"));
+
+    //a varint length indicator that does not end within 9 bytes is corrupt, not truncated
+    let mut overlong = vec![VARINT_ENCODING_HEADER];
+    overlong.extend_from_slice(&[0xFF; 9]);
+    overlong.extend_from_slice(&[0x01, 5, 5]);
+    varint.set_content(&overlong).unwrap();
+    assert!(varint.li_try_decode_single().unwrap_err().to_string().contains("corrupt"));
+    assert_eq!(io::ErrorKind::InvalidData, LIbaeReader::new(&overlong[..]).li_decode_single().unwrap_err().kind());
+}
+
+#[test]
+fn test_ubae_varint_encoder() {
+    let mut ubae = Ubae::new_with_encoding(VecStorageSystem::new_empty(), LIEncoding::Varint).unwrap();
+    ubae.add_entry("1", &[1, 1]).expect("adding entry 1 failed");
+    ubae.add_entry("2", &[2, 2, 2]).expect("adding entry 2 failed");
+    ubae.add_entry("3", &vec![3;1000]).expect("adding entry 3 failed");
+
+    assert_eq!(vec!["1", "2", "3"], ubae.get_tags().unwrap());
+    assert_eq!(3, ubae.tag_length("2").unwrap());
+    assert_eq!(vec![1, 1], ubae.delete_entry("1").unwrap().unwrap()); //first entry, directly behind the header
+    assert!(ubae.delete_entry_noreturn("2").unwrap());
+    assert_eq!(vec![3;1000], ubae.get_entry("3").unwrap().unwrap());
+    assert_eq!(vec!["3"], ubae.get_tags().unwrap());
+    assert_eq!(VARINT_ENCODING_HEADER, ubae.get_content().unwrap()[0]);
+}
+
+#[test]
+fn test_li_writer_and_reader() {
+    for encoding in [LIEncoding::LeadingByte, LIEncoding::Varint] {
+        let chunks = vec![vec![], vec![1u8, 2, 3], vec![20;300], vec![30;100000]];
+
+        let mut writer = LIbaeWriter::new_with_encoding(Vec::new(), encoding).unwrap();
+        let mut libae = LIbae::new_with_encoding(VecStorageSystem::new_empty(), encoding).unwrap();
+        for chunk in &chunks {
+            writer.li_encode_single(chunk).unwrap();
+            libae.li_encode_single(chunk).unwrap();
+        }
+        let mut stream_source = &chunks[2][..];
+        writer.li_encode_single_stream(&mut stream_source, 300).unwrap();
+        libae.li_encode_single(&chunks[2]).unwrap();
+        let written = writer.into_inner();
+        assert_eq!(libae.get_content().unwrap(), written); //byte for byte identical
+
+        let mut reader = LIbaeReader::new(&written[..]);
+        assert_eq!(encoding, reader.encoding().unwrap());
+        assert_eq!(chunks[0], reader.li_decode_single().unwrap().unwrap());
+        assert_eq!(Some(3), reader.li_skip_single().unwrap());
+        {
+            let (mut chunk_stream, chunk_length) = reader.li_decode_single_stream().unwrap().unwrap();
+            assert_eq!(300, chunk_length);
+            let mut partial = vec![0u8; 10];
+            chunk_stream.read_exact(&mut partial).unwrap();
+            assert_eq!(vec![20u8; 10], partial);
+        } //rest of the chunk is skipped
+        assert_eq!(chunks[3], reader.li_decode_single().unwrap().unwrap());
+        let rest:Vec<Vec<u8>> = reader.map(|chunk| chunk.unwrap()).collect();
+        assert_eq!(vec![chunks[2].clone()], rest);
+
+        //truncated content is an error, not the end
+        let mut reader = LIbaeReader::new(&written[..written.len()-1]);
+        let iterated:Vec<io::Result<Vec<u8>>> = (&mut reader).collect();
+        assert_eq!(5, iterated.len());
+        assert_eq!(io::ErrorKind::UnexpectedEof, iterated[4].as_ref().unwrap_err().kind());
+        assert!(reader.next().is_none());
+    }
+
+    //short source streams are padded, but reported
+    let mut writer = LIbaeWriter::new(Vec::new());
+    let mut short_source = &[1u8, 2][..];
+    assert!(writer.li_encode_single_stream(&mut short_source, 4).is_err());
+    writer.li_encode_single(&[5]).unwrap();
+    let mut reader = LIbaeReader::new(&writer.get_ref()[..]);
+    assert_eq!(vec![1, 2, 0, 0], reader.li_decode_single().unwrap().unwrap());
+    assert_eq!(vec![5], reader.li_decode_single().unwrap().unwrap());
+    assert!(reader.li_decode_single().unwrap().is_none());
+
+    //empty content
+    assert!(LIbaeReader::new(&[][..]).li_decode_single().unwrap().is_none());
+    //corrupt leading byte
+    assert_eq!(io::ErrorKind::InvalidData, LIbaeReader::new(&[12u8, 1][..]).li_decode_single().unwrap_err().kind());
+}
+
 #[test]
 fn test_stream_li_encoding() {
     let mut time_keeper = TimeKeeper::init();
@@ -302,6 +490,690 @@ fn test_ubae_on_file() {
 
 
 
+#[test]
+fn test_shared_ubae_concurrent_readers() {
+    let fp1 = env::home_dir().unwrap().join(Path::new("Desktop/test_file_storage_shared_ubae.txt"));
+    let path = fp1.to_str().unwrap();
+
+    let mut storage = FileStorageSystem::create_leave_source_intact(path);
+    storage.set_content(&[]).expect("set CONTENT FAILED");
+    let shared = SharedUbae::new(Ubae::new(storage));
+    shared.write().add_entry("fixed", &[7u8; 5000]).unwrap();
+    shared.write().add_entry("other", &[1, 2, 3]).unwrap();
+
+    //a held read lock does not block other readers (with a mutex this would time out)
+    {
+        let _read_guard = shared.read();
+        let (sender, receiver) = mpsc::channel();
+        let reader = shared.clone();
+        thread::spawn(move || {
+            sender.send(reader.get_entry("other").unwrap()).unwrap();
+        });
+        assert_eq!(Some(vec![1, 2, 3]), receiver.recv_timeout(Duration::from_secs(10)).expect("reader blocked by other reader"));
+    }
+
+    //readers see consistent entries while a writer alters the system
+    let mut handles = Vec::new();
+    for _ in 0..4 {
+        let reader = shared.clone();
+        handles.push(thread::spawn(move || {
+            for _ in 0..200 {
+                assert_eq!(Some(vec![7u8; 5000]), reader.get_entry("fixed").unwrap());
+                assert!(reader.get_tags().unwrap().contains(&"fixed".to_string()));
+            }
+        }));
+    }
+    let mut writer = shared.clone();
+    for i in 0..200 {
+        writer.add_entry(&format!("w{}", i % 10), &vec![i as u8; i]).unwrap();
+    }
+    for handle in handles {
+        handle.join().expect("reader failed");
+    }
+
+    assert_eq!(12, shared.get_tags().unwrap().len());
+    assert_eq!(Some(vec![199u8; 199]), shared.get_entry("w9").unwrap());
+    assert_eq!(199, shared.tag_length("w9").unwrap());
+    let mut sync_ubae = Ubae::new(VecStorageSystem::new_empty());
+    sync_ubae.set_content(&shared.get_content().unwrap()).unwrap();
+    assert_eq!(shared.get_tags().unwrap(), sync_ubae.get_tags().unwrap());
+}
+
+//a fresh directory within the temp directory, removed again once dropped (also if the test fails)
+struct TestDir {
+    path:PathBuf
+}
+impl TestDir {
+    fn new(name:&str) -> TestDir {
+        let path = env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path); //left over by an aborted run
+        TestDir { path }
+    }
+}
+impl Deref for TestDir {
+    type Target = Path;
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[test]
+fn directory_encoder_round_trip_test() {
+    let test_root = TestDir::new("ubae_directory_encoder_round_trip");
+    let orig_dir = test_root.join("orig");
+    let target_file = test_root.join("encoded.ubae");
+    let out_dir = test_root.join("out");
+
+    fs::create_dir_all(orig_dir.join("sub/empty_nested")).unwrap();
+    fs::create_dir_all(orig_dir.join("empty")).unwrap();
+    fs::write(orig_dir.join("plain.txt"), b"plain content").unwrap();
+    fs::write(orig_dir.join("sub/data.bin"), vec![13u8; 100_000]).unwrap();
+    fs::write(orig_dir.join("run.sh"), b"#!/bin/sh\necho hi\n").unwrap();
+    let old_mtime = std::time::UNIX_EPOCH + Duration::new(1_500_000_000, 123_456_789);
+    File::options().write(true).open(orig_dir.join("plain.txt")).unwrap().set_modified(old_mtime).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(orig_dir.join("run.sh"), fs::Permissions::from_mode(0o4755)).unwrap(); //setuid
+        fs::set_permissions(orig_dir.join("sub/data.bin"), fs::Permissions::from_mode(0o600)).unwrap();
+        std::os::unix::fs::symlink("sub/data.bin", orig_dir.join("link_to_data")).unwrap();
+        std::os::unix::fs::symlink("does_not_exist", orig_dir.join("dangling_link")).unwrap();
+    }
+    File::open(orig_dir.join("empty")).unwrap().set_modified(old_mtime).unwrap();
+
+    assert_eq!(0, ubae_directory_encoder::encode(orig_dir.to_str().unwrap(), target_file.to_str().unwrap()));
+    assert_eq!(0, ubae_directory_encoder::decode(target_file.to_str().unwrap(), out_dir.to_str().unwrap()));
+
+    assert_eq!(b"plain content".to_vec(), fs::read(out_dir.join("plain.txt")).unwrap());
+    assert_eq!(vec![13u8; 100_000], fs::read(out_dir.join("sub/data.bin")).unwrap());
+    assert!(out_dir.join("empty").is_dir());
+    assert!(out_dir.join("sub/empty_nested").is_dir());
+    assert_eq!(old_mtime, fs::metadata(out_dir.join("plain.txt")).unwrap().modified().unwrap());
+    assert_eq!(old_mtime, fs::metadata(out_dir.join("empty")).unwrap().modified().unwrap());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(0o755, fs::metadata(out_dir.join("run.sh")).unwrap().permissions().mode() & 0o7777); //setuid is not restored by default
+        assert_eq!(0o600, fs::metadata(out_dir.join("sub/data.bin")).unwrap().permissions().mode() & 0o7777);
+        assert_eq!(Path::new("sub/data.bin"), fs::read_link(out_dir.join("link_to_data")).unwrap());
+        assert_eq!(Path::new("does_not_exist"), fs::read_link(out_dir.join("dangling_link")).unwrap());
+        assert_eq!(vec![13u8; 100_000], fs::read(out_dir.join("link_to_data")).unwrap());
+
+        use crate::encoding::tag_based::bytes::ubae_directory_encoder::{OverwritePolicy, PathValidation};
+        let special_out_dir = test_root.join("out_special");
+        let report = ubae_directory_encoder::extract_with_report(target_file.to_str().unwrap(), special_out_dir.to_str().unwrap(), None, OverwritePolicy::Overwrite,
+                                                                 PathValidation::Normalize, true, 1, None);
+        assert!(report.is_success());
+        assert_eq!(0o4755, fs::metadata(special_out_dir.join("run.sh")).unwrap().permissions().mode() & 0o7777);
+    }
+}
+
+#[test]
+fn directory_encoder_update_test() {
+    let test_root = TestDir::new("ubae_directory_encoder_update");
+    let orig_dir = test_root.join("orig");
+    let archive = test_root.join("archive.ubae");
+    let out_dir = test_root.join("out");
+    let mtime = std::time::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+    fs::create_dir_all(orig_dir.join("sub")).unwrap();
+    fs::write(orig_dir.join("unchanged.txt"), b"same").unwrap();
+    fs::write(orig_dir.join("same_size.txt"), b"aaaa").unwrap();
+    fs::write(orig_dir.join("touched.txt"), b"only the mtime changes").unwrap();
+    fs::write(orig_dir.join("sub/deleted.txt"), b"gone soon").unwrap();
+    File::options().write(true).open(orig_dir.join("same_size.txt")).unwrap().set_modified(mtime).unwrap();
+    File::options().write(true).open(orig_dir.join("touched.txt")).unwrap().set_modified(mtime).unwrap();
+
+    //creates the archive if it does not exist
+    let summary = ubae_directory_encoder::update(orig_dir.to_str().unwrap(), archive.to_str().unwrap());
+    assert_eq!(5, summary.added);
+    assert!(summary.report.failed.is_empty());
+
+    fs::write(orig_dir.join("same_size.txt"), b"bbbb").unwrap(); //same size, different content and mtime
+    File::options().write(true).open(orig_dir.join("touched.txt")).unwrap().set_modified(mtime + Duration::from_secs(60)).unwrap();
+    fs::remove_file(orig_dir.join("sub/deleted.txt")).unwrap();
+    fs::write(orig_dir.join("sub/new.txt"), b"new file").unwrap();
+    fs::create_dir(orig_dir.join("new_empty_dir")).unwrap();
+    File::open(orig_dir.join("sub")).unwrap().set_modified(mtime).unwrap();
+
+    let mut progress_calls = Vec::new();
+    let summary = ubae_directory_encoder::update_with_report(orig_dir.to_str().unwrap(), archive.to_str().unwrap(),
+                                                             Some(&mut |progress:&ubae_directory_encoder::Progress| progress_calls.push((progress.files_done, progress.files_total, progress.bytes_done, progress.bytes_total))));
+    assert_eq!((
+        2, //added: sub/new.txt, new_empty_dir
+        1, //replaced: same_size.txt
+        2, //metadata updated: touched.txt (hash decides content is unchanged), sub
+        1, //deleted: sub/deleted.txt
+        1, //unchanged: unchanged.txt
+        0  //errors
+    ), (summary.added, summary.replaced, summary.metadata_updated, summary.deleted, summary.unchanged, summary.error_count()));
+    assert_eq!((2, 1, 0, 12), (summary.report.files, summary.report.directories, summary.report.symlinks, summary.report.bytes_written)); //sub/new.txt, same_size.txt, new_empty_dir
+    assert_eq!(2, progress_calls.len());
+    assert_eq!((2, 2, 12, 12), progress_calls[1]);
+
+    assert!(ubae_directory_encoder::verify(archive.to_str().unwrap()).unwrap().is_empty());
+
+    #[cfg(unix)]
+    { //failures are reported per path, the rest is still updated
+        use std::os::unix::ffi::OsStrExt;
+        std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(b"not\xffunicode"), orig_dir.join("bad_link")).unwrap();
+    }
+    let summary = ubae_directory_encoder::update(orig_dir.to_str().unwrap(), archive.to_str().unwrap());
+    assert_eq!(6, summary.unchanged);
+    assert_eq!(6, summary.unchanged + summary.added + summary.replaced + summary.metadata_updated + summary.deleted);
+    #[cfg(unix)]
+    {
+        assert_eq!(1, summary.report.failed.len());
+        assert!(summary.report.failed[0].0.ends_with("bad_link"));
+    }
+
+    assert_eq!(0, ubae_directory_encoder::decode(archive.to_str().unwrap(), out_dir.to_str().unwrap()));
+    assert_eq!(b"bbbb".to_vec(), fs::read(out_dir.join("same_size.txt")).unwrap());
+    assert_eq!(b"new file".to_vec(), fs::read(out_dir.join("sub/new.txt")).unwrap());
+    assert!(!out_dir.join("sub/deleted.txt").exists());
+    assert!(out_dir.join("new_empty_dir").is_dir());
+    assert_eq!(mtime + Duration::from_secs(60), fs::metadata(out_dir.join("touched.txt")).unwrap().modified().unwrap());
+}
+
+#[test]
+fn directory_encoder_list_and_extract_test() {
+    use crate::encoding::tag_based::bytes::ubae_directory_encoder::{EntryKind, OverwritePolicy, path_matches};
+
+    assert!(path_matches("sub/*.txt", "sub/a.txt"));
+    assert!(!path_matches("sub/*.txt", "sub/deeper/a.txt"));
+    assert!(path_matches("sub/**.txt", "sub/deeper/a.txt"));
+    assert!(path_matches("**/a.txt", "a.txt"));
+    assert!(path_matches("**/a.txt", "x/y/a.txt"));
+    assert!(path_matches("?.bin", "b.bin"));
+    assert!(!path_matches("?.bin", "bb.bin"));
+
+    let test_root = TestDir::new("ubae_directory_encoder_extract");
+    let orig_dir = test_root.join("orig");
+    let archive = test_root.join("archive.ubae");
+    let archive_path = archive.to_str().unwrap();
+
+    fs::create_dir_all(orig_dir.join("sub/deeper")).unwrap();
+    fs::create_dir_all(orig_dir.join("empty")).unwrap();
+    fs::write(orig_dir.join("top.bin"), vec![1u8; 10]).unwrap();
+    fs::write(orig_dir.join("sub/a.txt"), b"a").unwrap();
+    fs::write(orig_dir.join("sub/deeper/b.txt"), b"bb").unwrap();
+    assert_eq!(0, ubae_directory_encoder::encode(orig_dir.to_str().unwrap(), archive_path));
+
+    let listed = ubae_directory_encoder::list(archive_path).unwrap();
+    let listed:Vec<(&str, EntryKind, u64)> = listed.iter().map(|entry| (entry.path.as_str(), entry.kind, entry.size)).collect();
+    assert_eq!(vec![("empty", EntryKind::Directory, 0), ("sub", EntryKind::Directory, 0), ("sub/a.txt", EntryKind::File, 1),
+                    ("sub/deeper", EntryKind::Directory, 0), ("sub/deeper/b.txt", EntryKind::File, 2), ("top.bin", EntryKind::File, 10)], listed);
+
+    let partial = test_root.join("partial");
+    assert_eq!(0, ubae_directory_encoder::extract_paths(archive_path, &["*.bin", "sub/deeper"], partial.to_str().unwrap()));
+    assert!(partial.join("top.bin").is_file());
+    assert!(partial.join("sub/deeper/b.txt").is_file());
+    assert!(!partial.join("sub/a.txt").exists());
+    assert!(!partial.join("empty").exists());
+
+    let existing = test_root.join("existing");
+    fs::create_dir_all(&existing).unwrap();
+    fs::write(existing.join("top.bin"), b"local").unwrap();
+    fs::write(existing.join("unrelated"), b"kept").unwrap();
+    assert_eq!(0, ubae_directory_encoder::extract_into_existing(archive_path, existing.to_str().unwrap(), OverwritePolicy::Skip));
+    assert_eq!(b"local".to_vec(), fs::read(existing.join("top.bin")).unwrap());
+    assert_eq!(b"a".to_vec(), fs::read(existing.join("sub/a.txt")).unwrap());
+    assert!(existing.join("empty").is_dir());
+    //the local file is newer than the archived one
+    assert_eq!(0, ubae_directory_encoder::extract_into_existing(archive_path, existing.to_str().unwrap(), OverwritePolicy::OverwriteIfNewer));
+    assert_eq!(b"local".to_vec(), fs::read(existing.join("top.bin")).unwrap());
+    File::options().write(true).open(existing.join("top.bin")).unwrap().set_modified(std::time::UNIX_EPOCH).unwrap();
+    assert_eq!(0, ubae_directory_encoder::extract_into_existing(archive_path, existing.to_str().unwrap(), OverwritePolicy::OverwriteIfNewer));
+    assert_eq!(vec![1u8; 10], fs::read(existing.join("top.bin")).unwrap());
+    fs::write(existing.join("sub/a.txt"), b"changed").unwrap();
+    assert_eq!(0, ubae_directory_encoder::extract_into_existing(archive_path, existing.to_str().unwrap(), OverwritePolicy::Overwrite));
+    assert_eq!(b"a".to_vec(), fs::read(existing.join("sub/a.txt")).unwrap());
+    assert_eq!(b"kept".to_vec(), fs::read(existing.join("unrelated")).unwrap());
+}
+
+#[test]
+fn directory_encoder_report_test() {
+    use crate::encoding::tag_based::bytes::ubae_directory_encoder::{OverwritePolicy, PathValidation, Progress};
+
+    let test_root = TestDir::new("ubae_directory_encoder_report");
+    let orig_dir = test_root.join("orig");
+    let archive = test_root.join("archive.ubae");
+    fs::create_dir_all(orig_dir.join("sub/empty")).unwrap();
+    fs::write(orig_dir.join("a.txt"), b"aaa").unwrap();
+    fs::write(orig_dir.join("sub/b.txt"), vec![2u8; 1000]).unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("a.txt", orig_dir.join("link")).unwrap();
+    let symlinks = if cfg!(unix) { 1 } else { 0 };
+
+    for threads in [1, 3] {
+        let _ = fs::remove_file(&archive);
+        let mut progress_calls = Vec::new();
+        let report = ubae_directory_encoder::encode_with_report(orig_dir.to_str().unwrap(), archive.to_str().unwrap(), threads, false, Some(&mut |progress:&Progress| {
+            progress_calls.push((progress.files_done, progress.files_total, progress.bytes_done, progress.bytes_total));
+        }));
+        assert!(report.is_success());
+        assert_eq!((2, 2, symlinks), (report.files, report.directories, report.symlinks));
+        assert_eq!(1003, report.bytes_written);
+        assert_eq!(2, progress_calls.len());
+        assert_eq!((2, 2, 1003, 1003), progress_calls[1]);
+    }
+
+    let out_dir = test_root.join("out");
+    fs::create_dir_all(&out_dir).unwrap();
+    fs::write(out_dir.join("a.txt"), b"existing").unwrap();
+    fs::create_dir(out_dir.join("sub")).unwrap();
+    fs::create_dir(out_dir.join("sub/b.txt")).unwrap(); //a directory is in the way of a file
+    let mut files_done = 0;
+    let report = ubae_directory_encoder::extract_with_report(archive.to_str().unwrap(), out_dir.to_str().unwrap(), None, OverwritePolicy::Skip,
+                                                             PathValidation::Normalize, false, 2, Some(&mut |progress:&Progress| files_done = progress.files_done));
+    assert_eq!(vec!["a.txt".to_string(), "sub".to_string(), "sub/b.txt".to_string()], { let mut skipped = report.skipped.clone(); skipped.sort(); skipped });
+    assert_eq!(0, files_done);
+    assert_eq!(0, report.failed.len()); //skipped, not overwritten
+
+    let report = ubae_directory_encoder::extract_with_report(archive.to_str().unwrap(), out_dir.to_str().unwrap(), None, OverwritePolicy::Overwrite,
+                                                             PathValidation::Normalize, false, 2, None);
+    assert_eq!(1, report.failed.len());
+    assert_eq!("sub/b.txt", report.failed[0].0);
+    assert_eq!(io::ErrorKind::AlreadyExists, report.failed[0].1.kind());
+    assert_eq!((1, 2, symlinks, 3), (report.files, report.directories, report.symlinks, report.bytes_written));
+    assert_eq!(b"aaa".to_vec(), fs::read(out_dir.join("a.txt")).unwrap());
+}
+
+#[test]
+fn directory_encoder_deduplication_test() {
+    use crate::encoding::tag_based::bytes::ubae_directory_encoder::HASH_TAG_PREFIX;
+
+    let test_root = TestDir::new("ubae_directory_encoder_dedup");
+    let orig_dir = test_root.join("orig");
+    fs::create_dir_all(orig_dir.join("copies")).unwrap();
+    let body = vec![42u8; 50_000];
+    for i in 0..5 {
+        fs::write(orig_dir.join(format!("copies/copy{}.bin", i)), &body).unwrap();
+    }
+    fs::write(orig_dir.join("unique.txt"), b"unique").unwrap();
+    fs::write(orig_dir.join("empty_a"), b"").unwrap();
+    fs::write(orig_dir.join("empty_b"), b"").unwrap();
+
+    let plain = test_root.join("plain.ubae");
+    let deduplicated = test_root.join("deduplicated.ubae");
+    let deduplicated_parallel = test_root.join("deduplicated_parallel.ubae");
+    assert_eq!(0, ubae_directory_encoder::encode(orig_dir.to_str().unwrap(), plain.to_str().unwrap()));
+    assert_eq!(0, ubae_directory_encoder::encode_deduplicated(orig_dir.to_str().unwrap(), deduplicated.to_str().unwrap()));
+    let report = ubae_directory_encoder::encode_with_report(orig_dir.to_str().unwrap(), deduplicated_parallel.to_str().unwrap(), 3, true, None);
+    assert!(report.is_success());
+    assert_eq!(50_006, report.bytes_written);
+    assert!(fs::read(&deduplicated).unwrap() == fs::read(&deduplicated_parallel).unwrap());
+    assert!(fs::metadata(&deduplicated).unwrap().len() < fs::metadata(&plain).unwrap().len() - 3*50_000);
+
+    let mut ubae = Ubae::new(FileStorageSystem::create_leave_source_intact(deduplicated.to_str().unwrap()));
+    let hash_tags:Vec<String> = ubae.get_tags().unwrap().into_iter().filter(|tag| tag.starts_with(HASH_TAG_PREFIX)).collect();
+    assert_eq!(3, hash_tags.len()); //body, "unique", empty
+
+    let listed = ubae_directory_encoder::list(deduplicated.to_str().unwrap()).unwrap();
+    assert_eq!(9, listed.len());
+    assert!(listed.iter().filter(|entry| entry.path.starts_with("copies/")).all(|entry| entry.size == 50_000));
+
+    let out_dir = test_root.join("out");
+    assert_eq!(0, ubae_directory_encoder::decode_parallel(deduplicated.to_str().unwrap(), out_dir.to_str().unwrap(), 2));
+    for i in 0..5 {
+        assert_eq!(body, fs::read(out_dir.join(format!("copies/copy{}.bin", i))).unwrap());
+    }
+    assert_eq!(b"unique".to_vec(), fs::read(out_dir.join("unique.txt")).unwrap());
+    assert_eq!(0, fs::read(out_dir.join("empty_b")).unwrap().len());
+
+    //updating keeps deduplicating and drops contents nothing refers to anymore
+    fs::write(orig_dir.join("unique.txt"), b"changed").unwrap();
+    fs::write(orig_dir.join("copies/copy5.bin"), &body).unwrap();
+    let summary = ubae_directory_encoder::update(orig_dir.to_str().unwrap(), deduplicated.to_str().unwrap());
+    assert_eq!((1, 1, 0), (summary.added, summary.replaced, summary.error_count()));
+    let mut ubae = Ubae::new(FileStorageSystem::create_leave_source_intact(deduplicated.to_str().unwrap()));
+    let tags = ubae.get_tags().unwrap();
+    assert_eq!(3, tags.iter().filter(|tag| tag.starts_with(HASH_TAG_PREFIX)).count());
+    assert!(!tags.contains(&"copies/copy5.bin".to_string()));
+    let updated_out_dir = test_root.join("updated_out");
+    assert_eq!(0, ubae_directory_encoder::decode(deduplicated.to_str().unwrap(), updated_out_dir.to_str().unwrap()));
+    assert_eq!(b"changed".to_vec(), fs::read(updated_out_dir.join("unique.txt")).unwrap());
+    assert_eq!(body, fs::read(updated_out_dir.join("copies/copy5.bin")).unwrap());
+}
+
+#[test]
+fn directory_encoder_verify_test() {
+    let test_root = TestDir::new("ubae_directory_encoder_verify");
+    let orig_dir = test_root.join("orig");
+    let archive = test_root.join("archive.ubae");
+    fs::create_dir_all(orig_dir.join("sub")).unwrap();
+    fs::write(orig_dir.join("sub/file.txt"), b"some file content").unwrap();
+    assert_eq!(0, ubae_directory_encoder::encode(orig_dir.to_str().unwrap(), archive.to_str().unwrap()));
+    assert_eq!(Vec::<(String, String)>::new(), ubae_directory_encoder::verify(archive.to_str().unwrap()).unwrap());
+
+    let mut content = fs::read(&archive).unwrap();
+    let content_start = content.windows(4).position(|window| window == b"some").unwrap();
+    content[content_start] = b'S';
+    fs::write(&archive, &content).unwrap();
+    assert_eq!(vec![("sub/file.txt".to_string(), "content does not match its sha256".to_string())], ubae_directory_encoder::verify(archive.to_str().unwrap()).unwrap());
+
+    fs::write(&archive, &content[..content.len() - 3]).unwrap();
+    assert!(ubae_directory_encoder::verify(archive.to_str().unwrap()).is_err());
+}
+
+#[test]
+fn archive_fs_test() {
+    use std::io::{Seek, SeekFrom};
+    use crate::encoding::tag_based::bytes::ubae_archive_fs::ArchiveFs;
+
+    let test_root = TestDir::new("ubae_archive_fs");
+    let orig_dir = test_root.join("orig");
+    let archive = test_root.join("archive.ubae");
+    fs::create_dir_all(orig_dir.join("assets/textures")).unwrap();
+    fs::create_dir_all(orig_dir.join("empty")).unwrap();
+    fs::write(orig_dir.join("assets/config.txt"), b"key=value").unwrap();
+    let texture:Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(orig_dir.join("assets/textures/wall.bin"), &texture).unwrap();
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink("textures", orig_dir.join("assets/tex")).unwrap();
+        std::os::unix::fs::symlink("../../outside", orig_dir.join("assets/escaping")).unwrap();
+    }
+    assert_eq!(0, ubae_directory_encoder::encode(orig_dir.to_str().unwrap(), archive.to_str().unwrap()));
+
+    let archive_fs = ArchiveFs::new(&archive).unwrap();
+    let root:Vec<String> = archive_fs.read_dir("").unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+    assert_eq!(vec!["assets", "empty"], root);
+    let assets:Vec<(std::path::PathBuf, bool)> = archive_fs.read_dir("/assets").unwrap().map(|entry| {
+        let entry = entry.unwrap();
+        (entry.path(), entry.metadata().unwrap().is_dir())
+    }).collect();
+    assert!(assets.contains(&(Path::new("/assets").join("config.txt"), false)));
+    assert!(assets.contains(&(Path::new("/assets").join("textures"), true)));
+    assert_eq!(0, archive_fs.read_dir("empty").unwrap().count());
+
+    let metadata = archive_fs.metadata("assets/textures/wall.bin").unwrap();
+    assert!(metadata.is_file());
+    assert_eq!(10_000, metadata.len());
+    assert_eq!(fs::metadata(orig_dir.join("assets/textures/wall.bin")).unwrap().modified().unwrap(), metadata.modified().unwrap());
+    assert!(archive_fs.metadata("assets/missing").is_err());
+    assert!(archive_fs.metadata("assets/config.txt/below_a_file").is_err());
+    assert!(archive_fs.read_dir("assets/config.txt").is_err());
+    assert!(archive_fs.open("assets").is_err());
+
+    assert_eq!("key=value", archive_fs.read_to_string("./assets/../assets/config.txt").unwrap());
+    let mut file = archive_fs.open("assets/textures/wall.bin").unwrap();
+    let mut buf = [0u8; 10];
+    file.seek(SeekFrom::Start(5000)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&texture[5000..5010], &buf);
+    file.seek(SeekFrom::End(-4)).unwrap();
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).unwrap(); //does not read beyond the file into the rest of the archive
+    assert_eq!(&texture[9996..], &tail[..]);
+    file.seek(SeekFrom::Current(-10_000)).unwrap();
+    assert_eq!(texture, { let mut all = Vec::new(); file.read_to_end(&mut all).unwrap(); all });
+    assert!(file.seek(SeekFrom::Current(-10_001)).is_err());
+
+    #[cfg(unix)]
+    {
+        assert!(archive_fs.symlink_metadata("assets/tex").unwrap().is_symlink());
+        assert!(archive_fs.metadata("assets/tex").unwrap().is_dir());
+        assert_eq!(Path::new("textures"), archive_fs.read_link("assets/tex").unwrap());
+        assert_eq!(texture, archive_fs.read("assets/tex/wall.bin").unwrap());
+        assert!(archive_fs.symlink_metadata("assets/escaping").is_ok());
+        assert!(archive_fs.metadata("assets/escaping").is_err());
+    }
+}
+
+#[test]
+fn tar_round_trip_test() {
+    use crate::encoding::tag_based::bytes::ubae_directory_encoder::list;
+    use crate::encoding::tag_based::bytes::ubae_tar::{export_tar, import_tar};
+
+    let test_root = TestDir::new("ubae_tar_round_trip");
+    let orig_dir = test_root.join("orig");
+    let long_dir = "a_directory_name_that_is_long_enough/to_not_fit/into_the_hundred_bytes/of_a_plain_tar_header";
+    fs::create_dir_all(orig_dir.join(long_dir)).unwrap();
+    fs::create_dir_all(orig_dir.join("empty")).unwrap();
+    fs::write(orig_dir.join("plain.txt"), b"plain content").unwrap();
+    fs::write(orig_dir.join(long_dir).join("data.bin"), vec![7u8; 70_000]).unwrap();
+    fs::write(orig_dir.join("copy.bin"), vec![7u8; 70_000]).unwrap();
+    let old_mtime = std::time::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    File::options().write(true).open(orig_dir.join("plain.txt")).unwrap().set_modified(old_mtime).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(orig_dir.join("plain.txt"), fs::Permissions::from_mode(0o640)).unwrap();
+        std::os::unix::fs::symlink("plain.txt", orig_dir.join("link")).unwrap();
+    }
+
+    let encoded = test_root.join("encoded.ubae");
+    let imported = test_root.join("imported.ubae");
+    assert_eq!(0, ubae_directory_encoder::encode_deduplicated(orig_dir.to_str().unwrap(), encoded.to_str().unwrap()));
+    let (tar_stream, export_report) = export_tar(encoded.to_str().unwrap(), Vec::new()).unwrap();
+    assert!(export_report.is_success());
+    assert_eq!(3, export_report.files);
+    assert_eq!(13 + 2*70_000, export_report.bytes_written);
+
+    let import_report = import_tar(&tar_stream[..], imported.to_str().unwrap()).unwrap();
+    assert!(import_report.is_success());
+    assert_eq!((export_report.files, export_report.directories, export_report.symlinks), (import_report.files, import_report.directories, import_report.symlinks));
+    assert!(import_tar(&tar_stream[..], imported.to_str().unwrap()).is_err()); //exists already
+    assert!(ubae_directory_encoder::verify(imported.to_str().unwrap()).unwrap().is_empty());
+    let paths = |archive:&Path| list(archive.to_str().unwrap()).unwrap().into_iter().map(|entry| {
+        let metadata = entry.metadata.unwrap();
+        (entry.path, entry.kind, entry.size, metadata.mode, metadata.mtime_secs, metadata.symlink_target, metadata.sha256)
+    }).collect::<Vec<_>>();
+    assert_eq!(paths(&encoded), paths(&imported));
+
+    let out_dir = test_root.join("out");
+    assert_eq!(0, ubae_directory_encoder::decode(imported.to_str().unwrap(), out_dir.to_str().unwrap()));
+    assert_eq!(vec![7u8; 70_000], fs::read(out_dir.join(long_dir).join("data.bin")).unwrap());
+    assert_eq!(old_mtime, fs::metadata(out_dir.join("plain.txt")).unwrap().modified().unwrap());
+    assert!(out_dir.join("empty").is_dir());
+}
+
+#[test]
+fn tar_import_unusual_entries_test() {
+    use crate::encoding::tag_based::bytes::ubae_archive_fs::ArchiveFs;
+    use crate::encoding::tag_based::bytes::ubae_tar::import_tar;
+
+    let test_root = TestDir::new("ubae_tar_import");
+    fs::create_dir_all(&test_root).unwrap();
+    let imported = test_root.join("imported.ubae");
+
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut append = |entry_type:tar::EntryType, path:&[u8], content:&[u8]| {
+        let mut header = tar::Header::new_ustar();
+        header.as_mut_bytes()[..path.len()].copy_from_slice(path); //bypasses the path checks of the tar crate
+        header.set_entry_type(entry_type);
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, content).unwrap();
+    };
+    append(tar::EntryType::Directory, b"./", b"");
+    append(tar::EntryType::Regular, b"./dir/file.txt", b"first");
+    append(tar::EntryType::Regular, b"../escaping.txt", b"evil");
+    append(tar::EntryType::Link, b"hard_link", b"");
+    append(tar::EntryType::Fifo, b"fifo", b"");
+    append(tar::EntryType::Regular, b"dir//file.txt", b"second");
+    let tar_stream = builder.into_inner().unwrap();
+
+    let report = import_tar(&tar_stream[..], imported.to_str().unwrap()).unwrap();
+    let mut failed:Vec<&str> = report.failed.iter().map(|(path, _)| path.as_str()).collect();
+    failed.sort();
+    assert_eq!(vec!["../escaping.txt", "fifo", "hard_link"], failed);
+    assert_eq!(2, report.files);
+    assert!(ubae_directory_encoder::verify(imported.to_str().unwrap()).unwrap().is_empty());
+    let archive_fs = ArchiveFs::new(&imported).unwrap();
+    assert_eq!("second", archive_fs.read_to_string("dir/file.txt").unwrap());
+    assert_eq!(1, archive_fs.read_dir("").unwrap().count());
+
+    assert!(import_tar(&tar_stream[..700], test_root.join("truncated.ubae").to_str().unwrap()).is_err());
+}
+
+#[test]
+fn directory_encoder_malicious_archive_test() {
+    use crate::encoding::tag_based::bytes::ubae_directory_encoder::{EntryKind, EntryMetadata, METADATA_TAG_PREFIX, OverwritePolicy, PathValidation, normalize_archive_path};
+
+    assert_eq!(Ok("a/b".to_string()), normalize_archive_path("a/b"));
+    assert_eq!(Ok("etc/passwd".to_string()), normalize_archive_path("/etc/passwd"));
+    assert_eq!(Ok("x/y".to_string()), normalize_archive_path("C:\\x\\y"));
+    assert_eq!(Ok("b".to_string()), normalize_archive_path("./a/../b"));
+    assert!(normalize_archive_path("../x").is_err());
+    assert!(normalize_archive_path("a/../../x").is_err());
+    assert!(normalize_archive_path("a\\..\\..\\x").is_err());
+    assert!(normalize_archive_path("./.").is_err());
+
+    let test_root = TestDir::new("ubae_directory_encoder_malicious");
+    fs::create_dir_all(&test_root).unwrap();
+    let archive = test_root.join("malicious.ubae");
+    let symlink_metadata = |target:&str| EntryMetadata {
+        kind:EntryKind::Symlink, mode:0o777, mtime_secs:0, mtime_nanos:0, symlink_target:Some(target.to_string()), sha256:None
+    };
+    {
+        let mut ubae = Ubae::new(FileStorageSystem::create_leave_source_intact(archive.to_str().unwrap()));
+        ubae.add_entry_nocheck("fine.txt", b"fine").unwrap();
+        ubae.add_entry_nocheck("../../escaped.txt", b"evil").unwrap();
+        ubae.add_entry_nocheck("sub/../../escaped_too.txt", b"evil").unwrap();
+        ubae.add_entry_nocheck("..\\escaped_backslash.txt", b"evil").unwrap();
+        ubae.add_entry_nocheck(test_root.join("absolute.txt").to_str().unwrap(), b"absolute").unwrap();
+        ubae.add_entry_nocheck("./dot/./file.txt", b"dot").unwrap();
+        ubae.add_entry_nocheck("linked/file.txt", b"linked").unwrap();
+        ubae.add_entry_nocheck(&(METADATA_TAG_PREFIX.to_string() + "abs_link"), &symlink_metadata("/etc").encode()).unwrap();
+        ubae.add_entry_nocheck(&(METADATA_TAG_PREFIX.to_string() + "sub/up_link"), &symlink_metadata("../../outside").encode()).unwrap();
+        ubae.add_entry_nocheck(&(METADATA_TAG_PREFIX.to_string() + "sub/inside_link"), &symlink_metadata("../fine.txt").encode()).unwrap();
+    }
+    let archive_path = archive.to_str().unwrap();
+
+    //normalized: the three escaping paths are rejected, the absolute one becomes relative
+    let normalized = test_root.join("normalized");
+    assert_eq!(3, ubae_directory_encoder::extract(archive_path, normalized.to_str().unwrap(), None, OverwritePolicy::Overwrite, PathValidation::Normalize));
+    assert_eq!(b"fine".to_vec(), fs::read(normalized.join("fine.txt")).unwrap());
+    assert_eq!(b"dot".to_vec(), fs::read(normalized.join("dot/file.txt")).unwrap());
+    let absolute_inside = normalized.join(test_root.join("absolute.txt").to_str().unwrap().trim_start_matches('/'));
+    assert_eq!(b"absolute".to_vec(), fs::read(absolute_inside).unwrap());
+    assert!(!test_root.join("escaped.txt").exists());
+    assert!(!test_root.join("escaped_too.txt").exists());
+    assert!(!test_root.join("escaped_backslash.txt").exists());
+    assert!(!env::temp_dir().join("escaped.txt").exists());
+
+    //strict: everything that is not normalized and both symlinks pointing outside are rejected
+    let strict = test_root.join("strict");
+    assert_eq!(7, ubae_directory_encoder::extract(archive_path, strict.to_str().unwrap(), None, OverwritePolicy::Overwrite, PathValidation::Strict));
+    assert_eq!(b"fine".to_vec(), fs::read(strict.join("fine.txt")).unwrap());
+    assert!(!strict.join("dot").exists());
+    assert!(fs::symlink_metadata(strict.join("abs_link")).is_err());
+    assert!(fs::symlink_metadata(strict.join("sub/up_link")).is_err());
+    #[cfg(unix)]
+    {
+        assert_eq!(b"fine".to_vec(), fs::read(strict.join("sub/inside_link")).unwrap());
+
+        //nothing is written through a symlink that already exists in the target directory
+        let outside = test_root.join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        let existing = test_root.join("existing");
+        fs::create_dir_all(&existing).unwrap();
+        std::os::unix::fs::symlink(&outside, existing.join("linked")).unwrap();
+        //the 7 rejected paths are counted even if they are not selected
+        assert_eq!(8, ubae_directory_encoder::extract(archive_path, existing.to_str().unwrap(), Some(&["linked"]), OverwritePolicy::Overwrite, PathValidation::Strict));
+        assert!(!outside.join("file.txt").exists());
+        //neither while normalizing (the 3 escaping paths are rejected as well)
+        assert_eq!(4, ubae_directory_encoder::extract(archive_path, existing.to_str().unwrap(), Some(&["linked"]), OverwritePolicy::Overwrite, PathValidation::Normalize));
+        assert!(!outside.join("file.txt").exists());
+
+        //nor through a symlink an earlier extraction of an archive created
+        let earlier = test_root.join("earlier");
+        {
+            let mut ubae = Ubae::new(FileStorageSystem::create_leave_source_intact(test_root.join("link_first.ubae").to_str().unwrap()));
+            ubae.add_entry_nocheck(&(METADATA_TAG_PREFIX.to_string() + "linked"), &symlink_metadata(outside.to_str().unwrap()).encode()).unwrap();
+        }
+        assert_eq!(0, ubae_directory_encoder::extract(test_root.join("link_first.ubae").to_str().unwrap(), earlier.to_str().unwrap(), None, OverwritePolicy::Overwrite, PathValidation::Normalize));
+        assert!(fs::symlink_metadata(earlier.join("linked")).unwrap().file_type().is_symlink());
+        assert_eq!(4, ubae_directory_encoder::extract(archive_path, earlier.to_str().unwrap(), Some(&["linked"]), OverwritePolicy::Overwrite, PathValidation::Normalize));
+        assert!(!outside.join("file.txt").exists());
+
+        //nor through a symlink created by the same extraction, nothing below a symlink of the archive is extracted
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&outside, fs::Permissions::from_mode(0o755)).unwrap();
+        let same = test_root.join("same");
+        {
+            let mut ubae = Ubae::new(FileStorageSystem::create_leave_source_intact(test_root.join("link_and_below.ubae").to_str().unwrap()));
+            ubae.add_entry_nocheck(&(METADATA_TAG_PREFIX.to_string() + "d"), &symlink_metadata(outside.to_str().unwrap()).encode()).unwrap();
+            let directory_metadata = EntryMetadata { kind:EntryKind::Directory, mode:0o700, mtime_secs:0, mtime_nanos:0, symlink_target:None, sha256:None };
+            ubae.add_entry_nocheck(&(METADATA_TAG_PREFIX.to_string() + "d/x"), &directory_metadata.encode()).unwrap();
+            ubae.add_entry_nocheck("d/f.txt", b"below").unwrap();
+        }
+        let report = ubae_directory_encoder::extract_with_report(test_root.join("link_and_below.ubae").to_str().unwrap(), same.to_str().unwrap(), None, OverwritePolicy::Overwrite, PathValidation::Normalize, false, 0, None);
+        assert_eq!(2, report.failed.len());
+        assert!(report.failed.iter().all(|(path, _)| path == "d/x" || path == "d/f.txt"));
+        assert!(!outside.join("x").exists());
+        assert!(!outside.join("f.txt").exists());
+        assert_eq!(0o755, fs::metadata(&outside).unwrap().permissions().mode() & 0o777);
+    }
+}
+
+#[test]
+fn directory_encoder_parallel_test() {
+    let test_root = TestDir::new("ubae_directory_encoder_parallel");
+    let orig_dir = test_root.join("orig");
+    create_directory_encoder_test_tree(&orig_dir, 40, 20_000);
+    fs::write(orig_dir.join("large.bin"), vec![7u8; 5*1024*1024]).unwrap(); //streamed by the writer instead of read ahead
+    fs::create_dir_all(orig_dir.join("empty")).unwrap();
+
+    let sequential_archive = test_root.join("sequential.ubae");
+    let parallel_archive = test_root.join("parallel.ubae");
+    assert_eq!(0, ubae_directory_encoder::encode(orig_dir.to_str().unwrap(), sequential_archive.to_str().unwrap()));
+    assert_eq!(0, ubae_directory_encoder::encode_parallel(orig_dir.to_str().unwrap(), parallel_archive.to_str().unwrap(), 4));
+    assert!(fs::read(&sequential_archive).unwrap() == fs::read(&parallel_archive).unwrap());
+
+    let out_dir = test_root.join("out");
+    assert_eq!(0, ubae_directory_encoder::decode_parallel(parallel_archive.to_str().unwrap(), out_dir.to_str().unwrap(), 0));
+    for dir_index in 0..4 {
+        for file_index in 0..10 {
+            let file = format!("dir{}/file{}.bin", dir_index, file_index);
+            assert_eq!(fs::read(orig_dir.join(&file)).unwrap(), fs::read(out_dir.join(&file)).unwrap());
+        }
+    }
+    assert_eq!(vec![7u8; 5*1024*1024], fs::read(out_dir.join("large.bin")).unwrap());
+    assert!(out_dir.join("empty").is_dir());
+}
+
+//creates file_count files of (up to) file_size bytes, spread over up to 4 directories
+fn create_directory_encoder_test_tree(root:&Path, file_count:usize, file_size:usize) {
+    for file_index in 0..file_count {
+        let dir = root.join(format!("dir{}", file_index % 4));
+        fs::create_dir_all(&dir).unwrap();
+        let content:Vec<u8> = (0..file_size - file_index % 100).map(|i| (i * 31 + file_index) as u8).collect();
+        fs::write(dir.join(format!("file{}.bin", file_index / 4)), content).unwrap();
+    }
+}
+
+#[ignore]
+#[test]
+fn directory_encoder_parallel_benchmark() {
+    let test_root = TestDir::new("ubae_directory_encoder_benchmark");
+    let orig_dir = test_root.join("orig");
+    create_directory_encoder_test_tree(&orig_dir, 2000, 200_000);
+
+    let mut time_keeper = TimeKeeper::init();
+    ubae_directory_encoder::encode(orig_dir.to_str().unwrap(), test_root.join("sequential.ubae").to_str().unwrap());
+    time_keeper.println_set_mark("sequential encoding took");
+    ubae_directory_encoder::encode_parallel(orig_dir.to_str().unwrap(), test_root.join("parallel.ubae").to_str().unwrap(), 0);
+    time_keeper.println_set_mark("parallel encoding took");
+    ubae_directory_encoder::decode(test_root.join("sequential.ubae").to_str().unwrap(), test_root.join("sequential_out").to_str().unwrap());
+    time_keeper.println_set_mark("sequential decoding took");
+    ubae_directory_encoder::decode_parallel(test_root.join("parallel.ubae").to_str().unwrap(), test_root.join("parallel_out").to_str().unwrap(), 0);
+    time_keeper.println_set_mark("parallel decoding took");
+}
+
 #[ignore]
 #[test]
 fn directory_encoder_test() {
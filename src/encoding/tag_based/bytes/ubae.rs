@@ -5,6 +5,7 @@ use crate::transparent_storage::StorageSystem;
 use crate::transparent_storage::StorageSystemError;
 use crate::transparent_storage::Substream;
 
+use super::libae::LIEncoding;
 use super::libae::LIbae;
 use super::libae::LIbaeTraits;
 
@@ -39,6 +40,38 @@ impl<T:StorageSystem> Ubae<T> {
         }
     }
 
+    /// Creates a new ubae system using the given length indicator encoding. (see LIbae::new_with_encoding)
+    ///   new() keeps the encoding of existing content and uses the original encoding for empty content.
+    pub fn new_with_encoding(storagesystem:T, encoding:LIEncoding) -> Result<Ubae<T>, StorageSystemError> {
+        Ok(Ubae {
+            libae:LIbae::new_with_encoding(storagesystem, encoding)?
+        })
+    }
+
+    /// Read only access to the underlying storage system. (required for positional reads, see SharedUbae)
+    pub fn storage_system(&self) -> &T {
+        &self.libae.storage_system
+    }
+
+    pub fn encoding(&self) -> LIEncoding {
+        self.libae.encoding()
+    }
+
+    //appends tag and the entry read from stream, an entry that could not be read completely is removed again (a partial one would make the rest undecodable)
+    fn append_entry_from_stream(&mut self, tag:&str, stream:&mut dyn Read, stream_length:i64) -> Result<(), StorageSystemError> {
+        let size_before = self.libae.storage_system.content_size()?;
+        let mut counting = CountingRead { inner:stream, read:0 };
+        let appended = match self.libae.li_encode_single(tag.as_bytes()).and_then(|_| self.libae.li_encode_single_stream(&mut counting, stream_length)) {
+            Ok(()) if counting.read < stream_length.max(0) as u64 => Err(StorageSystemError::new("stream ended before stream_length was reached")),
+            appended => appended
+        };
+        if appended.is_err() {
+            let size_after = self.libae.storage_system.content_size()?;
+            self.libae.storage_system.delete(size_before, size_after)?;
+        }
+        appended
+    }
+
     /// Creates a new ubae system iterator with the provided storage system.
     pub fn new_tag_stream_iterator(storagesystem:T) -> UbaeStreamIter<T> {
         return UbaeStreamIter {
@@ -166,7 +199,7 @@ impl<T:StorageSystem> UbaeTraits<File> for Ubae<T> {
         self.libae.reset_read_pointer();
         let search_tag_as_bytes = tag.as_bytes(); //&str guarantees utf8
 
-        let mut last_read_pointer:i64 = 0;
+        let mut last_read_pointer:i64 = self.libae.manually_get_read_pointer(); //start of the first entry, behind a possible header
         while let Some(decoded_tag) = self.libae.li_decode_single() {
             if search_tag_as_bytes==&decoded_tag[..] {
                 let toreturn = self.libae.li_decode_single();
@@ -187,7 +220,7 @@ impl<T:StorageSystem> UbaeTraits<File> for Ubae<T> {
         self.libae.reset_read_pointer();
         let search_tag_as_bytes = tag.as_bytes(); //&str guarantees utf8
 
-        let mut last_read_pointer:i64 = 0;
+        let mut last_read_pointer:i64 = self.libae.manually_get_read_pointer(); //start of the first entry, behind a possible header
         while let Some(decoded_tag) = self.libae.li_decode_single() {
             if search_tag_as_bytes==&decoded_tag[..] {
                 self.libae.li_skip_single();
@@ -224,13 +257,11 @@ impl<T:StorageSystem> UbaeTraits<File> for Ubae<T> {
     }
 
     /// same as add_entry, but reads the entry from the provided stream.
-    ///   if stream is not of stream length behaviour is mostly undefined.
-    ///   Though the system will try not to break because of it.
+    ///   If stream ends before stream_length (or fails), nothing is added and Err is returned.
     fn add_entry_from_stream(&mut self, tag: &str, stream: &mut dyn Read, stream_length: i64) -> Result<(), StorageSystemError> {
         self.delete_entry_noreturn(tag)?;
 
-        self.libae.li_encode_single(tag.as_bytes())?;
-        self.libae.li_encode_single_stream(stream, stream_length)
+        self.append_entry_from_stream(tag, stream, stream_length)
     }
 
     /// Same as add_entry_from_stream,
@@ -240,7 +271,19 @@ impl<T:StorageSystem> UbaeTraits<File> for Ubae<T> {
     fn add_entry_from_stream_nocheck(&mut self, tag: &str, stream: &mut dyn Read, stream_length: i64) -> Result<(), StorageSystemError> {
 //        self.delete_entry_noreturn(tag);
 
-        self.libae.li_encode_single(tag.as_bytes())?;
-        self.libae.li_encode_single_stream(stream, stream_length)
+        self.append_entry_from_stream(tag, stream, stream_length)
+    }
+}
+
+//counts the bytes read from inner, storage systems may pad a stream that ends early (see StorageSystem::append_stream)
+struct CountingRead<'a> {
+    inner:&'a mut dyn Read,
+    read:u64
+}
+impl Read for CountingRead<'_> {
+    fn read(&mut self, buf:&mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        Ok(read)
     }
 }
\ No newline at end of file
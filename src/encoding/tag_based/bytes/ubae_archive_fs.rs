@@ -0,0 +1,323 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use super::ubae_directory_encoder::normalize_archive_path;
+use super::ubae_directory_encoder::read_archive_index_of;
+use super::ubae_directory_encoder::symlink_target_stays_inside;
+use super::ubae_directory_encoder::EntryKind;
+use super::ubae_directory_encoder::EntryMetadata;
+
+//same limit as linux
+const MAX_SYMLINK_HOPS:usize = 40;
+
+/// Read only view of an archive created by ubae_directory_encoder, without extracting it.
+///   Mirrors the parts of std::fs needed to browse and read files: read_dir, metadata, symlink_metadata, read_link, open, read.
+///
+/// Paths are relative to the root of the archive (a leading `/` is ignored), `.` and `..` are resolved.
+///   Symlinks in the archive are followed like a filesystem would, as long as their target stays within the archive.
+/// The index of the archive is read once when opening, later changes to the archive are not visible.
+///   Directories that only exist implicitly (archives written before metadata entries existed) are listed as well.
+pub struct ArchiveFs {
+    archive_file_path:PathBuf,
+    nodes:HashMap<String, Node>
+}
+
+//a path in the archive. The root is ""
+struct Node {
+    kind:EntryKind,
+    metadata:Option<EntryMetadata>,
+    content_range:Option<(i64, i64)>,
+    children:BTreeSet<String> //names, sorted so that read_dir is deterministic
+}
+
+impl ArchiveFs {
+    /// Reads the index of the archive, fails if it cannot be read.
+    pub fn new<P:AsRef<Path>>(archive_file_path:P) -> io::Result<ArchiveFs> {
+        let archive_file_path = archive_file_path.as_ref();
+        let path_str = archive_file_path.to_str().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "archive path is not valid unicode"))?;
+        let archived_paths = read_archive_index_of(path_str)?;
+
+        let mut nodes = HashMap::new();
+        nodes.insert(String::new(), Node { kind:EntryKind::Directory, metadata:None, content_range:None, children:BTreeSet::new() });
+        for (path, archived) in archived_paths {
+            let path = match normalize_archive_path(&path) {
+                Ok(path) => path,
+                Err(_) => continue //cannot be addressed anyways
+            };
+            let kind = match archived.metadata {
+                Some(ref metadata) => metadata.kind,
+                None => EntryKind::File
+            };
+            nodes.insert(path, Node { kind, metadata:archived.metadata, content_range:archived.content_range, children:BTreeSet::new() });
+        }
+
+        //register children, create implicit directories
+        let paths:Vec<String> = nodes.keys().filter(|path| !path.is_empty()).cloned().collect();
+        for path in paths {
+            let mut child = path.as_str();
+            while !child.is_empty() {
+                let (parent, name) = match child.rfind('/') {
+                    Some(separator) => (&child[..separator], &child[separator + 1..]),
+                    None => ("", child)
+                };
+                let parent_node = nodes.entry(parent.to_string()).or_insert_with(|| Node {
+                    kind:EntryKind::Directory, metadata:None, content_range:None, children:BTreeSet::new()
+                });
+                if !parent_node.children.insert(name.to_string()) {
+                    break //parent already known, so are its ancestors
+                }
+                child = parent;
+            }
+        }
+
+        Ok(ArchiveFs {
+            archive_file_path:archive_file_path.to_path_buf(),
+            nodes
+        })
+    }
+
+    /// Metadata of the path, symlinks are followed.
+    pub fn metadata<P:AsRef<Path>>(&self, path:P) -> io::Result<ArchiveMetadata> {
+        let resolved = self.resolve(path.as_ref(), true)?;
+        Ok(self.metadata_of(&resolved))
+    }
+    /// Metadata of the path itself, a symlink is not followed.
+    pub fn symlink_metadata<P:AsRef<Path>>(&self, path:P) -> io::Result<ArchiveMetadata> {
+        let resolved = self.resolve(path.as_ref(), false)?;
+        Ok(self.metadata_of(&resolved))
+    }
+    pub fn exists<P:AsRef<Path>>(&self, path:P) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    /// The target of a symlink, as it was stored.
+    pub fn read_link<P:AsRef<Path>>(&self, path:P) -> io::Result<PathBuf> {
+        let resolved = self.resolve(path.as_ref(), false)?;
+        match self.nodes[&resolved].metadata {
+            Some(EntryMetadata { kind:EntryKind::Symlink, symlink_target:Some(ref target), .. }) => Ok(PathBuf::from(target)),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "not a symlink"))
+        }
+    }
+
+    /// The entries of a directory, sorted by name. Entry paths are the given path joined with the name (like std::fs::read_dir).
+    pub fn read_dir<P:AsRef<Path>>(&self, path:P) -> io::Result<ArchiveReadDir> {
+        let resolved = self.resolve(path.as_ref(), true)?;
+        let node = &self.nodes[&resolved];
+        if node.kind != EntryKind::Directory {
+            return Err(io::Error::new(io::ErrorKind::NotADirectory, "not a directory"))
+        }
+        let entries:Vec<ArchiveDirEntry> = node.children.iter().map(|name| {
+            let child_path = if resolved.is_empty() { name.clone() } else { format!("{}/{}", resolved, name) };
+            ArchiveDirEntry {
+                path:path.as_ref().join(name),
+                name:name.clone(),
+                metadata:self.metadata_of(&child_path)
+            }
+        }).collect();
+        Ok(ArchiveReadDir { entries:entries.into_iter() })
+    }
+
+    /// Opens a file for reading, symlinks are followed.
+    pub fn open<P:AsRef<Path>>(&self, path:P) -> io::Result<ArchiveFile> {
+        let resolved = self.resolve(path.as_ref(), true)?;
+        let node = &self.nodes[&resolved];
+        match (node.kind, node.content_range) {
+            (EntryKind::File, Some((start, end))) => ArchiveFile::new(File::open(&self.archive_file_path)?, start as u64, end as u64),
+            (EntryKind::File, None) => Err(io::Error::new(io::ErrorKind::InvalidData, "content of the file is missing in the archive")),
+            _ => Err(io::Error::new(io::ErrorKind::IsADirectory, "is a directory"))
+        }
+    }
+    /// The entire content of a file.
+    pub fn read<P:AsRef<Path>>(&self, path:P) -> io::Result<Vec<u8>> {
+        let mut file = self.open(path)?;
+        let mut content = Vec::with_capacity(file.len() as usize);
+        file.read_to_end(&mut content)?;
+        Ok(content)
+    }
+    pub fn read_to_string<P:AsRef<Path>>(&self, path:P) -> io::Result<String> {
+        String::from_utf8(self.read(path)?).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))
+    }
+
+    fn metadata_of(&self, resolved:&str) -> ArchiveMetadata {
+        let node = &self.nodes[resolved];
+        ArchiveMetadata {
+            kind:node.kind,
+            len:node.content_range.map(|(start, end)| (end - start) as u64).unwrap_or(0),
+            metadata:node.metadata.clone()
+        }
+    }
+
+    //returns the archive path the given path refers to, following symlinks (the last component only if follow_last)
+    fn resolve(&self, path:&Path, follow_last:bool) -> io::Result<String> {
+        let path = path.to_str().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path is not valid unicode"))?;
+        let mut remaining:VecDeque<String> = path.split('/').map(|component| component.to_string()).collect();
+        let mut resolved:Vec<String> = Vec::new();
+        let mut symlink_hops = 0;
+        while let Some(component) = remaining.pop_front() {
+            match component.as_str() {
+                "" | "." => continue,
+                ".." => {
+                    resolved.pop(); //like the root directory of a filesystem, the root of the archive is its own parent
+                    continue
+                },
+                _ => {}
+            }
+            let candidate = if resolved.is_empty() { component.clone() } else { format!("{}/{}", resolved.join("/"), component) };
+            let node = self.nodes.get(&candidate).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found in the archive", candidate)))?;
+            if node.kind == EntryKind::Symlink && (follow_last || !remaining.is_empty()) {
+                symlink_hops+=1;
+                if symlink_hops > MAX_SYMLINK_HOPS {
+                    return Err(io::Error::other("too many levels of symbolic links"))
+                }
+                let target = node.metadata.as_ref().and_then(|metadata| metadata.symlink_target.as_deref()).unwrap_or("");
+                if !symlink_target_stays_inside(&candidate, target) {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} points outside of the archive", candidate)))
+                }
+                for target_component in target.split('/').rev() {
+                    remaining.push_front(target_component.to_string());
+                }
+            } else {
+                if node.kind != EntryKind::Directory && !remaining.iter().all(|rest| rest.is_empty() || rest == ".") {
+                    return Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", candidate)))
+                }
+                resolved.push(component);
+            }
+        }
+        Ok(resolved.join("/"))
+    }
+}
+
+/// Metadata of a path in an archive (see ArchiveFs::metadata)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveMetadata {
+    kind:EntryKind,
+    len:u64,
+    metadata:Option<EntryMetadata>
+}
+impl ArchiveMetadata {
+    pub fn kind(&self) -> EntryKind {
+        self.kind
+    }
+    pub fn is_file(&self) -> bool {
+        self.kind == EntryKind::File
+    }
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Directory
+    }
+    pub fn is_symlink(&self) -> bool {
+        self.kind == EntryKind::Symlink
+    }
+    /// content length of a file, 0 for anything else
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Err if the archive stores no metadata for the path (written by an older version, or an implicit directory)
+    pub fn modified(&self) -> io::Result<SystemTime> {
+        match self.metadata {
+            Some(ref metadata) => Ok(metadata.mtime()),
+            None => Err(io::Error::new(io::ErrorKind::Unsupported, "no modification time stored for this path"))
+        }
+    }
+    /// unix permission bits, if stored
+    pub fn mode(&self) -> Option<u32> {
+        self.metadata.as_ref().map(|metadata| metadata.mode)
+    }
+    /// the complete stored metadata, if any
+    pub fn entry_metadata(&self) -> Option<&EntryMetadata> {
+        self.metadata.as_ref()
+    }
+}
+
+/// Entry returned by ArchiveReadDir
+#[derive(Debug, Clone)]
+pub struct ArchiveDirEntry {
+    path:PathBuf,
+    name:String,
+    metadata:ArchiveMetadata
+}
+impl ArchiveDirEntry {
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+    pub fn file_name(&self) -> OsString {
+        OsString::from(&self.name)
+    }
+    /// like std::fs::DirEntry::metadata, does not follow a symlink
+    pub fn metadata(&self) -> io::Result<ArchiveMetadata> {
+        Ok(self.metadata.clone())
+    }
+    pub fn file_type(&self) -> io::Result<EntryKind> {
+        Ok(self.metadata.kind)
+    }
+}
+
+/// Iterator over the entries of a directory, like std::fs::ReadDir
+pub struct ArchiveReadDir {
+    entries:std::vec::IntoIter<ArchiveDirEntry>
+}
+impl Iterator for ArchiveReadDir {
+    type Item = io::Result<ArchiveDirEntry>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(Ok)
+    }
+}
+
+/// A file in an archive opened for reading. Reads and seeks only within the content of the file.
+///   Has its own handle to the archive file, so any number of them can be used at the same time.
+#[derive(Debug)]
+pub struct ArchiveFile {
+    archive_file:File,
+    start:u64,
+    len:u64,
+    pos:u64
+}
+impl ArchiveFile {
+    fn new(mut archive_file:File, start:u64, end:u64) -> io::Result<ArchiveFile> {
+        archive_file.seek(SeekFrom::Start(start))?;
+        Ok(ArchiveFile { archive_file, start, len:end - start, pos:0 })
+    }
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+impl Read for ArchiveFile {
+    fn read(&mut self, buf:&mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        let limit = remaining.min(buf.len() as u64) as usize;
+        if limit == 0 {
+            return Ok(0)
+        }
+        let read = self.archive_file.read(&mut buf[..limit])?;
+        self.pos+=read as u64;
+        Ok(read)
+    }
+}
+impl Seek for ArchiveFile {
+    fn seek(&mut self, pos:SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset)
+        };
+        let new_pos = new_pos.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
+        self.archive_file.seek(SeekFrom::Start(self.start + new_pos))?;
+        self.pos = new_pos;
+        Ok(new_pos)
+    }
+}
@@ -1,15 +1,324 @@
 extern crate core;
+extern crate ring;
 
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fmt;
 use std::fs;
 use std::fs::File;
+use std::fs::Metadata;
 use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
+use self::ring::digest;
+
+use crate::encoding::tag_based::bytes::libae::LIbae;
+use crate::encoding::tag_based::bytes::libae::LIbaeTraits;
 use crate::encoding::tag_based::bytes::ubae::Ubae;
 use crate::encoding::tag_based::bytes::ubae::UbaeTraits;
 use crate::transparent_storage::bytes::file_storage_system::FileStorageSystem;
+use crate::transparent_storage::bytes::vec_storage_system::VecStorageSystem;
 use crate::transparent_storage::StorageSystem;
+use crate::transparent_storage::StorageSystemError;
+
+/// Tags of metadata entries start with this prefix, followed by the inner path.
+///   No file path can contain a NUL byte, so metadata tags can never collide with the tag of a files content.
+pub const METADATA_TAG_PREFIX:&str = "\0m/";
+/// In deduplicating archives (see encode_deduplicated) each distinct file content is stored once, under this prefix followed by the lowercase hex sha256.
+///   Files have no content entry of their own, the sha256 in their metadata entry names the content.
+pub const HASH_TAG_PREFIX:&str = "\0h/";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink
+}
+
+/// Metadata stored for each path in the encoded directory.
+///   Encoded as libae chunks: kind, mode, mtime seconds, mtime nanoseconds, symlink target, sha256 of the content.
+///   Decoders ignore additional trailing chunks, so fields can be appended in the future.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryMetadata {
+    pub kind:EntryKind,
+    /// unix permission bits, including setuid, setgid and sticky. On other platforms only the write bits are meaningful (readonly flag).
+    pub mode:u32,
+    /// modification time relative to the unix epoch (may be negative)
+    pub mtime_secs:i64,
+    pub mtime_nanos:u32,
+    pub symlink_target:Option<String>,
+    /// sha256 of a files content, computed while encoding. None for anything but files and in older archives.
+    pub sha256:Option<Vec<u8>>
+}
+impl EntryMetadata {
+    pub fn from_fs(path:&Path, metadata:&Metadata) -> io::Result<EntryMetadata> {
+        let kind = if metadata.file_type().is_symlink() {
+            EntryKind::Symlink
+        } else if metadata.is_dir() {
+            EntryKind::Directory
+        } else {
+            EntryKind::File
+        };
+        let symlink_target = if kind == EntryKind::Symlink {
+            match fs::read_link(path)?.to_str() {
+                Some(target) => Some(target.to_string()),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, "symlink target is not valid unicode"))
+            }
+        } else {
+            None
+        };
+        let (mtime_secs, mtime_nanos) = match metadata.modified()?.duration_since(UNIX_EPOCH) {
+            Ok(after_epoch) => (after_epoch.as_secs() as i64, after_epoch.subsec_nanos()),
+            Err(before_epoch) => {
+                let before_epoch = before_epoch.duration();
+                if before_epoch.subsec_nanos() == 0 {
+                    (-(before_epoch.as_secs() as i64), 0)
+                } else {
+                    (-(before_epoch.as_secs() as i64) - 1, 1_000_000_000 - before_epoch.subsec_nanos())
+                }
+            }
+        };
+        Ok(EntryMetadata {
+            kind,
+            mode:get_mode(metadata),
+            mtime_secs,
+            mtime_nanos,
+            symlink_target,
+            sha256:None
+        })
+    }
+
+    pub fn mtime(&self) -> SystemTime {
+        if self.mtime_secs >= 0 {
+            UNIX_EPOCH + Duration::new(self.mtime_secs as u64, self.mtime_nanos)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(self.mtime_secs.unsigned_abs()) + Duration::from_nanos(self.mtime_nanos as u64)
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut libae = LIbae::new(VecStorageSystem::new_empty());
+        let kind = match self.kind {
+            EntryKind::File => b'f',
+            EntryKind::Directory => b'd',
+            EntryKind::Symlink => b'l',
+        };
+        //vec storage cannot fail
+        libae.li_encode_single(&[kind]).unwrap();
+        libae.li_encode_single(&self.mode.to_be_bytes()).unwrap();
+        libae.li_encode_single(&self.mtime_secs.to_be_bytes()).unwrap();
+        libae.li_encode_single(&self.mtime_nanos.to_be_bytes()).unwrap();
+        libae.li_encode_single(self.symlink_target.as_ref().map(|t| t.as_bytes()).unwrap_or(&[])).unwrap();
+        libae.li_encode_single(self.sha256.as_deref().unwrap_or(&[])).unwrap();
+        libae.get_content().unwrap()
+    }
+
+    pub fn decode(encoded:&[u8]) -> Option<EntryMetadata> {
+        let mut libae = LIbae::new(VecStorageSystem::new_empty());
+        libae.set_content(encoded).ok()?;
+        let kind = match &libae.li_try_decode_single().ok()??[..] {
+            b"f" => EntryKind::File,
+            b"d" => EntryKind::Directory,
+            b"l" => EntryKind::Symlink,
+            _ => return None
+        };
+        let mode = u32::from_be_bytes(libae.li_try_decode_single().ok()??.try_into().ok()?);
+        let mtime_secs = i64::from_be_bytes(libae.li_try_decode_single().ok()??.try_into().ok()?);
+        let mtime_nanos = u32::from_be_bytes(libae.li_try_decode_single().ok()??.try_into().ok()?);
+        let symlink_target = String::from_utf8(libae.li_try_decode_single().ok()??).ok()?;
+        let sha256 = match libae.li_try_decode_single().ok()? { //not written by older versions
+            Some(ref hash) if hash.len() == digest::SHA256_OUTPUT_LEN => Some(hash.clone()),
+            _ => None
+        };
+        Some(EntryMetadata {
+            kind,
+            mode,
+            mtime_secs,
+            mtime_nanos: if mtime_nanos < 1_000_000_000 { mtime_nanos } else { return None },
+            symlink_target: if kind == EntryKind::Symlink { Some(symlink_target) } else { None },
+            sha256
+        })
+    }
+}
+
+#[cfg(unix)]
+fn get_mode(metadata:&Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+#[cfg(not(unix))]
+fn get_mode(metadata:&Metadata) -> u32 {
+    if metadata.permissions().readonly() { 0o555 } else { 0o755 }
+}
+
+#[cfg(unix)]
+fn set_mode(path:&Path, mode:u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+#[cfg(not(unix))]
+fn set_mode(path:&Path, mode:u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+//mode bits restored unless the caller asks for the setuid, setgid and sticky bits as well
+const PERMISSION_MODE_BITS:u32 = 0o777;
+
+#[cfg(windows)]
+fn set_mtime(path:&Path, mtime:SystemTime) -> io::Result<()> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_WRITE_ATTRIBUTES:u32 = 0x0100; //all setting the mtime requires, so read only files work as well
+    const FILE_FLAG_BACKUP_SEMANTICS:u32 = 0x02000000; //required to open directories
+    fs::OpenOptions::new().access_mode(FILE_WRITE_ATTRIBUTES).custom_flags(FILE_FLAG_BACKUP_SEMANTICS).open(path)?.set_modified(mtime)
+}
+#[cfg(not(windows))]
+fn set_mtime(path:&Path, mtime:SystemTime) -> io::Result<()> {
+    File::open(path)?.set_modified(mtime) //does not require write access, works for directories
+}
+
+#[cfg(unix)]
+fn create_symlink(target:&str, link:&Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+#[cfg(windows)]
+fn create_symlink(target:&str, link:&Path) -> io::Result<()> {
+    if link.parent().map(|parent| parent.join(target).is_dir()).unwrap_or(false) {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+/// Computes the sha256 of everything read through it.
+pub(crate) struct Sha256Reader<R:Read> {
+    inner:R,
+    context:digest::Context
+}
+impl<R:Read> Sha256Reader<R> {
+    pub(crate) fn new(inner:R) -> Sha256Reader<R> {
+        Sha256Reader {
+            inner,
+            context:digest::Context::new(&digest::SHA256)
+        }
+    }
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.context.finish().as_ref().to_vec()
+    }
+}
+impl<R:Read> Read for Sha256Reader<R> {
+    fn read(&mut self, buf:&mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.context.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+fn sha256_of_file(path:&Path) -> io::Result<Vec<u8>> {
+    let mut hashing_reader = Sha256Reader::new(File::open(path)?);
+    io::copy(&mut hashing_reader, &mut io::sink())?;
+    Ok(hashing_reader.finish())
+}
+
+/// Outcome of encoding a directory or extracting an archive.
+#[derive(Debug, Default)]
+pub struct DirectoryReport {
+    /// paths that could not be (entirely) encoded or restored, with the reason. A path can fail more than once (e.g. content and metadata).
+    pub failed:Vec<(String, io::Error)>,
+    /// paths that were deliberately not written, because they already exist (see OverwritePolicy)
+    pub skipped:Vec<String>,
+    /// bytes of file content written (into the archive when encoding, into the target directory when extracting)
+    pub bytes_written:u64,
+    /// number of files, directories and symlinks successfully written
+    pub files:u64,
+    pub directories:u64,
+    pub symlinks:u64
+}
+impl DirectoryReport {
+    pub fn error_count(&self) -> u64 {
+        self.failed.len() as u64
+    }
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    pub(crate) fn fail<E:Into<io::Error>>(&mut self, path:&str, e:E) {
+        self.failed.push((path.to_string(), e.into()));
+    }
+    pub(crate) fn count(&mut self, kind:EntryKind) {
+        match kind {
+            EntryKind::File => self.files+=1,
+            EntryKind::Directory => self.directories+=1,
+            EntryKind::Symlink => self.symlinks+=1
+        }
+    }
+
+    //how the u64 returning functions report (they predate the report)
+    fn print_failures(&self) -> u64 {
+        for (path, e) in &self.failed {
+            println!("{} failed: {}", path, e);
+        }
+        self.error_count()
+    }
+}
+impl fmt::Display for DirectoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "files: {}, directories: {}, symlinks: {}, bytes written: {}, skipped: {}, errors: {}",
+               self.files, self.directories, self.symlinks, self.bytes_written, self.skipped.len(), self.failed.len())
+    }
+}
+
+/// Passed to the progress callback each time the content of a file was written.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress<'a> {
+    pub path:&'a str,
+    pub files_done:u64,
+    pub files_total:u64,
+    pub bytes_done:u64,
+    pub bytes_total:u64
+}
+
+struct ProgressTracker<'c> {
+    callback:Option<&'c mut dyn FnMut(&Progress)>,
+    files_done:u64,
+    files_total:u64,
+    bytes_done:u64,
+    bytes_total:u64
+}
+impl<'c> ProgressTracker<'c> {
+    fn new(callback:Option<&'c mut dyn FnMut(&Progress)>, files_total:u64, bytes_total:u64) -> ProgressTracker<'c> {
+        ProgressTracker { callback, files_done:0, files_total, bytes_done:0, bytes_total }
+    }
+    fn file_done(&mut self, path:&str, bytes:u64) {
+        self.files_done+=1;
+        self.bytes_done+=bytes;
+        if let Some(ref mut callback) = self.callback {
+            callback(&Progress {
+                path,
+                files_done:self.files_done,
+                files_total:self.files_total,
+                bytes_done:self.bytes_done,
+                bytes_total:self.bytes_total
+            });
+        }
+    }
+}
 
 ///About half the performance of a java program
 ///   SOO...... WTF??
@@ -19,10 +328,43 @@ use crate::transparent_storage::StorageSystem;
 ///
 ///Writes every file into the created target file
 ///
-///Please note that empty directories are not supported and will therefore silently disappear during encoding.
+///Additionally a metadata entry (see EntryMetadata) is written for every file, directory and symlink below the source directory.
+///   So empty directories, permissions, modification times and symlinks survive the round trip.
+///   Symlinks are not followed, they are stored as links.
 ///
+///Returns the number of paths that could not be encoded, see encode_with_report for details.
 ///
 pub fn encode(source_directory_path:&str, target_file_path:&str) -> u64 {
+    encode_with_report(source_directory_path, target_file_path, 1, false, None).print_failures()
+}
+
+///
+///Same as encode, but the source files are read (and hashed) by multiple threads. (0 threads uses the available parallelism)
+///   The archive is still written by a single thread and in the same order, so it is byte for byte identical to the one encode writes.
+///
+pub fn encode_parallel(source_directory_path:&str, target_file_path:&str, threads:usize) -> u64 {
+    encode_with_report(source_directory_path, target_file_path, threads, false, None).print_failures()
+}
+
+///
+///Same as encode, but files with identical content share a single copy of it (see HASH_TAG_PREFIX).
+///   Decoding, listing and updating work the same as for other archives.
+///
+pub fn encode_deduplicated(source_directory_path:&str, target_file_path:&str) -> u64 {
+    encode_with_report(source_directory_path, target_file_path, 1, true, None).print_failures()
+}
+
+//files up to this size are read into memory by the reader threads of a parallel encode, larger ones are streamed by the writer (after being hashed when deduplicating)
+const PARALLEL_READ_LIMIT:u64 = 4*1024*1024;
+//how many paths the reader threads of a parallel encode may be ahead of the writer (bounds the memory used)
+const PARALLEL_READ_AHEAD:usize = 32;
+
+///
+///Encodes like encode (with 1 thread) or encode_parallel, but returns what was written and what failed instead of printing failures.
+///   If deduplicate is set, identical file content is stored once (like encode_deduplicated).
+///   progress is called after each file was written.
+///
+pub fn encode_with_report(source_directory_path:&str, target_file_path:&str, threads:usize, deduplicate:bool, progress:Option<&mut dyn FnMut(&Progress)>) -> DirectoryReport {
     let source_directory = Path::new(source_directory_path);
     let target_file = Path::new(target_file_path);
     if !source_directory.exists() || !source_directory.is_dir() {
@@ -36,43 +378,589 @@ pub fn encode(source_directory_path:&str, target_file_path:&str) -> u64 {
     let mut ubae = Ubae::new(storage);
     ubae.set_content(&vec![0u8;0][..]).expect("setting content failed");
 
-    let errors = add_directory_to_system(&mut ubae, source_directory_path,
-                                         source_directory.to_owned());
+    let mut report = DirectoryReport::default();
+    let mut source_paths = Vec::new();
+    collect_directory(source_directory_path, source_directory, &mut source_paths, &mut report.failed);
+    let files = source_paths.iter().filter(|source_path| source_path.metadata.kind == EntryKind::File);
+    let mut progress = ProgressTracker::new(progress, files.clone().count() as u64, files.map(|source_path| source_path.len).sum());
+    let mut stored_hashes = if deduplicate { Some(HashSet::new()) } else { None };
+
+    let threads = thread_count(threads, source_paths.len());
+    if threads == 1 {
+        for source_path in &source_paths {
+            let result = add_source_path(&mut ubae, source_path, None, &mut stored_hashes);
+            record_encoded(&mut report, &mut progress, source_path, result);
+        }
+        return report
+    }
+
+    let next_path = AtomicUsize::new(0);
+    let written = (Mutex::new(0usize), Condvar::new());
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (next_path, written, source_paths) = (&next_path, &written, &source_paths);
+            scope.spawn(move || loop {
+                let index = next_path.fetch_add(1, Ordering::SeqCst);
+                if index >= source_paths.len() {
+                    break
+                }
+                {
+                    let (written_count, written_changed) = written;
+                    let mut written_count = written_count.lock().expect("obtaining lock failed");
+                    while index >= *written_count + PARALLEL_READ_AHEAD {
+                        written_count = written_changed.wait(written_count).expect("obtaining lock failed");
+                    }
+                }
+                if sender.send((index, read_for_encoding(&source_paths[index], deduplicate))).is_err() {
+                    break //writer is gone
+                }
+            });
+        }
+        drop(sender);
+
+        //writes in original order, whatever order the reads finish in
+        let mut finished_reads = HashMap::new();
+        for (index, source_path) in source_paths.iter().enumerate() {
+            let read = loop {
+                if let Some(read) = finished_reads.remove(&index) {
+                    break read
+                }
+                let (finished_index, read) = receiver.recv().expect("reader threads ended early");
+                finished_reads.insert(finished_index, read);
+            };
+            let result = match read {
+                Ok(prepared) => add_source_path(&mut ubae, source_path, prepared, &mut stored_hashes),
+                Err(e) => Err(StorageSystemError::from(e))
+            };
+            record_encoded(&mut report, &mut progress, source_path, result);
+
+            let (written_count, written_changed) = &written;
+            *written_count.lock().expect("obtaining lock failed") = index + 1;
+            written_changed.notify_all();
+        }
+    });
+
+    report
+}
+
+fn record_encoded(report:&mut DirectoryReport, progress:&mut ProgressTracker, source_path:&SourcePath, result:Result<u64, StorageSystemError>) {
+    match result {
+        Ok(bytes_written) => {
+            report.count(source_path.metadata.kind);
+            if source_path.metadata.kind == EntryKind::File {
+                report.bytes_written+=bytes_written;
+                progress.file_done(&source_path.internal_path, source_path.len);
+            }
+        },
+        Err(e) => report.fail(&source_path.internal_path, e)
+    }
+}
+
+/// A file read (or only hashed) ahead of being written into the archive.
+struct PreparedFile {
+    /// None if the file is too large to be held in memory
+    content:Option<Vec<u8>>,
+    sha256:Vec<u8>
+}
+
+//reads a file (and its sha256) into memory if it is small enough, only hashes larger ones when deduplicating (the hash is needed before writing).
+//  None if there is nothing to prepare
+fn read_for_encoding(source_path:&SourcePath, deduplicate:bool) -> io::Result<Option<PreparedFile>> {
+    if source_path.metadata.kind != EntryKind::File {
+        return Ok(None)
+    }
+    if source_path.len > PARALLEL_READ_LIMIT {
+        return Ok(if deduplicate {
+            Some(PreparedFile { content:None, sha256:sha256_of_file(&source_path.path)? })
+        } else {
+            None
+        })
+    }
+    let mut content = Vec::with_capacity(source_path.len as usize);
+    File::open(&source_path.path)?.take(source_path.len).read_to_end(&mut content)?;
+    content.resize(source_path.len as usize, 0); //like the streamed path, a file that shrunk in the meantime is padded
+    let sha256 = digest::digest(&digest::SHA256, &content).as_ref().to_vec();
+    Ok(Some(PreparedFile { content:Some(content), sha256 }))
+}
+
+fn thread_count(requested_threads:usize, jobs:usize) -> usize {
+    let threads = if requested_threads == 0 {
+        thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1)
+    } else {
+        requested_threads
+    };
+    threads.min(jobs).max(1)
+}
+
+//runs job for every element of jobs on up to threads threads, each thread has its own state created by init_state.
+//  on_done is called on the calling thread with the index and result of each job, in the order they finish
+fn run_in_parallel<J, S, I, F, D>(jobs:&[J], threads:usize, init_state:I, job:F, mut on_done:D)
+    where J:Sync, I:Fn() -> io::Result<S> + Sync, F:Fn(&mut S, &J) -> io::Result<()> + Sync, D:FnMut(usize, io::Result<()>) {
+    let run_job = |state:&mut io::Result<S>, index:usize| match state {
+        Ok(ref mut state) => job(state, &jobs[index]),
+        Err(ref e) => Err(io::Error::new(e.kind(), e.to_string()))
+    };
 
-    return errors;
+    let threads = thread_count(threads, jobs.len());
+    if threads == 1 {
+        let mut state = init_state();
+        for index in 0..jobs.len() {
+            on_done(index, run_job(&mut state, index));
+        }
+        return
+    }
+
+    let next_job = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (next_job, init_state, run_job) = (&next_job, &init_state, &run_job);
+            scope.spawn(move || {
+                let mut state = init_state();
+                loop {
+                    let index = next_job.fetch_add(1, Ordering::SeqCst);
+                    if index >= jobs.len() || sender.send((index, run_job(&mut state, index))).is_err() {
+                        break
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for (index, result) in receiver {
+            on_done(index, result);
+        }
+    });
+}
+
+/// A file, directory or symlink below the encoded directory.
+struct SourcePath {
+    path:PathBuf,
+    internal_path:String,
+    metadata:EntryMetadata,
+    len:u64
 }
 
-//allowing up to u64 errors is not necessary, but funny.
-fn add_directory_to_system<T:StorageSystem> (ubae: &mut Ubae<T>, original_directory_path:&str, directory:PathBuf) -> u64 {
+//collects all paths below directory(children before their parent directory), paths that could not be read are added to failed
+fn collect_directory(original_directory_path:&str, directory:&Path, collected:&mut Vec<SourcePath>, failed:&mut Vec<(String, io::Error)>) {
     if !directory.exists() || !directory.is_dir() {
         panic!("invalid directory supplied");
     }
 
-    let mut errors =0u64;
-    if let Ok(paths) = fs::read_dir(&directory) {
-        for path in paths {
-            if let Ok(subfilepath) = path {
-                let f = subfilepath.path();
-                if f.is_dir() {
-                    errors+=add_directory_to_system(ubae, original_directory_path, f);
-                } else {
-                    if let Ok(mut file) = File::open(&f) {
-                        if let Ok(metadata) = fs::metadata(&f) {
-                            let internal_path_opt = get_inner_path(original_directory_path, &f.to_str().expect("what?? how was this not valid unicode?"));
-                            if let Some(internal_path) = internal_path_opt {
-                                if let Ok(()) = ubae.add_entry_from_stream_nocheck(&internal_path, &mut file, metadata.len() as i64) {//added untested, should definitly work though
-                                    continue
-                                }
-                            }
+    let paths = match fs::read_dir(directory) {
+        Ok(paths) => paths,
+        Err(e) => return failed.push((directory.display().to_string(), e))
+    };
+    for path in paths {
+        let f = match path {
+            Ok(subfilepath) => subfilepath.path(),
+            Err(e) => {
+                failed.push((directory.display().to_string(), e));
+                continue
+            }
+        };
+        match read_source_path(original_directory_path, &f) {
+            Ok(source_path) => {
+                if source_path.metadata.kind == EntryKind::Directory {
+                    collect_directory(original_directory_path, &f, collected, failed);
+                }
+                collected.push(source_path);
+            },
+            Err(e) => failed.push((f.display().to_string(), e.into()))
+        }
+    }
+}
+fn read_source_path(original_directory_path:&str, f:&Path) -> Result<SourcePath, StorageSystemError> {
+    let metadata = fs::symlink_metadata(f)?; //does not follow symlinks
+    let internal_path = match f.to_str().and_then(|f_str| get_inner_path(original_directory_path, f_str)) {
+        Some(internal_path) => internal_path,
+        None => return Err(StorageSystemError::new("path not valid unicode or not within the encoded directory"))
+    };
+    Ok(SourcePath {
+        path:f.to_owned(),
+        internal_path,
+        metadata:EntryMetadata::from_fs(f, &metadata)?,
+        len:metadata.len()
+    })
+}
+
+//adds the content (for files) and the metadata entry, without checking whether they exist. Returns the number of content bytes written.
+//  prepared is used instead of reading the file, if present.
+//  stored_hashes are the hashes of the contents in a deduplicating archive (None if the archive does not deduplicate),
+//  content already stored is not written again.
+fn add_source_path<T:StorageSystem>(ubae:&mut Ubae<T>, source_path:&SourcePath, prepared:Option<PreparedFile>, stored_hashes:&mut Option<HashSet<String>>) -> Result<u64, StorageSystemError> {
+    if source_path.metadata.kind != EntryKind::File {
+        add_metadata_entry(ubae, &source_path.internal_path, &source_path.metadata)?;
+        return Ok(0)
+    }
+
+    let (content, sha256) = match prepared {
+        Some(prepared) => (prepared.content, Some(prepared.sha256)),
+        None => (None, None)
+    };
+    let (bytes_written, sha256) = match stored_hashes {
+        None => match (content, sha256) {
+            (Some(content), Some(sha256)) => {
+                ubae.add_entry_nocheck(&source_path.internal_path, &content)?;
+                (content.len() as u64, sha256)
+            },
+            _ => {
+                let mut hashing_reader = Sha256Reader::new(File::open(&source_path.path)?);
+                ubae.add_entry_from_stream_nocheck(&source_path.internal_path, &mut hashing_reader, source_path.len as i64)?;
+                (source_path.len, hashing_reader.finish())
+            }
+        },
+        Some(stored_hashes) => {
+            let sha256 = match sha256 {
+                Some(sha256) => sha256,
+                None => sha256_of_file(&source_path.path)?
+            };
+            let hash = to_hex(&sha256);
+            let bytes_written = if stored_hashes.contains(&hash) {
+                0
+            } else {
+                let hash_tag = HASH_TAG_PREFIX.to_string() + &hash;
+                match content {
+                    Some(content) => ubae.add_entry_nocheck(&hash_tag, &content)?,
+                    None => {
+                        let mut hashing_reader = Sha256Reader::new(File::open(&source_path.path)?);
+                        ubae.add_entry_from_stream_nocheck(&hash_tag, &mut hashing_reader, source_path.len as i64)?;
+                        if hashing_reader.finish() != sha256 { //the content would not match its tag
+                            ubae.delete_entry_noreturn(&hash_tag)?;
+                            return Err(StorageSystemError::new("file changed while it was encoded"))
                         }
                     }
-                    errors+=1;//jumped by continue
                 }
+                stored_hashes.insert(hash);
+                source_path.len
+            };
+            (bytes_written, sha256)
+        }
+    };
+    let mut metadata = source_path.metadata.clone();
+    metadata.sha256 = Some(sha256);
+    add_metadata_entry(ubae, &source_path.internal_path, &metadata)?;
+    Ok(bytes_written)
+}
+
+fn to_hex(bytes:&[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+pub(crate) fn add_metadata_entry<T:StorageSystem>(ubae:&mut Ubae<T>, internal_path:&str, metadata:&EntryMetadata) -> Result<(), StorageSystemError> {
+    ubae.add_entry_nocheck(&(METADATA_TAG_PREFIX.to_string() + internal_path), &metadata.encode())
+}
+pub(crate) fn delete_path<T:StorageSystem>(ubae:&mut Ubae<T>, internal_path:&str) -> Result<(), StorageSystemError> {
+    ubae.delete_entry_noreturn(internal_path)?;
+    ubae.delete_entry_noreturn(&(METADATA_TAG_PREFIX.to_string() + internal_path))?;
+    Ok(())
+}
+
+
+
+
+/// What update changed in the archive. Paths are files, directories and symlinks.
+#[derive(Debug, Default)]
+pub struct UpdateSummary {
+    pub added:u64,
+    /// content changed, or the kind of the path changed
+    pub replaced:u64,
+    /// content unchanged, but permissions, mtime or a symlink target changed
+    pub metadata_updated:u64,
+    pub deleted:u64,
+    pub unchanged:u64,
+    /// the content and metadata written for added and replaced paths, and the paths that could not be read or updated
+    pub report:DirectoryReport
+}
+impl UpdateSummary {
+    pub fn error_count(&self) -> u64 {
+        self.report.error_count()
+    }
+}
+impl fmt::Display for UpdateSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "added: {}, replaced: {}, metadata updated: {}, deleted: {}, unchanged: {}, errors: {}",
+               self.added, self.replaced, self.metadata_updated, self.deleted, self.unchanged, self.report.failed.len())
+    }
+}
+
+///
+///Updates an archive previously created by encode, so that it matches the current state of the source directory.
+///   Only what changed is added, replaced or deleted. A non existent archive is created.
+///
+///A file is considered unchanged if its size and mtime match the archive.
+///   If only the mtime differs, the content hash decides (archives without hashes have their files replaced).
+///Deduplicating archives stay deduplicating, contents no file refers to anymore are removed.
+///Everything that goes is deleted in a single pass over the archive, then the new entries are appended.
+///   A path that fails to be read keeps what the archive had for it.
+///
+pub fn update(source_directory_path:&str, archive_file_path:&str) -> UpdateSummary {
+    update_with_report(source_directory_path, archive_file_path, None)
+}
+
+///
+///Updates like update, progress is called after the content of each added or replaced file was written.
+///
+pub fn update_with_report(source_directory_path:&str, archive_file_path:&str, progress:Option<&mut dyn FnMut(&Progress)>) -> UpdateSummary {
+    let source_directory = Path::new(source_directory_path);
+    if !source_directory.exists() || !source_directory.is_dir() {
+        panic!("Provided directory file(arg0={}) is not a valid directory", source_directory_path);
+    }
+
+    let mut summary = UpdateSummary::default();
+    let (mut archived_paths, hashed_contents) = {
+        let mut libae = LIbae::new(FileStorageSystem::create_leave_source_intact_with_custom_buf_size(archive_file_path, 16384));
+        match read_archive_index_with_hashes(&mut libae) {
+            Ok(index) => index,
+            Err(e) => {
+                summary.report.fail(archive_file_path, e);
+                return summary
+            }
+        }
+    };
+    //an archive that contains hashed contents stays deduplicating
+    let deduplicating = !hashed_contents.is_empty();
+
+    let mut source_paths = Vec::new();
+    collect_directory(source_directory_path, source_directory, &mut source_paths, &mut summary.report.failed);
+
+    let mut deleted_tags = HashSet::new();
+    let mut referenced_hashes = HashSet::new();
+    let mut changes = Vec::new();
+    for mut source_path in source_paths {
+        let archived = archived_paths.remove(&source_path.internal_path);
+        match plan_path_change(&mut source_path, archived.as_ref(), deduplicating) {
+            Ok((change, prepared)) => {
+                if change == PathChange::Replace {
+                    deleted_tags.insert(source_path.internal_path.clone());
+                }
+                if change == PathChange::Replace || change == PathChange::UpdateMetadata {
+                    deleted_tags.insert(METADATA_TAG_PREFIX.to_string() + &source_path.internal_path);
+                }
+                if let Some(sha256) = prepared.as_ref().map(|prepared| &prepared.sha256).or(source_path.metadata.sha256.as_ref()) {
+                    referenced_hashes.insert(to_hex(sha256));
+                }
+                match change {
+                    PathChange::Unchanged => summary.unchanged+=1,
+                    change => changes.push((source_path, change, prepared))
+                }
+            },
+            Err(e) => {
+                if let Some(sha256) = archived.as_ref().and_then(|archived| archived.metadata.as_ref()).and_then(|metadata| metadata.sha256.as_ref()) {
+                    referenced_hashes.insert(to_hex(sha256));
+                }
+                summary.report.fail(&source_path.internal_path, e);
             }
         }
     }
-    return errors;
+
+    let deleted_paths = archived_paths.len() as u64; //no longer in the source directory
+    for internal_path in archived_paths.into_keys() {
+        deleted_tags.insert(METADATA_TAG_PREFIX.to_string() + &internal_path);
+        deleted_tags.insert(internal_path);
+    }
+    let mut stored_hashes = HashSet::new();
+    for hash in hashed_contents.into_keys() {
+        if referenced_hashes.contains(&hash) {
+            stored_hashes.insert(hash);
+        } else { //no file refers to the content anymore
+            deleted_tags.insert(HASH_TAG_PREFIX.to_string() + &hash);
+        }
+    }
+    let mut stored_hashes = if deduplicating { Some(stored_hashes) } else { None };
+
+    if let Err(e) = delete_tags(archive_file_path, &deleted_tags) {
+        summary.report.fail(archive_file_path, e);
+        return summary
+    }
+    summary.deleted = deleted_paths;
+
+    let written_files = changes.iter().filter(|(source_path, change, _)| *change != PathChange::UpdateMetadata && source_path.metadata.kind == EntryKind::File);
+    let mut progress = ProgressTracker::new(progress, written_files.clone().count() as u64, written_files.map(|(source_path, _, _)| source_path.len).sum());
+    let mut ubae = Ubae::new(FileStorageSystem::create_leave_source_intact_with_custom_buf_size(archive_file_path, 16384));
+    for (source_path, change, prepared) in changes {
+        if change == PathChange::UpdateMetadata {
+            match add_metadata_entry(&mut ubae, &source_path.internal_path, &source_path.metadata) {
+                Ok(_) => summary.metadata_updated+=1,
+                Err(e) => summary.report.fail(&source_path.internal_path, e)
+            }
+            continue
+        }
+        let result = add_source_path(&mut ubae, &source_path, prepared, &mut stored_hashes);
+        match (&result, change) {
+            (Ok(_), PathChange::Add) => summary.added+=1,
+            (Ok(_), _) => summary.replaced+=1,
+            (Err(_), _) => {}
+        }
+        record_encoded(&mut summary.report, &mut progress, &source_path, result);
+    }
+
+    summary
+}
+
+//deletes the entries of all of the tags in a single pass over the archive (deleting them one by one moves the rest of the archive once per tag)
+fn delete_tags(archive_file_path:&str, tags:&HashSet<String>) -> Result<(), StorageSystemError> {
+    if tags.is_empty() {
+        return Ok(())
+    }
+    let mut libae = LIbae::new(FileStorageSystem::create_leave_source_intact_with_custom_buf_size(archive_file_path, 16384));
+    let mut deleted_ranges:Vec<(u64, u64)> = Vec::new();
+    let mut entry_start = libae.manually_get_read_pointer() as u64;
+    while let Some(tag) = libae.li_try_decode_single()? {
+        libae.li_try_skip_single()?.ok_or_else(|| StorageSystemError::new("truncated archive: tag without entry"))?;
+        let entry_end = libae.manually_get_read_pointer() as u64;
+        if std::str::from_utf8(&tag).map(|tag| tags.contains(tag)).unwrap_or(false) {
+            match deleted_ranges.last_mut() {
+                Some((_, deleted_end)) if *deleted_end == entry_start => *deleted_end = entry_end,
+                _ => deleted_ranges.push((entry_start, entry_end))
+            }
+        }
+        entry_start = entry_end;
+    }
+    drop(libae);
+    remove_ranges(&mut fs::OpenOptions::new().read(true).write(true).open(archive_file_path)?, &deleted_ranges)?;
+    Ok(())
+}
+
+//removes the sorted, non overlapping ranges from the file. Everything behind the first range is moved to the front once.
+fn remove_ranges(file:&mut File, ranges:&[(u64, u64)]) -> io::Result<()> {
+    let mut write_position = match ranges.first() {
+        Some((start, _)) => *start,
+        None => return Ok(())
+    };
+    let file_length = file.metadata()?.len();
+    let mut buf = vec![0u8; 16384];
+    for (index, (_, end)) in ranges.iter().enumerate() {
+        let kept_end = ranges.get(index + 1).map(|(next_start, _)| *next_start).unwrap_or(file_length);
+        let mut read_position = *end;
+        while read_position < kept_end {
+            let length = buf.len().min((kept_end - read_position) as usize);
+            file.seek(SeekFrom::Start(read_position))?;
+            file.read_exact(&mut buf[..length])?;
+            file.seek(SeekFrom::Start(write_position))?;
+            file.write_all(&buf[..length])?;
+            read_position+=length as u64;
+            write_position+=length as u64;
+        }
+    }
+    file.set_len(write_position)
+}
+
+/// What the archive knows about a path
+#[derive(Default)]
+pub(crate) struct ArchivedPath {
+    pub(crate) metadata:Option<EntryMetadata>,
+    pub(crate) metadata_corrupt:bool,
+    /// start(incl) and end(excl) of the content within the archive
+    pub(crate) content_range:Option<(i64, i64)>
+}
+impl ArchivedPath {
+    pub(crate) fn content_length(&self) -> Option<i64> {
+        self.content_range.map(|(start, end)| end - start)
+    }
+}
+
+pub(crate) fn read_archive_index_of(archive_file_path:&str) -> Result<HashMap<String, ArchivedPath>, StorageSystemError> {
+    if !Path::new(archive_file_path).is_file() {
+        return Err(StorageSystemError::new(&format!("{} is not a file", archive_file_path)));
+    }
+    read_archive_index(&mut LIbae::new(FileStorageSystem::create_leave_source_intact_with_custom_buf_size(archive_file_path, 16384)))
+}
+fn read_archive_index<T:StorageSystem>(libae:&mut LIbae<T>) -> Result<HashMap<String, ArchivedPath>, StorageSystemError> {
+    Ok(read_archive_index_with_hashes(libae)?.0)
+}
+/// content ranges of a deduplicating archive by hex sha256
+type HashedContents = HashMap<String, (i64, i64)>;
+//also returns the hashed contents of a deduplicating archive. The content ranges of its files are resolved through their hashes.
+fn read_archive_index_with_hashes<T:StorageSystem>(libae:&mut LIbae<T>) -> Result<(HashMap<String, ArchivedPath>, HashedContents), StorageSystemError> {
+    libae.reset_read_pointer();
+    let mut archived_paths:HashMap<String, ArchivedPath> = HashMap::new();
+    let mut hashed_contents:HashedContents = HashMap::new();
+    while let Some(tag) = libae.li_try_decode_single()? {
+        let tag = String::from_utf8(tag).map_err(|_| StorageSystemError::new("corrupt archive: tag is not valid utf8"))?;
+        if let Some(internal_path) = tag.strip_prefix(METADATA_TAG_PREFIX) {
+            let encoded = libae.li_try_decode_single()?.ok_or_else(|| StorageSystemError::new("truncated archive: tag without entry"))?;
+            let archived_path = archived_paths.entry(internal_path.to_string()).or_default();
+            archived_path.metadata = EntryMetadata::decode(&encoded);
+            archived_path.metadata_corrupt = archived_path.metadata.is_none();
+        } else {
+            let content_length = libae.li_try_skip_single()?.ok_or_else(|| StorageSystemError::new("truncated archive: tag without entry"))?;
+            let content_end = libae.manually_get_read_pointer();
+            let content_range = (content_end - content_length, content_end);
+            match tag.strip_prefix(HASH_TAG_PREFIX) {
+                Some(hash) => { hashed_contents.insert(hash.to_string(), content_range); },
+                None => archived_paths.entry(tag).or_default().content_range = Some(content_range)
+            }
+        }
+    }
+    if !hashed_contents.is_empty() {
+        for archived_path in archived_paths.values_mut() {
+            if let (None, Some(EntryMetadata { kind:EntryKind::File, sha256:Some(ref sha256), .. })) = (archived_path.content_range, &archived_path.metadata) {
+                archived_path.content_range = hashed_contents.get(&to_hex(sha256)).cloned();
+            }
+        }
+    }
+    Ok((archived_paths, hashed_contents))
+}
+
+/// How update brings an archived path up to date with the source directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PathChange {
+    Add,
+    /// content or kind changed, or the archived metadata is unknown
+    Replace,
+    UpdateMetadata,
+    Unchanged
+}
+
+//decides how the archived path has to change, the archive is not touched. The sha256 of unchanged content is taken over into the metadata of source_path.
+//  files to be written into a deduplicating archive are hashed right away (see prepare_for_update)
+fn plan_path_change(source_path:&mut SourcePath, archived:Option<&ArchivedPath>, deduplicating:bool) -> io::Result<(PathChange, Option<PreparedFile>)> {
+    let archived = match archived {
+        None => return Ok((PathChange::Add, prepare_for_update(source_path, deduplicating, None)?)),
+        Some(archived) => archived
+    };
+
+    let old_metadata = match archived.metadata {
+        Some(ref old_metadata) if old_metadata.kind == source_path.metadata.kind => old_metadata,
+        _ => return Ok((PathChange::Replace, prepare_for_update(source_path, deduplicating, None)?)) //kind changed or unknown
+    };
+
+    if source_path.metadata.kind == EntryKind::File {
+        let same_size = archived.content_length() == Some(source_path.len as i64);
+        let same_mtime = (old_metadata.mtime_secs, old_metadata.mtime_nanos) == (source_path.metadata.mtime_secs, source_path.metadata.mtime_nanos);
+        let sha256 = if same_size && !same_mtime && old_metadata.sha256.is_some() {
+            Some(sha256_of_file(&source_path.path)?)
+        } else {
+            None
+        };
+        let content_unchanged = same_size && (same_mtime || (sha256.is_some() && sha256 == old_metadata.sha256));
+        if !content_unchanged {
+            return Ok((PathChange::Replace, prepare_for_update(source_path, deduplicating, sha256)?))
+        }
+        source_path.metadata.sha256 = old_metadata.sha256.clone();
+    }
+
+    if *old_metadata == source_path.metadata {
+        Ok((PathChange::Unchanged, None))
+    } else {
+        Ok((PathChange::UpdateMetadata, None))
+    }
 }
+
+//the sha256 of a file to be written into a deduplicating archive (computed unless already known), so it is known which contents stay referenced before anything is deleted
+fn prepare_for_update(source_path:&SourcePath, deduplicating:bool, sha256:Option<Vec<u8>>) -> io::Result<Option<PreparedFile>> {
+    if !deduplicating || source_path.metadata.kind != EntryKind::File {
+        return Ok(None)
+    }
+    let sha256 = match sha256 {
+        Some(sha256) => sha256,
+        None => sha256_of_file(&source_path.path)?
+    };
+    Ok(Some(PreparedFile { content:None, sha256 }))
+}
+
 fn get_inner_path(dir_root_path:&str, path:&str) -> Option<String> {
     if (&path).contains(&dir_root_path) {
         let sub = &path.replace("\\", "/")[dir_root_path.len()..];
@@ -92,7 +980,8 @@ fn get_inner_path(dir_root_path:&str, path:&str) -> Option<String> {
 ///
 ///Decodes the content of the file into the target directory.
 ///If it was previously encoded using this software, then the directory should be identical to the one that it was encoded from.
-///    Except for the missing empty directories.
+///    Including empty directories, symlinks, permissions and modification times (if they were encoded, older files have no metadata entries).
+///Archived paths are normalized, paths that would end up outside of the target directory are not decoded (see PathValidation).
 ///
 pub fn decode(source_file_path:&str, target_directory_path:&str) -> u64 {
     let source_file = Path::new(source_file_path);
@@ -104,20 +993,459 @@ pub fn decode(source_file_path:&str, target_directory_path:&str) -> u64 {
         panic!("Provided target_directory(arg1={}) already exists", target_directory_path);
     }
 
-    let storage = FileStorageSystem::create_leave_source_intact_with_custom_buf_size(source_file_path, 16384);
-    let ubae_iter = Ubae::new_tag_stream_iterator(storage);
+    extract(source_file_path, target_directory_path, None, OverwritePolicy::Overwrite, PathValidation::Normalize)
+}
 
-    let mut errors = 0u64;//yes we do need a u64 for this. (honestly we do not actually, but I think it's funny)
-    for (tag, mut stream) in ubae_iter {
-        let target_file = target_directory.join(Path::new(&tag));
-        fs::create_dir_all(target_file.parent().expect("parent file could not be found or something like that i don't even anymore.")).expect("create parent dir failed");
-        if let Ok(mut target_stream) = File::create(target_file) {
-            io::copy(&mut stream.0, &mut target_stream).expect("copy failed");
-        } else {
-            println!("{} failed to be restored", tag);
-            errors+=1;
+
+
+/// A path stored in an archive, as returned by list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveListEntry {
+    pub path:String,
+    pub kind:EntryKind,
+    /// content size in bytes, 0 for directories and symlinks
+    pub size:u64,
+    /// None for archives created before metadata was stored
+    pub metadata:Option<EntryMetadata>
+}
+
+/// Lists every path in the archive, sorted by path.
+pub fn list(archive_file_path:&str) -> Result<Vec<ArchiveListEntry>, StorageSystemError> {
+    let mut entries:Vec<ArchiveListEntry> = read_archive_index_of(archive_file_path)?.into_iter().map(|(path, archived)| {
+        ArchiveListEntry {
+            path,
+            kind:archived.metadata.as_ref().map(|metadata| metadata.kind).unwrap_or(EntryKind::File),
+            size:archived.content_length().unwrap_or(0) as u64,
+            metadata:archived.metadata
+        }
+    }).collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+///
+///Checks an archive created by encode: every file has content, all metadata is decodable and every content matches the sha256 in its metadata.
+///   Returns the problems found as (path, description), sorted by path. Err if the archive itself cannot be read.
+///
+pub fn verify(archive_file_path:&str) -> Result<Vec<(String, String)>, StorageSystemError> {
+    let archived_paths = read_archive_index_of(archive_file_path)?;
+    let mut archive_file = File::open(archive_file_path)?;
+    let mut problems = Vec::new();
+    for (path, archived) in archived_paths {
+        if archived.metadata_corrupt {
+            problems.push((path.clone(), "metadata could not be decoded".to_string()));
+        }
+        let metadata = match archived.metadata {
+            Some(metadata) => metadata,
+            None => continue //written by an older version, nothing to check against
+        };
+        match (metadata.kind, archived.content_range) {
+            (EntryKind::File, None) => problems.push((path, "content is missing".to_string())),
+            (EntryKind::File, Some((start, end))) => {
+                if let Some(sha256) = metadata.sha256 {
+                    archive_file.seek(SeekFrom::Start(start as u64))?;
+                    let mut hashing_reader = Sha256Reader::new((&mut archive_file).take((end - start) as u64));
+                    io::copy(&mut hashing_reader, &mut io::sink())?;
+                    if hashing_reader.finish() != sha256 {
+                        problems.push((path, "content does not match its sha256".to_string()));
+                    }
+                }
+            },
+            (_, Some(_)) => problems.push((path, format!("{:?} has content", metadata.kind))),
+            (_, None) => {}
+        }
+    }
+    problems.sort();
+    Ok(problems)
+}
+
+/// What to do if a path to be extracted already exists in the target directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverwritePolicy {
+    /// keep the existing path
+    Skip,
+    /// replace the existing path (existing directories are kept, but get the metadata from the archive)
+    Overwrite,
+    /// replace the existing path if the archived mtime is newer. Archived paths without metadata are never newer.
+    OverwriteIfNewer
+}
+
+/// Whether a path matches a glob pattern.
+///   `*` and `?` match any characters(any single character) except `/`, `**` matches anything including `/`.
+///   So `sub/*.txt` matches `sub/a.txt`, but not `sub/deeper/a.txt` - `sub/**.txt` and `**/a.txt` match both.
+pub fn path_matches(pattern:&str, path:&str) -> bool {
+    glob_matches(pattern.as_bytes(), path.as_bytes())
+}
+fn glob_matches(pattern:&[u8], path:&[u8]) -> bool {
+    if pattern.starts_with(b"**/") && glob_matches(&pattern[3..], path) { //**/ may also match nothing
+        return true
+    }
+    if pattern.starts_with(b"**") {
+        return glob_matches(&pattern[2..], path) || (!path.is_empty() && glob_matches(pattern, &path[1..]))
+    }
+    match (pattern.first(), path.first()) {
+        (None, None) => true,
+        (None, Some(_)) => false,
+        (Some(b'*'), _) => glob_matches(&pattern[1..], path) || (!path.is_empty() && path[0] != b'/' && glob_matches(pattern, &path[1..])),
+        (Some(_), None) => false,
+        (Some(b'?'), Some(c)) => *c != b'/' && glob_matches(&pattern[1..], &path[1..]),
+        (Some(p), Some(c)) => p == c && glob_matches(&pattern[1..], &path[1..]),
+    }
+}
+//a path is selected if it or any of its parent directories matches one of the patterns
+fn is_selected(patterns:Option<&[&str]>, internal_path:&str) -> bool {
+    match patterns {
+        None => true,
+        Some(patterns) => {
+            let mut candidate = internal_path;
+            loop {
+                if patterns.iter().any(|pattern| path_matches(pattern, candidate)) {
+                    return true
+                }
+                match candidate.rfind('/') {
+                    Some(parent_end) => candidate = &candidate[..parent_end],
+                    None => return false
+                }
+            }
         }
     }
+}
+
+///
+///Extracts all paths matching any of the patterns(see path_matches) into the target directory.
+///   A pattern matching a directory selects everything in it. The target directory may already exist, existing paths are kept.
+///
+pub fn extract_paths(archive_file_path:&str, patterns:&[&str], target_directory_path:&str) -> u64 {
+    extract(archive_file_path, target_directory_path, Some(patterns), OverwritePolicy::Skip, PathValidation::Normalize)
+}
 
-    return errors;
-}
\ No newline at end of file
+///
+///Extracts the entire archive into a possibly existing target directory. Existing paths are handled according to the policy.
+///
+pub fn extract_into_existing(archive_file_path:&str, target_directory_path:&str, overwrite_policy:OverwritePolicy) -> u64 {
+    extract(archive_file_path, target_directory_path, None, overwrite_policy, PathValidation::Normalize)
+}
+
+///
+///Extracts the selected paths (all if patterns is None) into the target directory, which is created if it does not exist.
+///   Returns the number of paths that could not be restored (including the ones rejected by the path validation).
+///   Patterns are matched against the normalized paths.
+///
+pub fn extract(archive_file_path:&str, target_directory_path:&str, patterns:Option<&[&str]>, overwrite_policy:OverwritePolicy, path_validation:PathValidation) -> u64 {
+    extract_with_report(archive_file_path, target_directory_path, patterns, overwrite_policy, path_validation, false, 1, None).print_failures()
+}
+
+///
+///Same as decode, but files are written by multiple threads. (0 threads uses the available parallelism)
+///
+pub fn decode_parallel(source_file_path:&str, target_directory_path:&str, threads:usize) -> u64 {
+    let source_file = Path::new(source_file_path);
+    let target_directory = Path::new(target_directory_path);
+    if !source_file.exists() || source_file.is_dir() {
+        panic!("Provided directory source_file(arg0={}) is not a valid source file", source_file_path);
+    }
+    if target_directory.exists() {
+        panic!("Provided target_directory(arg1={}) already exists", target_directory_path);
+    }
+
+    extract_with_report(source_file_path, target_directory_path, None, OverwritePolicy::Overwrite, PathValidation::Normalize, false, threads, None).print_failures()
+}
+
+///
+///Extracts like extract, but with multiple threads (0 uses the available parallelism) and returns what was written and what failed instead of printing failures.
+///   progress is called after the content of each file was written.
+///   The setuid, setgid and sticky bits are only restored if special_mode_bits is set (extract never restores them), so extracting does not create setuid programs by surprise.
+///
+#[allow(clippy::too_many_arguments)]
+pub fn extract_with_report(archive_file_path:&str, target_directory_path:&str, patterns:Option<&[&str]>, overwrite_policy:OverwritePolicy, path_validation:PathValidation,
+                           special_mode_bits:bool, threads:usize, progress:Option<&mut dyn FnMut(&Progress)>) -> DirectoryReport {
+    let target_directory = Path::new(target_directory_path);
+    let mut report = DirectoryReport::default();
+    let archived_paths = match read_archive_index_of(archive_file_path) {
+        Ok(archived_paths) => validate_archived_paths(archived_paths, path_validation, &mut report.failed),
+        Err(e) => {
+            report.fail(archive_file_path, e);
+            return report
+        }
+    };
+    if let Err(e) = fs::create_dir_all(target_directory) { //exists even if the encoded directory was empty
+        report.fail(target_directory_path, e);
+        return report
+    }
+
+    let mut metadata_entries = Vec::new();
+
+    let mut content_jobs = Vec::new();
+    for (internal_path, archived) in archived_paths.iter() {
+        let content_range = match archived.content_range {
+            Some(content_range) if is_selected(patterns, internal_path) => content_range,
+            _ => continue
+        };
+        let target_file = target_directory.join(Path::new(internal_path));
+        match check_target(target_directory, internal_path).and_then(|_| prepare_target(&target_file, archived.metadata.as_ref(), overwrite_policy)) {
+            Ok(true) => content_jobs.push((internal_path.as_str(), target_file, content_range, archived.metadata.clone())),
+            Ok(false) => report.skipped.push(internal_path.clone()),
+            Err(e) => report.fail(internal_path, e)
+        }
+    }
+    content_jobs.sort_by_key(|job| (job.2).0); //in archive order, reads sequentially through the archive
+
+    let mut progress = ProgressTracker::new(progress, content_jobs.len() as u64, content_jobs.iter().map(|(_, _, (start, end), _)| (end - start) as u64).sum());
+    let mut restored_content = vec![false; content_jobs.len()];
+    run_in_parallel(&content_jobs, threads, || File::open(archive_file_path), |archive_file, (_, target_file, (start, end), _)| {
+        fs::create_dir_all(target_file.parent().expect("parent file could not be found or something like that i don't even anymore."))?;
+        archive_file.seek(SeekFrom::Start(*start as u64))?;
+        let copied = io::copy(&mut archive_file.take((end - start) as u64), &mut File::create(target_file)?)?;
+        if copied < (end - start) as u64 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "archive ended within the content"));
+        }
+        Ok(())
+    }, |job_index, result| {
+        let (internal_path, _, (start, end), _) = &content_jobs[job_index];
+        match result {
+            Ok(_) => {
+                restored_content[job_index] = true;
+                report.bytes_written+=(end - start) as u64;
+                progress.file_done(internal_path, (end - start) as u64);
+            },
+            Err(e) => report.fail(internal_path, e)
+        }
+    });
+    for (restored, (internal_path, _, _, archived_metadata)) in restored_content.into_iter().zip(content_jobs) {
+        if !restored {
+            continue
+        }
+        match archived_metadata {
+            Some(archived_metadata) => metadata_entries.push((internal_path, archived_metadata)),
+            None => report.count(EntryKind::File) //written by an older version, there is no metadata to restore
+        }
+    }
+
+    for (internal_path, archived) in archived_paths.iter() {
+        if !is_selected(patterns, internal_path) {
+            continue
+        }
+        if archived.metadata_corrupt {
+            report.fail(internal_path, io::Error::new(io::ErrorKind::InvalidData, "metadata could not be decoded"));
+        }
+        if let Some(ref archived_metadata) = archived.metadata {
+            let path = target_directory.join(Path::new(internal_path));
+            if archived_metadata.kind != EntryKind::File {
+                if let Err(e) = check_target(target_directory, internal_path) {
+                    report.fail(internal_path, e);
+                    continue
+                }
+            }
+            let write = match archived_metadata.kind {
+                EntryKind::File => continue, //done above
+                EntryKind::Directory => !path.is_dir() || should_overwrite(&path, archived_metadata, overwrite_policy),
+                EntryKind::Symlink => match prepare_target(&path, Some(archived_metadata), overwrite_policy) {
+                    Ok(write) => write,
+                    Err(e) => {
+                        report.fail(internal_path, e);
+                        continue
+                    }
+                }
+            };
+            if write {
+                metadata_entries.push((internal_path.as_str(), archived_metadata.clone()));
+            } else {
+                report.skipped.push(internal_path.clone());
+            }
+        }
+    }
+
+    //metadata is applied after all content is written, writing into a directory alters its mtime (and it may be read only)
+    //  directories last and deepest first, for the same reason
+    metadata_entries.sort_by_key(|(internal_path, entry_metadata)| (entry_metadata.kind == EntryKind::Directory, std::cmp::Reverse(internal_path.split('/').count())));
+    for (internal_path, entry_metadata) in metadata_entries {
+        match restore_metadata(target_directory, internal_path, &entry_metadata, special_mode_bits) {
+            Ok(_) => report.count(entry_metadata.kind),
+            Err(e) => report.fail(internal_path, e)
+        }
+    }
+
+    report
+}
+
+/// How extract treats the paths stored in an archive. They are never trusted to stay within the target directory.
+///   In either mode nothing is written through a symlink that exists in the target directory (unlike tar, which follows them).
+///   Paths below a symlink of the archive itself are rejected as well.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathValidation {
+    /// paths are normalized (see normalize_archive_path). Paths that escape the target directory are rejected.
+    Normalize,
+    /// only paths that are already normalized are accepted, as are only symlinks pointing to a relative path within the target directory.
+    Strict
+}
+
+/// Normalizes a path stored in an archive into a relative path with `/` separators and no `.`, `..` or empty components.
+///   Backslashes are treated as separators, leading separators and windows drive prefixes are removed (so absolute paths become relative).
+///   `..` removes the previous component, paths that would leave the target directory this way (`../x`, `a/../../x`) are rejected.
+pub fn normalize_archive_path(path:&str) -> Result<String, &'static str> {
+    if path.contains('\0') {
+        return Err("path contains a nul byte")
+    }
+    let path = path.replace('\\', "/");
+    let path = if has_drive_prefix(&path) { &path[2..] } else { &path[..] };
+
+    let mut components:Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {},
+            ".." => if components.pop().is_none() {
+                return Err("path escapes the target directory")
+            },
+            component => components.push(component)
+        }
+    }
+    if components.is_empty() {
+        return Err("path does not name anything within the target directory")
+    }
+    Ok(components.join("/"))
+}
+
+//like C: in C:\x or C:x
+fn has_drive_prefix(path:&str) -> bool {
+    let path_bytes = path.as_bytes();
+    path_bytes.len() >= 2 && path_bytes[0].is_ascii_alphabetic() && path_bytes[1] == b':'
+}
+
+//whether a relative symlink target, resolved from the directory of the link, stays within the target directory
+pub(crate) fn symlink_target_stays_inside(internal_path:&str, symlink_target:&str) -> bool {
+    if symlink_target.starts_with('/') || symlink_target.starts_with('\\') || has_drive_prefix(symlink_target) {
+        return false
+    }
+    let mut depth = internal_path.split('/').count() as i64 - 1;
+    for component in symlink_target.split(['/', '\\']) {
+        match component {
+            "" | "." => {},
+            ".." => depth -= 1,
+            _ => depth += 1
+        }
+        if depth < 0 {
+            return false
+        }
+    }
+    true
+}
+
+//replaces the archived paths by their normalized form, returns the accepted paths. Rejected ones are added to failed
+fn validate_archived_paths(archived_paths:HashMap<String, ArchivedPath>, path_validation:PathValidation, failed:&mut Vec<(String, io::Error)>) -> HashMap<String, ArchivedPath> {
+    let mut normalized_paths:Vec<(String, Result<String, &'static str>, ArchivedPath)> = archived_paths.into_iter().map(|(path, archived)| {
+        let normalized = normalize_archive_path(&path);
+        (path, normalized, archived)
+    }).collect();
+    //paths that already are normalized win over the ones that only collide after normalization
+    normalized_paths.sort_by(|(path_a, normalized_a, _), (path_b, normalized_b, _)|
+        (normalized_a.as_ref() != Ok(path_a), path_a).cmp(&(normalized_b.as_ref() != Ok(path_b), path_b)));
+
+    let mut accepted = HashMap::new();
+    for (path, normalized, archived) in normalized_paths {
+        let checked = normalized.and_then(|normalized| {
+            if path_validation == PathValidation::Strict {
+                if normalized != path {
+                    return Err("path is not normalized")
+                }
+                if let Some(EntryMetadata { kind:EntryKind::Symlink, symlink_target:Some(ref symlink_target), .. }) = archived.metadata {
+                    if !symlink_target_stays_inside(&normalized, symlink_target) {
+                        return Err("symlink points outside of the target directory")
+                    }
+                }
+            }
+            if accepted.contains_key(&normalized) {
+                return Err("path collides with another archived path after normalization")
+            }
+            Ok(normalized)
+        });
+        match checked {
+            Ok(normalized) => {
+                accepted.insert(normalized, archived);
+            },
+            Err(e) => failed.push((path, io::Error::new(io::ErrorKind::InvalidData, e)))
+        }
+    }
+
+    //the archive's own symlinks would only be checked for once they exist, so nothing may be extracted below them
+    let symlinks:HashSet<String> = accepted.iter()
+        .filter(|(_, archived)| matches!(archived.metadata, Some(EntryMetadata { kind:EntryKind::Symlink, .. })))
+        .map(|(path, _)| path.clone()).collect();
+    let below_symlink:Vec<String> = accepted.keys()
+        .filter(|path| path.match_indices('/').any(|(separator, _)| symlinks.contains(&path[..separator])))
+        .cloned().collect();
+    for path in below_symlink {
+        accepted.remove(&path);
+        failed.push((path, io::Error::new(io::ErrorKind::InvalidData, "path lies below a symlink of the archive")));
+    }
+    accepted
+}
+
+//fails if any directory on the way to internal_path within target_directory is a symlink (it may point anywhere)
+fn check_target(target_directory:&Path, internal_path:&str) -> io::Result<()> {
+    let mut ancestor = target_directory.to_path_buf();
+    let components:Vec<&str> = internal_path.split('/').collect();
+    for component in &components[..components.len() - 1] {
+        ancestor.push(component);
+        if fs::symlink_metadata(&ancestor).map(|metadata| metadata.file_type().is_symlink()).unwrap_or(false) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "a symlink exists on the way to the target path"))
+        }
+    }
+    Ok(())
+}
+
+fn should_overwrite(path:&Path, archived_metadata:&EntryMetadata, overwrite_policy:OverwritePolicy) -> bool {
+    match overwrite_policy {
+        OverwritePolicy::Skip => false,
+        OverwritePolicy::Overwrite => true,
+        OverwritePolicy::OverwriteIfNewer => match fs::symlink_metadata(path).and_then(|existing| existing.modified()) {
+            Ok(existing_mtime) => archived_metadata.mtime() > existing_mtime,
+            Err(_) => false
+        }
+    }
+}
+
+//returns whether the file or symlink should be written to path, removes whatever is in the way if so.
+//  (an existing symlink is removed rather than written through, it may point anywhere)
+fn prepare_target(path:&Path, archived_metadata:Option<&EntryMetadata>, overwrite_policy:OverwritePolicy) -> io::Result<bool> {
+    let existing = match fs::symlink_metadata(path) {
+        Err(_) => return Ok(true),
+        Ok(existing) => existing
+    };
+    let overwrite = match archived_metadata {
+        Some(archived_metadata) => should_overwrite(path, archived_metadata, overwrite_policy),
+        None => overwrite_policy == OverwritePolicy::Overwrite
+    };
+    if !overwrite {
+        return Ok(false)
+    }
+    if existing.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "a directory exists at the target path"))
+    }
+    fs::remove_file(path)?;
+    Ok(true)
+}
+
+//checked again right before anything is created or changed, symlinks may have been created since the entry was planned
+fn restore_metadata(target_directory:&Path, internal_path:&str, entry_metadata:&EntryMetadata, special_mode_bits:bool) -> io::Result<()> {
+    check_target(target_directory, internal_path)?;
+    let path = target_directory.join(Path::new(internal_path));
+    match entry_metadata.kind {
+        EntryKind::Directory => {
+            if fs::symlink_metadata(&path).map(|metadata| metadata.file_type().is_symlink()).unwrap_or(false) {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, "a symlink exists at the target path of the directory"))
+            }
+            fs::create_dir_all(&path)?
+        },
+        EntryKind::Symlink => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            return create_symlink(entry_metadata.symlink_target.as_deref().unwrap_or(""), &path) //neither permissions nor mtime of the link itself can be set portably
+        },
+        EntryKind::File => if fs::symlink_metadata(&path)?.file_type().is_symlink() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "a symlink replaced the extracted file"))
+        }
+    }
+    set_mtime(&path, entry_metadata.mtime())?;
+    set_mode(&path, if special_mode_bits { entry_metadata.mode } else { entry_metadata.mode & PERMISSION_MODE_BITS })
+}
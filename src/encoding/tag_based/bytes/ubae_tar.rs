@@ -0,0 +1,187 @@
+extern crate tar;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+
+use self::tar::Archive;
+use self::tar::Builder;
+use self::tar::EntryType;
+use self::tar::Header;
+
+use crate::encoding::tag_based::bytes::ubae::Ubae;
+use crate::encoding::tag_based::bytes::ubae::UbaeTraits;
+use crate::encoding::tag_based::bytes::ubae_directory_encoder::add_metadata_entry;
+use crate::encoding::tag_based::bytes::ubae_directory_encoder::delete_path;
+use crate::encoding::tag_based::bytes::ubae_directory_encoder::normalize_archive_path;
+use crate::encoding::tag_based::bytes::ubae_directory_encoder::read_archive_index_of;
+use crate::encoding::tag_based::bytes::ubae_directory_encoder::DirectoryReport;
+use crate::encoding::tag_based::bytes::ubae_directory_encoder::EntryKind;
+use crate::encoding::tag_based::bytes::ubae_directory_encoder::EntryMetadata;
+use crate::encoding::tag_based::bytes::ubae_directory_encoder::Sha256Reader;
+use crate::transparent_storage::bytes::file_storage_system::FileStorageSystem;
+
+//Conversion between directory archives (see ubae_directory_encoder) and tar streams.
+//   Both directions stream, neither the archive nor the tar stream is ever held in memory or written to a temporary file.
+//
+//What survives the conversion: files, directories, symlinks, permission bits and modification times (tar stores whole seconds only).
+//   Hard links, device files, fifos and sparse files in a tar stream cannot be represented in a directory archive, they are reported as failed.
+
+///
+///Writes every path of the directory archive into writer as a tar stream (GNU format, long paths are supported).
+///   Paths are written sorted, so every directory comes before its content.
+///   Archived paths that are not normalized (see normalize_archive_path) are not written, they are reported as failed.
+///   Err if the archive cannot be read or writing to writer fails, the tar stream is incomplete then.
+///
+///Returns the writer, after the end of the tar stream was written.
+///
+pub fn export_tar<W:Write>(archive_file_path:&str, writer:W) -> io::Result<(W, DirectoryReport)> {
+    let mut archived_paths:Vec<_> = read_archive_index_of(archive_file_path)?.into_iter().collect();
+    archived_paths.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut archive_file = File::open(archive_file_path)?;
+    let mut builder = Builder::new(writer);
+    let mut report = DirectoryReport::default();
+
+    for (path, archived) in archived_paths {
+        if normalize_archive_path(&path).ok().as_ref() != Some(&path) {
+            report.fail(&path, io::Error::new(io::ErrorKind::InvalidData, "path is not normalized"));
+            continue
+        }
+        if archived.metadata_corrupt {
+            report.fail(&path, io::Error::new(io::ErrorKind::InvalidData, "metadata could not be decoded"));
+            continue
+        }
+        //archives created before metadata was stored only contain files
+        let (kind, mode, mtime) = match archived.metadata {
+            Some(ref metadata) => (metadata.kind, metadata.mode & 0o7777, metadata.mtime_secs.max(0) as u64),
+            None => (EntryKind::File, 0o644, 0)
+        };
+
+        let mut header = Header::new_gnu();
+        header.set_mode(mode);
+        header.set_mtime(mtime);
+        match kind {
+            EntryKind::File => {
+                let (start, end) = match archived.content_range {
+                    Some(content_range) => content_range,
+                    None => {
+                        report.fail(&path, io::Error::new(io::ErrorKind::InvalidData, "content is missing"));
+                        continue
+                    }
+                };
+                header.set_entry_type(EntryType::Regular);
+                header.set_size((end - start) as u64);
+                archive_file.seek(SeekFrom::Start(start as u64))?;
+                builder.append_data(&mut header, &path, (&mut archive_file).take((end - start) as u64))?;
+                report.bytes_written+=(end - start) as u64;
+            },
+            EntryKind::Directory => {
+                header.set_entry_type(EntryType::Directory);
+                header.set_size(0);
+                builder.append_data(&mut header, &path, io::empty())?;
+            },
+            EntryKind::Symlink => {
+                let target = archived.metadata.as_ref().and_then(|metadata| metadata.symlink_target.clone()).unwrap_or_default();
+                header.set_entry_type(EntryType::Symlink);
+                header.set_size(0);
+                builder.append_link(&mut header, &path, &target)?;
+            }
+        }
+        report.count(kind);
+    }
+
+    Ok((builder.into_inner()?, report))
+}
+
+///
+///Reads the tar stream from reader into a new directory archive at target_file_path.
+///   Paths are normalized like when extracting (see normalize_archive_path), paths that cannot be normalized are reported as failed.
+///   If a path occurs more than once, the last occurrence is kept (like tar itself does when extracting).
+///   Err if the tar stream is corrupt or writing the archive fails, the archive contains everything imported up until then.
+///
+///The archive is not deduplicating, because the content hash is only known after the content was written.
+///
+pub fn import_tar<R:Read>(reader:R, target_file_path:&str) -> io::Result<DirectoryReport> {
+    if Path::new(target_file_path).exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", target_file_path)))
+    }
+    let mut ubae = Ubae::new(FileStorageSystem::create_leave_source_intact_with_custom_buf_size(target_file_path, 16384));
+    ubae.set_content(&[])?;
+
+    let mut archive = Archive::new(reader);
+    let mut imported_paths = HashSet::new();
+    let mut report = DirectoryReport::default();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let raw_path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let entry_type = entry.header().entry_type();
+        let kind = match entry_type {
+            EntryType::Regular | EntryType::Continuous => EntryKind::File,
+            EntryType::Directory => EntryKind::Directory,
+            EntryType::Symlink => EntryKind::Symlink,
+            EntryType::XGlobalHeader => continue, //pax extensions for the whole stream, nothing in it is stored
+            _ => {
+                report.fail(&raw_path, io::Error::new(io::ErrorKind::Unsupported, format!("unsupported tar entry type: {:?}", entry_type)));
+                continue
+            }
+        };
+        let path = match entry.path() {
+            Ok(path) => match path.to_str().map(normalize_archive_path) {
+                Some(Ok(path)) => path,
+                Some(Err(_)) if kind == EntryKind::Directory && raw_path.split('/').all(|component| component.is_empty() || component == ".") => {
+                    continue //the root itself ("./"), the archive has no entry for it
+                },
+                Some(Err(reason)) => {
+                    report.fail(&raw_path, io::Error::new(io::ErrorKind::InvalidData, reason));
+                    continue
+                },
+                None => {
+                    report.fail(&raw_path, io::Error::new(io::ErrorKind::InvalidData, "path is not valid unicode"));
+                    continue
+                }
+            },
+            Err(e) => {
+                report.fail(&raw_path, e);
+                continue
+            }
+        };
+        let symlink_target = if kind == EntryKind::Symlink {
+            match entry.link_name()?.as_ref().and_then(|target| target.to_str()) {
+                Some(target) => Some(target.to_string()),
+                None => {
+                    report.fail(&path, io::Error::new(io::ErrorKind::InvalidData, "symlink target is missing or not valid unicode"));
+                    continue
+                }
+            }
+        } else {
+            None
+        };
+        let mut metadata = EntryMetadata {
+            kind,
+            mode:entry.header().mode()? & 0o7777,
+            mtime_secs:entry.header().mtime()? as i64,
+            mtime_nanos:0,
+            symlink_target,
+            sha256:None
+        };
+
+        if !imported_paths.insert(path.clone()) {
+            delete_path(&mut ubae, &path)?;
+        }
+        if kind == EntryKind::File {
+            let size = entry.size();
+            let mut hashing_reader = Sha256Reader::new(&mut entry);
+            ubae.add_entry_from_stream_nocheck(&path, &mut hashing_reader, size as i64)?;
+            metadata.sha256 = Some(hashing_reader.finish());
+            report.bytes_written+=size;
+        }
+        add_metadata_entry(&mut ubae, &path, &metadata)?;
+        report.count(kind);
+    }
+    Ok(report)
+}
@@ -1,47 +1,41 @@
-
 pub fn transform_booleans(val:&[bool]) -> Vec<u8> {
-    if val.len() == 0 {
-        vec![]
-    } else {
-        let number_of_bits_to_store = val.len() + 3;
-        let number_of_over_bits:isize = val.len() as isize % 8;
-
-        let mut bs:Vec<u8> = Vec::with_capacity(number_of_bits_to_store/8 + (if number_of_bits_to_store % 8 == 0 {0} else {1}));
-        bs.push(((number_of_over_bits-1) << (8 - 3)) as u8);
+    if val.is_empty() {
+        return vec![]
+    }
+    //the first 3 bits hold the number of booleans modulo 8 (minus one, wrapping), the booleans follow directly
+    let mut bs:Vec<u8> = Vec::with_capacity(val.len() / 8 + 2);
+    bs.push((((val.len() + 7) % 8) << (8 - 3)) as u8);
 
-        for i in 0..val.len() {
-            let bit_index = i+3;
-            let byte_index = bit_index/8;
-            let bit_index_in_byte = bit_index % 8;
-            if bit_index_in_byte == 0 {
-                bs.push(0);
-            }
-            if val[i] {
-                bs[byte_index] = set_bit(bs[byte_index], 7 - bit_index_in_byte);
-            }
+    for (i, value) in val.iter().enumerate() {
+        let bit_index = i+3;
+        let byte_index = bit_index/8;
+        if byte_index == bs.len() {
+            bs.push(0);
+        }
+        if *value {
+            bs[byte_index] = set_bit(bs[byte_index], 7 - bit_index % 8);
         }
-        return bs
     }
+    bs
 }
 
 pub fn detransform_booleans(en:&[u8]) -> Vec<bool> {
-    if en.len() == 0 {
-        vec![]
-    } else {
-        let number_of_over_bits = ((en[0] >> 5 & 0b111 ) + 1) % 8;
-
-        let size = (en.len() - 1 ) * 8 + number_of_over_bits as usize;
-        let mut results = Vec::with_capacity(size);
-
-        for i in 0..size {
-            let bit_index = i+3; //jump bytes occupied by over bit indicator
-            let byte_index = bit_index/8;
-            let bit_index_in_byte = bit_index % 8;
-            results.push(get_bit(en[byte_index], 7 - bit_index_in_byte) == 1);
-        }
+    let size = encoded_boolean_count(en).expect("not an encoding of booleans");
+    (0..size).map(|i| {
+        let bit_index = i+3; //jump bits occupied by the header
+        get_bit(en[bit_index/8], 7 - bit_index % 8) == 1
+    }).collect()
+}
 
-        return results
+/// The number of booleans in en (an encoding of transform_booleans), None if en is no such encoding.
+pub fn encoded_boolean_count(en:&[u8]) -> Option<usize> {
+    if en.is_empty() {
+        return Some(0)
     }
+    let count_modulo_8 = ((en[0] >> 5) as usize + 1) % 8;
+    //the encoding uses as few bytes as possible, so the count is the largest one with that remainder that fits behind the header
+    let capacity = en.len() * 8 - 3;
+    capacity.checked_sub((capacity + 8 - count_modulo_8) % 8).filter(|count| *count > 0)
 }
 
 
@@ -83,4 +77,13 @@ fn test() {
 
         assert_eq!(o, d);
     }
+
+    for len in 0..=17 {
+        let o:Vec<bool> = (0..len).map(|i| i % 3 != 1).collect();
+        let t = transform_booleans(&o);
+        assert_eq!((len + 3 + 7) / 8 - if len == 0 {1} else {0}, t.len());
+        assert_eq!(Some(len), encoded_boolean_count(&t));
+        assert_eq!(o, detransform_booleans(&t));
+    }
+    assert_eq!(None, encoded_boolean_count(&[0b110_00000])); //would be 7 booleans, only 5 fit
 }
\ No newline at end of file
@@ -1,33 +1,39 @@
 extern crate byteorder;
 
 use std;
+use std::io::{Error, ErrorKind, Result};
 
 use crate::encoding::tag_based::bytes::libae::LIbae;
 use crate::encoding::tag_based::bytes::libae::LIbaeTraits;
 use crate::encoding::type_transformer::bytes::booleans::detransform_booleans;
+use crate::encoding::type_transformer::bytes::booleans::encoded_boolean_count;
 use crate::encoding::type_transformer::bytes::booleans::transform_booleans;
 
 use self::byteorder::{BigEndian, ByteOrder};
 
 ///:author jokrey
-
-#[cfg(test)]
 pub mod booleans;
 
-trait Transform<SF> {
+pub trait Transform<SF> {
     fn transform(&self) -> SF;
 }
-trait DeTransform<SF> {
+pub trait DeTransform<SF> {
     fn detransform(raw:&SF) -> Self;
 }
-trait DeTransformBytes
+pub trait DeTransformBytes
     where Self: std::marker::Sized {
+    #[allow(clippy::ptr_arg)] //with a slice, detransform(&t.transform()) could not infer which Transform is meant
     fn detransform(raw:&Vec<u8>) -> Self {
         DeTransformBytes::detransform_from(&raw[..])
     }
-    fn detransform_from(raw:&[u8]) -> Self;
+    /// Panics if raw is not an encoding of Self, use try_detransform_from for raw bytes that cannot be trusted (e.g. read from the network).
+    fn detransform_from(raw:&[u8]) -> Self {
+        Self::try_detransform_from(raw).unwrap()
+    }
+    /// Err(InvalidData) if raw is not an encoding of Self (wrong length, invalid utf8, ...).
+    fn try_detransform_from(raw:&[u8]) -> Result<Self>;
 }
-trait FromToTransform<SF> : Transform<SF> + DeTransform<SF> {}
+pub trait FromToTransform<SF> : Transform<SF> + DeTransform<SF> {}
 
 impl Transform<Vec<u8>> for bool {
     fn transform(&self) -> Vec<u8> {
@@ -39,8 +45,8 @@ impl Transform<Vec<u8>> for bool {
     }
 }
 impl DeTransformBytes for bool {
-    fn detransform_from(raw: &[u8]) -> Self {
-        raw[0] == 1
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        Ok(fixed_size(raw, 1)?[0] == 1)
     }
 }
 //impl Transform<Vec<u8>> for [bool] {
@@ -54,8 +60,11 @@ impl Transform<Vec<u8>> for Vec<bool> {
     }
 }
 impl DeTransformBytes for Vec<bool> {
-    fn detransform_from(raw: &[u8]) -> Self {
-        detransform_booleans(raw)
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        match encoded_boolean_count(raw) {
+            Some(_) => Ok(detransform_booleans(raw)),
+            None => Err(Error::new(ErrorKind::InvalidData, "booleans announce more bits than are encoded"))
+        }
     }
 }
 
@@ -67,8 +76,8 @@ impl Transform<Vec<u8>> for i16 {
     }
 }
 impl DeTransformBytes for i16 {
-    fn detransform_from(raw: &[u8]) -> Self {
-        BigEndian::read_i16(raw)
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        Ok(BigEndian::read_i16(fixed_size(raw, 2)?))
     }
 }
 //impl Transform<Vec<u8>> for [i16] {
@@ -82,7 +91,7 @@ impl Transform<Vec<u8>> for Vec<i16> {
     }
 }
 impl DeTransformBytes for Vec<i16> {
-    fn detransform_from(raw: &[u8]) -> Self {
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
         detransform_array(raw, 2)
     }
 }
@@ -95,8 +104,8 @@ impl Transform<Vec<u8>> for i32 {
     }
 }
 impl DeTransformBytes for i32 {
-    fn detransform_from(raw: &[u8]) -> Self {
-        BigEndian::read_i32(raw)
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        Ok(BigEndian::read_i32(fixed_size(raw, 4)?))
     }
 }
 //impl Transform<Vec<u8>> for [i32] {
@@ -110,7 +119,7 @@ impl Transform<Vec<u8>> for Vec<i32> {
     }
 }
 impl DeTransformBytes for Vec<i32> {
-    fn detransform_from(raw: &[u8]) -> Self {
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
         detransform_array(raw, 4)
     }
 }
@@ -123,8 +132,8 @@ impl Transform<Vec<u8>> for i64 {
     }
 }
 impl DeTransformBytes for i64 {
-    fn detransform_from(raw: &[u8]) -> Self {
-        BigEndian::read_i64(raw)
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        Ok(BigEndian::read_i64(fixed_size(raw, 8)?))
     }
 }
 //impl Transform<Vec<u8>> for [i64] {
@@ -138,7 +147,7 @@ impl Transform<Vec<u8>> for Vec<i64> {
     }
 }
 impl DeTransformBytes for Vec<i64> {
-    fn detransform_from(raw: &[u8]) -> Self {
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
         detransform_array(raw, 8)
     }
 }
@@ -151,8 +160,8 @@ impl Transform<Vec<u8>> for f32 {
     }
 }
 impl DeTransformBytes for f32 {
-    fn detransform_from(raw: &[u8]) -> Self {
-        BigEndian::read_f32(raw)
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        Ok(BigEndian::read_f32(fixed_size(raw, 4)?))
     }
 }
 //impl Transform<Vec<u8>> for [f32] {
@@ -166,7 +175,7 @@ impl Transform<Vec<u8>> for Vec<f32> {
     }
 }
 impl DeTransformBytes for Vec<f32> {
-    fn detransform_from(raw: &[u8]) -> Self {
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
         detransform_array(raw, 4)
     }
 }
@@ -179,8 +188,8 @@ impl Transform<Vec<u8>> for f64 {
     }
 }
 impl DeTransformBytes for f64 {
-    fn detransform_from(raw: &[u8]) -> Self {
-        BigEndian::read_f64(raw)
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        Ok(BigEndian::read_f64(fixed_size(raw, 8)?))
     }
 }
 //impl Transform<Vec<u8>> for [f64] {
@@ -194,7 +203,7 @@ impl Transform<Vec<u8>> for Vec<f64> {
     }
 }
 impl DeTransformBytes for Vec<f64> {
-    fn detransform_from(raw: &[u8]) -> Self {
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
         detransform_array(raw, 8)
     }
 }
@@ -205,8 +214,8 @@ impl Transform<Vec<u8>> for String {
     }
 }
 impl DeTransformBytes for String {
-    fn detransform_from(raw: &[u8]) -> Self {
-        String::from_utf8(raw.to_vec()).unwrap()
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        String::from_utf8(raw.to_vec()).map_err(|e| Error::new(ErrorKind::InvalidData, e))
     }
 }
 impl Transform<Vec<u8>> for str {
@@ -219,6 +228,46 @@ impl Transform<Vec<u8>> for &'static str {
         Vec::from(self.as_bytes())
     }
 }
+impl Transform<Vec<u8>> for Vec<String> {
+    fn transform(&self) -> Vec<u8> {
+        transform_any_array(self)
+    }
+}
+impl DeTransformBytes for Vec<String> {
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        detransform_any_array(raw)
+    }
+}
+
+impl Transform<Vec<u8>> for Vec<u8> {
+    fn transform(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+impl DeTransformBytes for Vec<u8> {
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        Ok(raw.to_vec())
+    }
+}
+
+impl Transform<Vec<u8>> for () {
+    fn transform(&self) -> Vec<u8> {
+        vec![]
+    }
+}
+impl DeTransformBytes for () {
+    fn try_detransform_from(raw: &[u8]) -> Result<Self> {
+        fixed_size(raw, 0).map(|_| ())
+    }
+}
+
+fn fixed_size(raw:&[u8], size:usize) -> Result<&[u8]> {
+    if raw.len() == size {
+        Ok(raw)
+    } else {
+        Err(Error::new(ErrorKind::InvalidData, format!("expected {} bytes, got {}", size, raw.len())))
+    }
+}
 
 fn transform_array<T:Transform<Vec<u8>>+Sized+Clone>(ts:&[T], element_size:usize) -> Vec<u8> {
     let mut bs = Vec::with_capacity(element_size * ts.len());
@@ -227,16 +276,14 @@ fn transform_array<T:Transform<Vec<u8>>+Sized+Clone>(ts:&[T], element_size:usize
             bs.push(c);
         }
     }
-    return bs
+    bs
 }
-fn detransform_array<T:DeTransformBytes+Sized+Clone>(arr:&[u8], element_size:usize) -> Vec<T> {
-    let size = arr.len() / element_size;
-    let mut result = Vec::with_capacity(size);
-    for i in 0..size {
-        let e_arr = &arr[i*element_size..((i+1)*element_size)];
-        result.push(T::detransform_from(e_arr));
+#[allow(unknown_lints, clippy::manual_is_multiple_of)] //usize::is_multiple_of requires rust 1.87
+fn detransform_array<T:DeTransformBytes+Sized+Clone>(arr:&[u8], element_size:usize) -> Result<Vec<T>> {
+    if arr.len() % element_size != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} bytes are no array of {} byte elements", arr.len(), element_size)))
     }
-    return result;
+    arr.chunks(element_size).map(T::try_detransform_from).collect()
 }
 
 fn transform_any_array<T:Transform<Vec<u8>>>(ts:&[T]) -> Vec<u8> {
@@ -246,14 +293,14 @@ fn transform_any_array<T:Transform<Vec<u8>>>(ts:&[T]) -> Vec<u8> {
     }
     libae.get_content().unwrap()
 }
-fn detransform_any_array<T:DeTransformBytes>(raw: &[u8]) -> Vec<T> {
+fn detransform_any_array<T:DeTransformBytes>(raw: &[u8]) -> Result<Vec<T>> {
     let mut result = Vec::with_capacity(25);
     let mut libae = LIbae::ram();
-    libae.set_content(raw).unwrap();
+    libae.set_content(raw)?;
     for raw_part in libae {
-        result.push(T::detransform_from(&raw_part));
+        result.push(T::try_detransform_from(&raw_part?)?);
     }
-    result
+    Ok(result)
 }
 
 
@@ -305,4 +352,4 @@ fn test() {
 
     //recursively supported arrays
     let a1 = vec![p9, "213123", "ä+sdäf+sdäf#+däsf+äsdvf", "test", ""];
-    assert_eq!(a1, detransform_any_array(&transform_any_array(&a1)) as Vec<String>); }
\ No newline at end of file
+    assert_eq!(a1, detransform_any_array(&transform_any_array(&a1)).unwrap() as Vec<String>); }
\ No newline at end of file
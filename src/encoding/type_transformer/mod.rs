@@ -1,4 +1,2 @@
 ///:author jokrey
-
-#[cfg(test)]
 pub mod bytes;
\ No newline at end of file
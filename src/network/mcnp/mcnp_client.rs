@@ -1,7 +1,17 @@
 use core::str::FromStr;
 use std::net::*;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
 
 use super::mcnp_connection::McnpConnection;
+#[cfg(feature = "tls")]
+use super::mcnp_tls::rustls::ClientConfig;
+#[cfg(feature = "tls")]
+use super::mcnp_tls::TlsStream;
 
 //semantic stuff
 
@@ -11,4 +21,16 @@ impl McnpClient {
     pub fn new(addr:&str, port:u16) -> McnpConnection {
         return McnpConnection::new_from_stream(TcpStream::connect(SocketAddr::new(IpAddr::from_str(addr).unwrap(), port)).expect("cannot connect to server"));
     }
+
+    /// Connects to a server listening on a unix domain socket (see McnpServer::run_unix_listener_loop).
+    #[cfg(unix)]
+    pub fn new_unix<P:AsRef<Path>>(socket_path:P) -> std::io::Result<McnpConnection<UnixStream>> {
+        Ok(McnpConnection::new_from_stream(UnixStream::connect(socket_path)?))
+    }
+
+    /// Connects over TLS, the server has to present a certificate for server_name that config trusts.
+    #[cfg(feature = "tls")]
+    pub fn new_tls(addr:&str, port:u16, config:Arc<ClientConfig>, server_name:&str) -> std::io::Result<McnpConnection<TlsStream>> {
+        Ok(McnpConnection::new_from_stream(TlsStream::connect(addr, port, config, server_name)?))
+    }
 }
\ No newline at end of file
@@ -1,27 +1,156 @@
 extern crate byteorder;
+extern crate socket2;
 
 use core::str::FromStr;
 use std::io::*;
 use std::io::Write;
 use std::net::*;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
 
 use crate::transparent_storage::Substream;
 
+use super::mcnp_transport::SharedStream;
+
 use self::byteorder::{BigEndian, ByteOrder};
+use self::socket2::SockRef;
+use self::socket2::TcpKeepalive;
+
+/// What a McnpConnection reads from and writes to, a TcpStream unless stated otherwise.
+///   try_clone has to return a second handle to the same connection (like TcpStream::try_clone does), reads through either handle consume the same bytes.
+///   The clone is used to hand out variable chunks as streams (see read_variable_chunk_as_stream).
+/// Any other duplex stream can be used by wrapping it into a SharedStream (see new_from_read_write),
+///   for connections within the same process there is memory_pipe (both in mcnp_transport).
+pub trait McnpTransport: Read + Write + Send + Sized {
+    fn try_clone(&self) -> Result<Self>;
+
+    /// Limits how long a single read or write may block (None waits forever), one that takes longer fails (see is_timeout).
+    ///   Clones share the timeouts. Transports that cannot time out only accept None.
+    fn set_timeouts(&self, read:Option<Duration>, write:Option<Duration>) -> Result<()> {
+        match (read, write) {
+            (None, None) => Ok(()),
+            _ => Err(Error::new(ErrorKind::Unsupported, "the transport does not support timeouts"))
+        }
+    }
+
+    /// Ends the writing direction (of all clones): the other side reads eof once it read what was written before, later writes fail.
+    ///   Used to abort a chunk that cannot be completed (see send_variable_chunk_from_stream). Transports that cannot do so return Err(Unsupported).
+    fn shutdown_write(&self) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "the transport cannot be shut down"))
+    }
+}
+
+/// A transport whose clones can be used independently: a clone waiting in a read does not keep another from writing.
+///   Required where one clone is read from all the time while others write, like by McnpMultiplexer.
+///   True for TcpStream, UnixStream, TlsStream, memory_pipe and multiplexed streams, not for SharedStream (its clones take turns).
+pub trait McnpConcurrentTransport: McnpTransport {}
+
+impl McnpTransport for TcpStream {
+    fn try_clone(&self) -> Result<TcpStream> {
+        TcpStream::try_clone(self)
+    }
+    fn set_timeouts(&self, read:Option<Duration>, write:Option<Duration>) -> Result<()> {
+        self.set_read_timeout(read)?;
+        self.set_write_timeout(write)
+    }
+    fn shutdown_write(&self) -> Result<()> {
+        self.shutdown(Shutdown::Write)
+    }
+}
+impl McnpConcurrentTransport for TcpStream {}
+#[cfg(unix)]
+impl McnpTransport for UnixStream {
+    fn try_clone(&self) -> Result<UnixStream> {
+        UnixStream::try_clone(self)
+    }
+    fn set_timeouts(&self, read:Option<Duration>, write:Option<Duration>) -> Result<()> {
+        self.set_read_timeout(read)?;
+        self.set_write_timeout(write)
+    }
+    fn shutdown_write(&self) -> Result<()> {
+        self.shutdown(Shutdown::Write)
+    }
+}
+#[cfg(unix)]
+impl McnpConcurrentTransport for UnixStream {}
+
+/// Whether e is the error of a read or write that exceeded its timeout (the kind differs between platforms).
+pub fn is_timeout(e:&Error) -> bool {
+    e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut
+}
+
+/// Enables tcp keepalive, so a peer that vanished without closing the connection (crashed, network gone) is noticed.
+///   time is how long the connection has to be idle before the first probe is sent. None disables keepalive.
+pub fn set_tcp_keepalive(stream:&TcpStream, time:Option<Duration>) -> Result<()> {
+    let socket = SockRef::from(stream);
+    match time {
+        Some(time) => socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(time)),
+        None => socket.set_keepalive(false)
+    }
+}
 
-pub trait McnpConnectionTraits {
+/// The limit of read_variable_chunk, unless configured otherwise (100 megabyte).
+pub const DEFAULT_MAX_CHUNK_LENGTH:u64 = 100_000_000;
+
+/// The error of read_variable_chunk for a chunk longer than the limit of the connection (see McnpConnection::set_max_chunk_length), wrapped in an io::Error of kind InvalidData.
+///   The content of the chunk is left unread, the connection stays usable once it is consumed (see McnpConnection::skip_oversized_chunk and read_oversized_chunk_as_stream).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkTooLarge {
+    pub length:u64,
+    pub limit:u64
+}
+impl ChunkTooLarge {
+    /// The ChunkTooLarge e wraps, if any.
+    pub fn of(e:&Error) -> Option<&ChunkTooLarge> {
+        e.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+}
+impl std::fmt::Display for ChunkTooLarge {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "chunk of {} bytes exceeds the limit of {} bytes. Try using a stream to handle this much data.", self.length, self.limit)
+    }
+}
+impl std::error::Error for ChunkTooLarge {}
+
+/// The timeouts of a McnpConnection (see McnpConnection::set_timeouts). None waits forever, which is the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct McnpTimeouts {
+    /// How long a single read may block, for example when the other side stalls in the middle of a chunk.
+    pub read:Option<Duration>,
+    /// How long a single write may block, for example when the other side stops reading.
+    pub write:Option<Duration>,
+    /// How long read_cause waits for the next cause, i.e. how long the connection may be idle between two conversations.
+    pub idle:Option<Duration>
+}
+
+pub trait McnpConnectionTraits<S:Read> {
     fn send_fixed_chunk_u8(&mut self, val:u8) -> Result<()>;
+    fn send_fixed_chunk_u16(&mut self, val:u16) -> Result<()>;
+    fn send_fixed_chunk_u32(&mut self, val:u32) -> Result<()>;
+    fn send_fixed_chunk_u64(&mut self, val:u64) -> Result<()>;
+    fn send_fixed_chunk_u128(&mut self, val:u128) -> Result<()>;
     fn send_fixed_chunk_i16(&mut self, val:i16) -> Result<()>;
     fn send_fixed_chunk_i32(&mut self, val:i32) -> Result<()>;
     fn send_fixed_chunk_i64(&mut self, val:i64) -> Result<()>;
+    fn send_fixed_chunk_i128(&mut self, val:i128) -> Result<()>;
+    /// One byte, 1 for true and 0 for false (like the bool of type_transformer).
+    fn send_fixed_chunk_bool(&mut self, val:bool) -> Result<()>;
     fn send_fixed_chunk_f32(&mut self, val:f32) -> Result<()>;
     fn send_fixed_chunk_f64(&mut self, val:f64) -> Result<()>;
     fn send_fixed_chunk_u8_arr(&mut self, val:&[u8]) -> Result<()>;
 
     fn read_fixed_chunk_u8(&mut self) -> Result<u8>;
+    fn read_fixed_chunk_u16(&mut self) -> Result<u16>;
+    fn read_fixed_chunk_u32(&mut self) -> Result<u32>;
+    fn read_fixed_chunk_u64(&mut self) -> Result<u64>;
+    fn read_fixed_chunk_u128(&mut self) -> Result<u128>;
     fn read_fixed_chunk_i16(&mut self) -> Result<i16>;
     fn read_fixed_chunk_i32(&mut self) -> Result<i32>;
     fn read_fixed_chunk_i64(&mut self) -> Result<i64>;
+    fn read_fixed_chunk_i128(&mut self) -> Result<i128>;
+    /// Err(InvalidData) for any byte other than 0 or 1.
+    fn read_fixed_chunk_bool(&mut self) -> Result<bool>;
     fn read_fixed_chunk_f32(&mut self) -> Result<f32>;
     fn read_fixed_chunk_f64(&mut self) -> Result<f64>;
     fn read_fixed_chunk_u8_arr(&mut self, bytes_to_read: usize) -> Result<Vec<u8>>;
@@ -37,31 +166,142 @@ pub trait McnpConnectionTraits {
 
     //optionals
     fn send_variable_chunk(&mut self, arr:&[u8]) -> Result<()>;
-    fn read_variable_chunk_as_stream(&mut self) -> Result<(Substream<TcpStream>, i64)>;
+    /// None is sent as the none chunk (a negative length), which read_variable_chunk rejects and read_variable_chunk_optional returns as None.
+    fn send_variable_chunk_optional(&mut self, arr:Option<&[u8]>) -> Result<()>;
+    fn read_variable_chunk_optional(&mut self) -> Result<Option<Vec<u8>>>;
+    fn send_variable_utf8(&mut self, string:&str) -> Result<()>;
+    /// Err(InvalidData) if the chunk is not valid utf8 (it is consumed regardless).
+    fn read_variable_utf8(&mut self) -> Result<String>;
+    fn send_variable_utf8_optional(&mut self, string:Option<&str>) -> Result<()>;
+    fn read_variable_utf8_optional(&mut self) -> Result<Option<String>>;
+    fn read_variable_chunk_as_stream(&mut self) -> Result<(Substream<S>, i64)>;
+    /// Sends exactly stream_length bytes from stream as one chunk (a longer stream is not read further).
+    ///   If the stream ends early or fails, its error is returned (UnexpectedEof if it ended) and the chunk is aborted by shutting down the writing direction of the transport (see McnpTransport::shutdown_write),
+    ///   so the other side fails to read the chunk (UnexpectedEof) instead of receiving incomplete content. The connection cannot send anymore then and should be dropped.
     fn send_variable_chunk_from_stream(&mut self, stream: &mut dyn Read, stream_length:i64) -> Result<()>;
 }
 
 #[derive(Debug)]
-pub struct McnpConnection {
-    socket: TcpStream,
+pub struct McnpConnection<S:McnpTransport = TcpStream> {
+    socket: S,
+    timeouts: McnpTimeouts,
+    max_chunk_length: u64,
+    oversized_chunk: Option<u64>,
 }
 
-impl McnpConnection {
-    pub fn new_from_stream(stream: TcpStream) -> McnpConnection {
+impl<S:McnpTransport> McnpConnection<S> {
+    pub fn new_from_stream(stream: S) -> McnpConnection<S> {
         McnpConnection {
             socket:stream,
+            timeouts:McnpTimeouts::default(),
+            max_chunk_length:DEFAULT_MAX_CHUNK_LENGTH,
+            oversized_chunk:None,
         }
     }
+    /// The underlying transport, for example to query the peer address of a TcpStream.
+    pub fn transport(&self) -> &S {
+        &self.socket
+    }
+    /// The underlying transport, for example to hand it to a McnpMultiplexer. Settings of the connection that live in the transport (like the timeouts) remain.
+    pub fn into_transport(self) -> S {
+        self.socket
+    }
+
+    /// A read or write exceeding its timeout fails (see is_timeout), the connection should be closed then - it is unknown how much of a chunk was transferred.
+    ///   Also applies to the streams returned by read_variable_chunk_as_stream. Err if the transport does not support timeouts (any of them, including idle).
+    pub fn set_timeouts(&mut self, timeouts:McnpTimeouts) -> Result<()> {
+        if timeouts.idle != timeouts.read {
+            self.socket.set_timeouts(timeouts.idle, timeouts.write)?; //read_cause switches to it later, so a transport that rejects it has to do so now
+        }
+        self.socket.set_timeouts(timeouts.read, timeouts.write)?;
+        self.timeouts = timeouts;
+        Ok(())
+    }
+    pub fn timeouts(&self) -> McnpTimeouts {
+        self.timeouts
+    }
+
+    /// The longest chunk read_variable_chunk reads into memory (default DEFAULT_MAX_CHUNK_LENGTH), longer chunks fail with ChunkTooLarge.
+    ///   Servers should lower it to bound the memory a client can make them allocate. read_variable_chunk_as_stream is not limited.
+    pub fn set_max_chunk_length(&mut self, max_chunk_length:u64) {
+        self.max_chunk_length = max_chunk_length;
+    }
+    pub fn max_chunk_length(&self) -> u64 {
+        self.max_chunk_length
+    }
+
+    /// The length of the chunk last rejected with ChunkTooLarge, while its content is still unread.
+    ///   Until it is consumed (with skip_oversized_chunk or read_oversized_chunk_as_stream) any other read would read from the middle of it.
+    pub fn pending_oversized_chunk(&self) -> Option<u64> {
+        self.oversized_chunk
+    }
+    /// Reads and discards the content of the chunk rejected with ChunkTooLarge. Returns how many bytes were discarded (0 if there was no such chunk).
+    pub fn skip_oversized_chunk(&mut self) -> Result<u64> {
+        let length = match self.oversized_chunk.take() {
+            Some(length) => length,
+            None => return Ok(0)
+        };
+        let skipped = copy(&mut Read::by_ref(&mut self.socket).take(length), &mut sink())?;
+        if skipped < length {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed within the chunk"))
+        }
+        Ok(skipped)
+    }
+    /// The content of the chunk rejected with ChunkTooLarge as a stream (like read_variable_chunk_as_stream), so it can be handled after all.
+    ///   Err(InvalidInput) if there is no such chunk.
+    pub fn read_oversized_chunk_as_stream(&mut self) -> Result<(Substream<S>, i64)> {
+        let length = self.oversized_chunk.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "no chunk was rejected as too large"))?;
+        let socket_clone = self.socket.try_clone()?;
+        self.oversized_chunk = None;
+        Ok((Substream::new_from_start(socket_clone, length), length as i64))
+    }
+}
+impl<T:Read + Write + Send> McnpConnection<SharedStream<T>> {
+    /// A connection over any duplex stream, that cannot be cloned itself.
+    pub fn new_from_read_write(stream: T) -> McnpConnection<SharedStream<T>> {
+        McnpConnection::new_from_stream(SharedStream::new(stream))
+    }
+}
+impl McnpConnection {
     pub fn new(addr:&str, port:u16) -> McnpConnection {
         return McnpConnection::new_from_stream(TcpStream::connect(SocketAddr::new(IpAddr::from_str(addr).unwrap(), port)).unwrap());
     }
+
+    /// See set_tcp_keepalive.
+    pub fn set_keepalive(&self, time:Option<Duration>) -> Result<()> {
+        set_tcp_keepalive(&self.socket, time)
+    }
 }
 
-impl McnpConnectionTraits for McnpConnection {
+impl<S:McnpTransport> McnpConnectionTraits<S> for McnpConnection<S> {
     fn send_fixed_chunk_u8(&mut self, val: u8) -> Result<()> {
         return self.socket.write_all(&[val]);
     }
 
+    fn send_fixed_chunk_u16(&mut self, val: u16) -> Result<()> {
+        let mut buf = [0; 2];
+        BigEndian::write_u16(&mut buf, val);
+        self.socket.write_all(&buf)
+    }
+
+    fn send_fixed_chunk_u32(&mut self, val: u32) -> Result<()> {
+        let mut buf = [0; 4];
+        BigEndian::write_u32(&mut buf, val);
+        self.socket.write_all(&buf)
+    }
+
+    fn send_fixed_chunk_u64(&mut self, val: u64) -> Result<()> {
+        let mut buf = [0; 8];
+        BigEndian::write_u64(&mut buf, val);
+        self.socket.write_all(&buf)
+    }
+
+    fn send_fixed_chunk_u128(&mut self, val: u128) -> Result<()> {
+        let mut buf = [0; 16];
+        BigEndian::write_u128(&mut buf, val);
+        self.socket.write_all(&buf)
+    }
+
     fn send_fixed_chunk_i16(&mut self, val: i16) -> Result<()> {
         let mut buf = [0; 2];
         BigEndian::write_i16(&mut buf, val);
@@ -80,6 +320,16 @@ impl McnpConnectionTraits for McnpConnection {
         return self.socket.write_all(&buf)
     }
 
+    fn send_fixed_chunk_i128(&mut self, val: i128) -> Result<()> {
+        let mut buf = [0; 16];
+        BigEndian::write_i128(&mut buf, val);
+        self.socket.write_all(&buf)
+    }
+
+    fn send_fixed_chunk_bool(&mut self, val: bool) -> Result<()> {
+        self.send_fixed_chunk_u8(if val {1} else {0})
+    }
+
     fn send_fixed_chunk_f32(&mut self, val: f32) -> Result<()> {
         let mut buf = [0; 4];
         BigEndian::write_f32(&mut buf, val);
@@ -102,6 +352,30 @@ impl McnpConnectionTraits for McnpConnection {
         Ok(buf[0])
     }
 
+    fn read_fixed_chunk_u16(&mut self) -> Result<u16> {
+        let mut buf = [0_u8; 2];
+        self.socket.read_exact(&mut buf)?;
+        Ok(BigEndian::read_u16(&buf))
+    }
+
+    fn read_fixed_chunk_u32(&mut self) -> Result<u32> {
+        let mut buf = [0_u8; 4];
+        self.socket.read_exact(&mut buf)?;
+        Ok(BigEndian::read_u32(&buf))
+    }
+
+    fn read_fixed_chunk_u64(&mut self) -> Result<u64> {
+        let mut buf = [0_u8; 8];
+        self.socket.read_exact(&mut buf)?;
+        Ok(BigEndian::read_u64(&buf))
+    }
+
+    fn read_fixed_chunk_u128(&mut self) -> Result<u128> {
+        let mut buf = [0_u8; 16];
+        self.socket.read_exact(&mut buf)?;
+        Ok(BigEndian::read_u128(&buf))
+    }
+
     fn read_fixed_chunk_i16(&mut self) -> Result<i16> {
         let mut buf = [0_u8; 2];
         self.socket.read_exact(&mut buf)?;
@@ -120,6 +394,20 @@ impl McnpConnectionTraits for McnpConnection {
         Ok(BigEndian::read_i64(&buf))
     }
 
+    fn read_fixed_chunk_i128(&mut self) -> Result<i128> {
+        let mut buf = [0_u8; 16];
+        self.socket.read_exact(&mut buf)?;
+        Ok(BigEndian::read_i128(&buf))
+    }
+
+    fn read_fixed_chunk_bool(&mut self) -> Result<bool> {
+        match self.read_fixed_chunk_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(Error::new(ErrorKind::InvalidData, format!("{} is not a bool, only 0 and 1 are", b)))
+        }
+    }
+
     fn read_fixed_chunk_f32(&mut self) -> Result<f32> {
         let mut buf = [0_u8; 4];
         self.socket.read_exact(&mut buf)?;
@@ -143,7 +431,16 @@ impl McnpConnectionTraits for McnpConnection {
     }
 
     fn read_cause(&mut self) -> Result<i32> {
-        self.read_fixed_chunk_i32()
+        if self.timeouts.idle == self.timeouts.read {
+            return self.read_fixed_chunk_i32()
+        }
+        //waiting for a cause is bounded by the idle timeout instead
+        self.socket.set_timeouts(self.timeouts.idle, self.timeouts.write)?;
+        let cause = self.read_fixed_chunk_i32();
+        let restored = self.socket.set_timeouts(self.timeouts.read, self.timeouts.write);
+        let cause = cause?;
+        restored?;
+        Ok(cause)
     }
 
     fn start_variable_chunk(&mut self, chunk_length: i64) -> Result<()> {
@@ -155,17 +452,10 @@ impl McnpConnectionTraits for McnpConnection {
     }
 
     fn read_variable_chunk(&mut self) -> Result<Vec<u8>> {
-        let chunk_length = self.read_fixed_chunk_i64()?;
-        if chunk_length < 0 { // array length was negative. This may indicate that the other side isn't able to fulfill the request
-            Err(Error::new(ErrorKind::InvalidData, "reading a negative amount of bytes is difficult at best"))
-        } else if chunk_length > 100_000_000 { //100 megabyte
-            Err(Error::new(ErrorKind::InvalidData, "chunk length to big. Try using a stream to handle this much data."))
-        } else {
-            let mut buf = vec![0u8; chunk_length as usize];
-            match self.socket.read_exact(&mut buf) {
-                Ok(_) => return Ok(buf),
-                Err(e) => return Err(e)
-            }
+        match self.read_variable_chunk_optional()? {
+            Some(chunk) => Ok(chunk),
+            // array length was negative. This may indicate that the other side isn't able to fulfill the request
+            None => Err(Error::new(ErrorKind::InvalidData, "reading a negative amount of bytes is difficult at best"))
         }
     }
 
@@ -174,50 +464,92 @@ impl McnpConnectionTraits for McnpConnection {
         self.send_variable_chunk_part(chunk)
     }
 
-    fn read_variable_chunk_as_stream(&mut self) -> Result<(Substream<TcpStream>, i64)> {
+    fn send_variable_chunk_optional(&mut self, chunk: Option<&[u8]>) -> Result<()> {
+        match chunk {
+            Some(chunk) => self.send_variable_chunk(chunk),
+            None => self.start_variable_chunk(-1)
+        }
+    }
+
+    fn read_variable_chunk_optional(&mut self) -> Result<Option<Vec<u8>>> {
+        let chunk_length = self.read_fixed_chunk_i64()?;
+        if chunk_length < 0 {
+            Ok(None)
+        } else if chunk_length as u64 > self.max_chunk_length {
+            self.oversized_chunk = Some(chunk_length as u64);
+            Err(Error::new(ErrorKind::InvalidData, ChunkTooLarge { length:chunk_length as u64, limit:self.max_chunk_length }))
+        } else {
+            let mut buf = vec![0u8; chunk_length as usize];
+            self.socket.read_exact(&mut buf)?;
+            Ok(Some(buf))
+        }
+    }
+
+    fn send_variable_utf8(&mut self, string: &str) -> Result<()> {
+        self.send_variable_chunk(string.as_bytes())
+    }
+
+    fn read_variable_utf8(&mut self) -> Result<String> {
+        String::from_utf8(self.read_variable_chunk()?).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    fn send_variable_utf8_optional(&mut self, string: Option<&str>) -> Result<()> {
+        self.send_variable_chunk_optional(string.map(str::as_bytes))
+    }
+
+    fn read_variable_utf8_optional(&mut self) -> Result<Option<String>> {
+        match self.read_variable_chunk_optional()? {
+            Some(chunk) => String::from_utf8(chunk).map(Some).map_err(|e| Error::new(ErrorKind::InvalidData, e)),
+            None => Ok(None)
+        }
+    }
+
+    fn read_variable_chunk_as_stream(&mut self) -> Result<(Substream<S>, i64)> {
+        let socket_clone = self.socket.try_clone()?; //before anything is read, so a failure leaves the chunk unread
         let chunk_length = self.read_fixed_chunk_i64()?;
         if chunk_length < 0 { // array length was negative. This may indicate that the other side isn't able to fulfill the request
             return Err(Error::new(ErrorKind::InvalidData, "reading a negative amount of bytes is difficult at best"));
         }
 
-        let socket_clone = self.socket.try_clone().unwrap();
         return Ok((Substream::new_from_start(socket_clone, chunk_length as u64), chunk_length))
     }
 
     fn send_variable_chunk_from_stream(&mut self, stream: &mut dyn Read, stream_length:i64) -> Result<()> {
-        match self.start_variable_chunk(stream_length) {
-            Ok(_) => {
-                let buffer_size = 1024 * 4;
-                let mut buffer = vec![0u8; buffer_size];
-
-                let byte_counter = 0;
-                while byte_counter < stream_length {
-                    match stream.read(&mut buffer) {
-                        Err(e) => return Err(e),
-                        Ok(read) => {
-                            if read > 0 {
-                                self.send_variable_chunk_part(&buffer[0..read]).expect("error sending chunk part");
-                            } else {
-                                break;
-                            }
-                        }
-                    }
+        self.start_variable_chunk(stream_length)?;
+        if stream_length <= 0 { //a negative length is the none chunk, it has no content
+            return Ok(())
+        }
+        let stream_length = stream_length as u64;
+
+        let mut buffer = vec![0u8; 1024 * 4];
+        let mut byte_counter = 0u64;
+        let mut stream_error = None;
+        while byte_counter < stream_length {
+            let to_read = buffer.len().min((stream_length - byte_counter) as usize);
+            match stream.read(&mut buffer[..to_read]) {
+                Ok(0) => {
+                    stream_error = Some(Error::new(ErrorKind::UnexpectedEof, format!("stream ended after {} of the announced {} bytes", byte_counter, stream_length)));
+                    break
+                },
+                Ok(read) => {
+                    self.send_variable_chunk_part(&buffer[..read])?;
+                    byte_counter += read as u64;
+                },
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => {
+                    stream_error = Some(e);
+                    break
                 }
-                Ok(())
-            },
-            Err(_) => {
-                panic!("error sending start chunk - no proper error handling implemented");
             }
         }
 
-//        match self.start_variable_chunk(chunk.len() as i64) {
-//            Ok(_) => {
-//                return self.send_variable_chunk_part(chunk);
-//            },
-//            Err(e) => {
-//                return Err(e);
-//            },
-//        }
+        if let Some(e) = stream_error {
+            //the other side waits for the rest of the announced length, eof is the only way to tell it that there is none
+            //  (if the transport cannot be shut down, it waits until the connection is dropped)
+            let _ = self.socket.shutdown_write();
+            return Err(e)
+        }
+        Ok(())
     }
 }
 
@@ -0,0 +1,378 @@
+extern crate byteorder;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::io::*;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use self::byteorder::{BigEndian, ByteOrder};
+
+use super::mcnp_connection::McnpConnection;
+use super::mcnp_connection::McnpConnectionTraits;
+use super::mcnp_connection::McnpConcurrentTransport;
+use super::mcnp_connection::McnpTransport;
+
+/// The initial cause with which a client asks for a multiplexed connection (see McnpMultiplexer::negotiate), the server answers with the same cause.
+///   Reserved, it cannot be the initial cause of a normal connection.
+pub const MULTIPLEX_INITIAL_CAUSE:i32 = i32::MIN;
+/// The most content a single frame carries. Longer writes are split into several frames, so other streams can send in between.
+pub const MAX_FRAME_LENGTH:usize = 16 * 1024;
+
+//frames are: stream id (u32), content length (i32), content
+const FRAME_HEADER_LENGTH:usize = 8;
+//as content length: the sender is done with the stream, as stream id with it: the sender is done with the whole connection
+const END_OF_STREAM:i32 = -1;
+const CONNECTION_STREAM_ID:u32 = 0;
+
+/// Several logical connections (streams) over a single transport, so one conversation does not have to wait for another to finish.
+///   Each stream is the transport of a McnpConnection of its own and carries conversations like any other connection (initial cause, then causes).
+///   Clients open streams, the server accepts them (see McnpServer::handle_multiplexed_connection), each side can open and accept.
+///
+/// Content arriving for a stream is buffered until it is read (without limit, unless set_max_buffered_per_stream), so streams should be read from continuously.
+///   Once the multiplexer and all of its streams are dropped, the other side is told and the transport is closed.
+/// The transport is read from in a thread of its own all the time, so its clones have to be usable independently (see McnpConcurrentTransport).
+pub struct McnpMultiplexer {
+    owner:Arc<Owner>
+}
+
+impl McnpMultiplexer {
+    /// Client side: asks the server for multiplexing, by sending MULTIPLEX_INITIAL_CAUSE as the initial cause of con.
+    ///   A server that does not support multiplexing does not answer, so con should have a read timeout (which is removed afterwards, streams are idle for arbitrarily long).
+    pub fn negotiate<T:'static + McnpConcurrentTransport>(mut con:McnpConnection<T>) -> Result<McnpMultiplexer> {
+        con.send_cause(MULTIPLEX_INITIAL_CAUSE)?;
+        if con.read_fixed_chunk_i32()? != MULTIPLEX_INITIAL_CAUSE {
+            return Err(Error::new(ErrorKind::InvalidData, "the server does not support multiplexing"))
+        }
+        McnpMultiplexer::start(con, 1)
+    }
+    /// Server side: agrees to multiplexing, once MULTIPLEX_INITIAL_CAUSE was read as the initial cause of con.
+    pub fn accept_negotiation<T:'static + McnpConcurrentTransport>(mut con:McnpConnection<T>) -> Result<McnpMultiplexer> {
+        con.send_cause(MULTIPLEX_INITIAL_CAUSE)?;
+        McnpMultiplexer::start(con, 2)
+    }
+
+    //the side that negotiated opens odd streams, the other one even streams
+    fn start<T:'static + McnpConcurrentTransport>(con:McnpConnection<T>, first_local_id:u32) -> Result<McnpMultiplexer> {
+        let write_timeout = con.timeouts().write;
+        let transport = con.into_transport();
+        transport.set_timeouts(None, write_timeout)?;
+        let reader = transport.try_clone()?;
+        let core = Arc::new(Core {
+            writer:Mutex::new(Box::new(transport)),
+            state:Mutex::new(State {
+                streams:HashMap::new(),
+                incoming:VecDeque::new(),
+                next_local_id:first_local_id,
+                lowest_unseen_remote_id:3 - first_local_id,
+                seen_remote_ids:HashSet::new(),
+                max_remote_streams:None,
+                max_buffered_per_stream:None,
+                closed:false
+            }),
+            changed:Condvar::new()
+        });
+        let demultiplexer = core.clone();
+        thread::spawn(move || demultiplexer.demultiplex(reader));
+        Ok(McnpMultiplexer { owner:Arc::new(Owner { core }) })
+    }
+
+    /// Opens a stream and sends initial_cause over it, the other side handles it like a new connection.
+    pub fn open(&self, initial_cause:i32) -> Result<McnpConnection<MultiplexedStream>> {
+        let mut con = McnpConnection::new_from_stream(self.open_stream()?);
+        con.send_cause(initial_cause)?;
+        Ok(con)
+    }
+    /// Opens a stream. The other side only learns of it once something is written to it (so streams may be accepted in another order than they were opened in).
+    pub fn open_stream(&self) -> Result<MultiplexedStream> {
+        let core = &self.owner.core;
+        let mut state = core.lock();
+        if state.closed {
+            return Err(Error::new(ErrorKind::ConnectionAborted, "the multiplexed connection is closed"))
+        }
+        let id = state.next_local_id;
+        state.next_local_id += 2;
+        state.streams.insert(id, StreamBuffer::default());
+        Ok(MultiplexedStream::new(id, self.owner.clone()))
+    }
+    /// Waits for the next stream opened by the other side. None once the connection is closed.
+    pub fn accept(&self) -> Option<MultiplexedStream> {
+        let core = &self.owner.core;
+        let mut state = core.lock();
+        loop {
+            if let Some(id) = state.incoming.pop_front() {
+                return Some(MultiplexedStream::new(id, self.owner.clone()))
+            }
+            if state.closed {
+                return None
+            }
+            state = core.changed.wait(state).expect("obtaining lock for multiplexer failed");
+        }
+    }
+    /// Whether the other side is done with the connection or it failed. Streams still read what arrived before.
+    pub fn is_closed(&self) -> bool {
+        self.owner.core.lock().closed
+    }
+
+    /// How many streams opened by the other side may be open at the same time (default unlimited), accepted or not.
+    ///   Further streams are refused: the other side reads eof from them right away, what it writes to them is dropped.
+    pub fn set_max_remote_streams(&self, max_remote_streams:Option<usize>) {
+        self.owner.core.lock().max_remote_streams = max_remote_streams;
+    }
+    /// How much unread content a stream buffers (default unlimited). There is no flow control, the other side can send faster than a stream is read.
+    ///   A stream that would buffer more is reset: the buffered content is dropped and reading it fails (OutOfMemory), the other side reads eof from it.
+    pub fn set_max_buffered_per_stream(&self, max_buffered_per_stream:Option<usize>) {
+        self.owner.core.lock().max_buffered_per_stream = max_buffered_per_stream;
+    }
+}
+
+/// A logical connection of a McnpMultiplexer, the transport of a McnpConnection.
+///   Clones are handles to the same stream. Once all of them are dropped the other side reads eof.
+///   Only supports a read timeout, the write timeout is the one of the multiplexed transport.
+#[derive(Clone)]
+pub struct MultiplexedStream {
+    handle:Arc<StreamHandle>
+}
+impl MultiplexedStream {
+    fn new(id:u32, owner:Arc<Owner>) -> MultiplexedStream {
+        MultiplexedStream {
+            handle:Arc::new(StreamHandle { id, owner, read_timeout:Mutex::new(None) })
+        }
+    }
+    pub fn id(&self) -> u32 {
+        self.handle.id
+    }
+}
+impl Read for MultiplexedStream {
+    fn read(&mut self, buf:&mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0)
+        }
+        let id = self.handle.id;
+        let deadline = self.handle.read_timeout.lock().expect("obtaining lock for stream failed").map(|timeout| Instant::now() + timeout);
+        let core = &self.handle.owner.core;
+        let mut state = core.lock();
+        loop {
+            let closed = state.closed;
+            let stream = state.streams.get_mut(&id).expect("streams are only removed once all handles are dropped");
+            if stream.overflowed {
+                return Err(Error::new(ErrorKind::OutOfMemory, "the other side sent more than the stream buffers, it was reset"))
+            }
+            if !stream.bytes.is_empty() {
+                let read = buf.len().min(stream.bytes.len());
+                for (target, byte) in buf.iter_mut().zip(stream.bytes.drain(..read)) {
+                    *target = byte;
+                }
+                return Ok(read)
+            }
+            if stream.remote_finished {
+                return Ok(0)
+            }
+            if closed {
+                return Err(Error::new(ErrorKind::ConnectionAborted, "the multiplexed connection was closed within the stream"))
+            }
+            state = match deadline {
+                None => core.changed.wait(state).expect("obtaining lock for multiplexer failed"),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::new(ErrorKind::TimedOut, "reading from the stream timed out"))
+                    }
+                    core.changed.wait_timeout(state, deadline - now).expect("obtaining lock for multiplexer failed").0
+                }
+            };
+        }
+    }
+}
+impl Write for MultiplexedStream {
+    fn write(&mut self, buf:&[u8]) -> Result<usize> {
+        if matches!(self.handle.owner.core.lock().streams.get(&self.handle.id), Some(stream) if stream.write_finished) {
+            return Err(Error::new(ErrorKind::BrokenPipe, "the stream was shut down for writing"))
+        }
+        for frame in buf.chunks(MAX_FRAME_LENGTH) {
+            self.handle.owner.core.send_frame(self.handle.id, frame.len() as i32, frame)?;
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> Result<()> {
+        self.handle.owner.core.lock_writer().flush()
+    }
+}
+impl McnpTransport for MultiplexedStream {
+    fn try_clone(&self) -> Result<MultiplexedStream> {
+        Ok(self.clone())
+    }
+    fn set_timeouts(&self, read:Option<Duration>, write:Option<Duration>) -> Result<()> {
+        if write.is_some() {
+            return Err(Error::new(ErrorKind::Unsupported, "the write timeout of a stream is the one of the multiplexed transport"))
+        }
+        *self.handle.read_timeout.lock().expect("obtaining lock for stream failed") = read;
+        Ok(())
+    }
+    fn shutdown_write(&self) -> Result<()> {
+        if self.handle.finish_writing() {
+            self.handle.owner.core.send_frame(self.handle.id, END_OF_STREAM, &[])?;
+        }
+        Ok(())
+    }
+}
+impl McnpConcurrentTransport for MultiplexedStream {}
+
+struct StreamHandle {
+    id:u32,
+    owner:Arc<Owner>,
+    read_timeout:Mutex<Option<Duration>>
+}
+impl StreamHandle {
+    //whether the other side still has to be told that nothing more is written (only true once)
+    fn finish_writing(&self) -> bool {
+        match self.owner.core.lock().streams.get_mut(&self.id) {
+            Some(stream) => !std::mem::replace(&mut stream.write_finished, true),
+            None => false
+        }
+    }
+}
+impl Drop for StreamHandle {
+    fn drop(&mut self) {
+        let core = &self.owner.core;
+        let tell_remote = self.finish_writing();
+        {
+            let mut state = core.lock();
+            if let Some(stream) = state.streams.get_mut(&self.id) {
+                stream.local_finished = true;
+                stream.bytes.clear();
+                if stream.remote_finished {
+                    state.streams.remove(&self.id);
+                }
+            }
+        }
+        if tell_remote {
+            let _ = core.send_frame(self.id, END_OF_STREAM, &[]);
+        }
+    }
+}
+
+//held by the multiplexer and its streams, once all of them are gone the other side is told
+struct Owner {
+    core:Arc<Core>
+}
+impl Drop for Owner {
+    fn drop(&mut self) {
+        let _ = self.core.send_frame(CONNECTION_STREAM_ID, END_OF_STREAM, &[]);
+    }
+}
+
+struct Core {
+    writer:Mutex<Box<dyn Write + Send>>,
+    state:Mutex<State>,
+    changed:Condvar
+}
+struct State {
+    streams:HashMap<u32, StreamBuffer>,
+    //opened by the other side and not yet accepted
+    incoming:VecDeque<u32>,
+    next_local_id:u32,
+    //streams of the other side are new the first time one of their frames arrives, in any order (a later stream may write first)
+    //  all ids below lowest_unseen_remote_id were seen, seen_remote_ids holds those above it
+    lowest_unseen_remote_id:u32,
+    seen_remote_ids:HashSet<u32>,
+    max_remote_streams:Option<usize>,
+    max_buffered_per_stream:Option<usize>,
+    closed:bool
+}
+#[derive(Default)]
+struct StreamBuffer {
+    bytes:VecDeque<u8>,
+    remote_finished:bool,
+    local_finished:bool,
+    //the local side sent END_OF_STREAM (on shutdown_write or once all handles are dropped)
+    write_finished:bool,
+    //more content arrived than max_buffered_per_stream, later content is dropped
+    overflowed:bool
+}
+
+impl Core {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("obtaining lock for multiplexer failed")
+    }
+    fn lock_writer(&self) -> MutexGuard<'_, Box<dyn Write + Send>> {
+        self.writer.lock().expect("obtaining lock for multiplexed transport failed")
+    }
+
+    fn send_frame(&self, id:u32, length:i32, content:&[u8]) -> Result<()> {
+        if self.lock().closed {
+            return Err(Error::new(ErrorKind::ConnectionAborted, "the multiplexed connection is closed"))
+        }
+        let mut frame = vec![0u8; FRAME_HEADER_LENGTH + content.len()];
+        BigEndian::write_u32(&mut frame[..4], id);
+        BigEndian::write_i32(&mut frame[4..FRAME_HEADER_LENGTH], length);
+        frame[FRAME_HEADER_LENGTH..].copy_from_slice(content);
+        self.lock_writer().write_all(&frame) //a frame as a whole, so frames of different streams do not interleave
+    }
+
+    //runs in a thread of its own, hands the content of incoming frames to their streams until the connection is closed
+    fn demultiplex<R:Read>(&self, mut reader:R) {
+        let mut header = [0u8; FRAME_HEADER_LENGTH];
+        let mut content = vec![0u8; MAX_FRAME_LENGTH];
+        while reader.read_exact(&mut header).is_ok() {
+            let id = BigEndian::read_u32(&header[..4]);
+            let length = BigEndian::read_i32(&header[4..]);
+            if id == CONNECTION_STREAM_ID || length < END_OF_STREAM || length as i64 > MAX_FRAME_LENGTH as i64 {
+                break //done or not a valid frame, there is no resyncing either way
+            }
+            let content = &mut content[..length.max(0) as usize];
+            if reader.read_exact(content).is_err() {
+                break
+            }
+
+            let mut state = self.lock();
+            //refused and reset streams are ended towards the other side, once the lock is released
+            let mut end_stream = false;
+            let is_new = id % 2 != state.next_local_id % 2 && id >= state.lowest_unseen_remote_id && state.seen_remote_ids.insert(id);
+            if is_new {
+                let mut lowest_unseen = state.lowest_unseen_remote_id;
+                while state.seen_remote_ids.remove(&lowest_unseen) {
+                    lowest_unseen += 2;
+                }
+                state.lowest_unseen_remote_id = lowest_unseen;
+                let open_remote_streams = state.streams.keys().filter(|open| *open % 2 == id % 2).count();
+                if matches!(state.max_remote_streams, Some(max) if open_remote_streams >= max) {
+                    end_stream = length != END_OF_STREAM; //never inserted, so its later frames are dropped like those of a finished stream
+                } else {
+                    state.streams.insert(id, StreamBuffer::default());
+                    state.incoming.push_back(id);
+                }
+            }
+            let max_buffered = state.max_buffered_per_stream;
+            if let Some(stream) = state.streams.get_mut(&id) { //unknown streams were finished locally
+                if length == END_OF_STREAM {
+                    stream.remote_finished = true;
+                    if stream.local_finished {
+                        state.streams.remove(&id);
+                    }
+                } else if !stream.local_finished && !stream.overflowed {
+                    if matches!(max_buffered, Some(max) if stream.bytes.len() + content.len() > max) {
+                        stream.bytes = VecDeque::new();
+                        stream.overflowed = true;
+                        end_stream = !std::mem::replace(&mut stream.write_finished, true);
+                    } else {
+                        stream.bytes.extend(content.iter());
+                    }
+                }
+            }
+            drop(state);
+            self.changed.notify_all();
+            if end_stream && self.send_frame(id, END_OF_STREAM, &[]).is_err() {
+                break
+            }
+        }
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::*;
+
+use crate::encoding::type_transformer::bytes::DeTransformBytes;
+use crate::encoding::type_transformer::bytes::Transform;
+
+use super::mcnp_connection::McnpConnection;
+use super::mcnp_connection::McnpConnectionTraits;
+use super::mcnp_connection::McnpTransport;
+
+/// A conversation of the form: cause, request, response. Request and response are each one variable chunk, encoded with type_transformer.
+///   Usually declared with mcnp_service!, which also generates the client stub and the server dispatch.
+pub trait McnpProcedure {
+    const CAUSE:i32;
+    type Request:Transform<Vec<u8>> + DeTransformBytes;
+    type Response:Transform<Vec<u8>> + DeTransformBytes;
+}
+
+/// Client side of procedure P: sends its cause and the request, then reads the response.
+///   Err(Other) if the server could not answer the request (it sent the none chunk, see serve), the connection stays usable then.
+pub fn call<P:McnpProcedure, S:McnpTransport>(con:&mut McnpConnection<S>, request:&P::Request) -> Result<P::Response> {
+    con.send_cause(P::CAUSE)?;
+    send_message(con, request)?;
+    match read_message_optional(con)? {
+        Some(response) => Ok(response),
+        None => Err(Error::other(format!("the server could not answer the request of cause {}", P::CAUSE)))
+    }
+}
+
+/// Server side of procedure P, once its cause was read: reads the request, hands it to handler and sends the response.
+///   If the request is invalid or the handler fails the none chunk is sent instead (so the client's call fails, but the connection stays in sync) and the error is returned.
+pub fn serve<P:McnpProcedure, S:McnpTransport, F:FnOnce(P::Request) -> Result<P::Response>>(con:&mut McnpConnection<S>, handler:F) -> Result<()> {
+    let request = con.read_variable_chunk()?;
+    match P::Request::try_detransform_from(&request).and_then(handler) {
+        Ok(response) => send_message(con, &response),
+        Err(e) => {
+            con.send_variable_chunk_optional(None)?;
+            Err(e)
+        }
+    }
+}
+
+/// Sends message as one variable chunk.
+pub fn send_message<T:Transform<Vec<u8>> + ?Sized, S:McnpTransport>(con:&mut McnpConnection<S>, message:&T) -> Result<()> {
+    con.send_variable_chunk(&message.transform())
+}
+/// Reads a message sent with send_message. Err(InvalidData) if the chunk is not an encoding of T (it is consumed regardless).
+pub fn read_message<T:DeTransformBytes, S:McnpTransport>(con:&mut McnpConnection<S>) -> Result<T> {
+    T::try_detransform_from(&con.read_variable_chunk()?)
+}
+fn read_message_optional<T:DeTransformBytes, S:McnpTransport>(con:&mut McnpConnection<S>) -> Result<Option<T>> {
+    match con.read_variable_chunk_optional()? {
+        Some(chunk) => T::try_detransform_from(&chunk).map(Some),
+        None => Ok(None)
+    }
+}
+
+/// Declares an mcnp service: a procedure (see McnpProcedure) per cause, a trait for the server side and a client stub.
+///
+/// ```ignore
+/// mcnp_service! {
+///     /// Does math remotely.
+///     pub service Calculator, client CalculatorClient {
+///         Square = 1 => fn square(i32) -> i64;
+///         Join = 2 => fn join(Vec<String>) -> String;
+///     }
+/// }
+/// ```
+///
+/// generates the unit structs Square and Join implementing McnpProcedure,
+///   the trait Calculator with a method per procedure (fn square(&mut self, request:i32) -> io::Result<i64>) and
+///   dispatch(&mut self, cause, con), which serves the procedure of cause (Ok(false) if the cause is none of the service's),
+///   and the struct CalculatorClient wrapping a McnpConnection, with a method per procedure (fn square(&mut self, request:&i32) -> io::Result<i64>).
+/// The server side usually implements the trait on its ConnectionState and calls dispatch from handle_interaction.
+#[macro_export]
+macro_rules! mcnp_service {
+    (
+        $(#[$meta:meta])*
+        $vis:vis service $service:ident, client $client:ident {
+            $($procedure:ident = $cause:literal => fn $method:ident($request:ty) -> $response:ty;)*
+        }
+    ) => {
+        $(
+            $vis struct $procedure;
+            impl $crate::network::mcnp::mcnp_rpc::McnpProcedure for $procedure {
+                const CAUSE:i32 = $cause;
+                type Request = $request;
+                type Response = $response;
+            }
+        )*
+
+        $(#[$meta])*
+        $vis trait $service {
+            $(fn $method(&mut self, request:$request) -> ::std::io::Result<$response>;)*
+
+            /// Serves the procedure of cause (reads the request, sends the response). Ok(false) if cause is none of this service's procedures, nothing was read then.
+            ///   Err if the connection failed, or if the request was invalid or the handler failed (the client was told then, see mcnp_rpc::serve).
+            fn dispatch<S:$crate::network::mcnp::mcnp_connection::McnpTransport>(&mut self, cause:i32, con:&mut $crate::network::mcnp::mcnp_connection::McnpConnection<S>) -> ::std::io::Result<bool> where Self:Sized {
+                match cause {
+                    $($cause => $crate::network::mcnp::mcnp_rpc::serve::<$procedure, S, _>(con, |request| self.$method(request)).map(|_| true),)*
+                    _ => Ok(false)
+                }
+            }
+        }
+
+        /// Client stub of the service, see mcnp_service!.
+        $vis struct $client<S:$crate::network::mcnp::mcnp_connection::McnpTransport = ::std::net::TcpStream> {
+            con:$crate::network::mcnp::mcnp_connection::McnpConnection<S>
+        }
+        #[allow(dead_code)]
+        impl<S:$crate::network::mcnp::mcnp_connection::McnpTransport> $client<S> {
+            /// con has to have sent its initial cause already.
+            $vis fn new(con:$crate::network::mcnp::mcnp_connection::McnpConnection<S>) -> $client<S> {
+                $client { con }
+            }
+            /// For conversations outside of the service.
+            $vis fn connection(&mut self) -> &mut $crate::network::mcnp::mcnp_connection::McnpConnection<S> {
+                &mut self.con
+            }
+            $vis fn into_connection(self) -> $crate::network::mcnp::mcnp_connection::McnpConnection<S> {
+                self.con
+            }
+            $(
+                $vis fn $method(&mut self, request:&$request) -> ::std::io::Result<$response> {
+                    $crate::network::mcnp::mcnp_rpc::call::<$procedure, S>(&mut self.con, request)
+                }
+            )*
+        }
+    };
+}
@@ -1,13 +1,70 @@
+extern crate socket2;
+#[cfg(feature = "async")]
+extern crate tokio;
+
 use core::str::FromStr;
+use std::collections::HashMap;
+use std::io;
 use std::net::*;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
 use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use self::socket2::Domain;
+use self::socket2::Protocol;
+use self::socket2::Socket;
+use self::socket2::Type;
+#[cfg(feature = "async")]
+use self::tokio::sync::Semaphore;
+#[cfg(feature = "async")]
+use self::tokio::task::JoinSet;
 
 use crate::network::mcnp::mcnp_connection::McnpConnectionTraits;
 
+use super::mcnp_connection::is_timeout;
+use super::mcnp_connection::set_tcp_keepalive;
+use super::mcnp_connection::DEFAULT_MAX_CHUNK_LENGTH;
 use super::mcnp_connection::McnpConnection;
+use super::mcnp_connection::McnpTimeouts;
+use super::mcnp_connection::McnpConcurrentTransport;
+use super::mcnp_connection::McnpTransport;
+use super::mcnp_multiplex::McnpMultiplexer;
+use super::mcnp_multiplex::MultiplexedStream;
+use super::mcnp_multiplex::MULTIPLEX_INITIAL_CAUSE;
+#[cfg(feature = "tls")]
+use super::mcnp_tls::rustls::ServerConfig;
+#[cfg(feature = "tls")]
+use super::mcnp_tls::TlsStream;
+
+/// How many streams a client may have open over one multiplexed connection, unless configured otherwise (see McnpServerBuilder::max_streams_per_connection).
+pub const DEFAULT_MAX_STREAMS_PER_CONNECTION:usize = 32;
+/// How much unread content each stream of a multiplexed connection buffers, unless configured otherwise (see McnpServerBuilder::stream_buffer_limit).
+pub const DEFAULT_STREAM_BUFFER_LIMIT:usize = 4 * 1024 * 1024;
 
 pub struct McnpServer {
-    pub server_socket:TcpListener
+    pub server_socket:TcpListener,
+    shutdown:Arc<ShutdownState>,
+    max_connections:Option<usize>,
+    timeouts:McnpTimeouts,
+    keepalive:Option<Duration>,
+    max_chunk_length:u64,
+    max_streams_per_connection:usize,
+    stream_buffer_limit:usize
 }
 pub trait ConnectionState {
     fn get_initial_cause(self) -> i32;
@@ -20,42 +77,616 @@ impl ConnectionState for DefaultConnectionState {
 }
 
 
+/// Configures the socket of a McnpServer before it is bound, see McnpServer::builder.
+#[derive(Debug, Clone)]
+pub struct McnpServerBuilder {
+    addr:SocketAddr,
+    backlog:i32,
+    max_connections:Option<usize>,
+    timeouts:McnpTimeouts,
+    keepalive:Option<Duration>,
+    max_chunk_length:u64,
+    max_streams_per_connection:usize,
+    stream_buffer_limit:usize
+}
+impl McnpServerBuilder {
+    /// The address to listen on, ipv4 or ipv6 (default 127.0.0.1:0).
+    ///   With port 0 the os chooses a free port, McnpServer::local_addr tells which.
+    pub fn addr(mut self, addr:SocketAddr) -> McnpServerBuilder {
+        self.addr = addr;
+        self
+    }
+    /// Only changes the port of the address.
+    pub fn port(mut self, port:u16) -> McnpServerBuilder {
+        self.addr.set_port(port);
+        self
+    }
+    /// How many connections the os queues up until they are accepted (default 128).
+    pub fn backlog(mut self, backlog:i32) -> McnpServerBuilder {
+        self.backlog = backlog;
+        self
+    }
+
+    /// How many connections are handled at the same time (default unlimited).
+    ///   Connections are handled by a pool of at most this many threads. While all of them are busy, no new connection is accepted,
+    ///   so further clients wait in the backlog (and are refused by the os once it is full).
+    pub fn max_connections(mut self, max_connections:usize) -> McnpServerBuilder {
+        self.max_connections = Some(max_connections.max(1));
+        self
+    }
+    /// The timeouts of every accepted connection (default none).
+    ///   A connection exceeding one is closed, so a stalled or idle client does not occupy a connection forever. The read and write timeouts also apply to establishing a transport (e.g. a tls handshake).
+    pub fn timeouts(mut self, timeouts:McnpTimeouts) -> McnpServerBuilder {
+        self.timeouts = timeouts;
+        self
+    }
+    /// The longest chunk the connections read into memory (default DEFAULT_MAX_CHUNK_LENGTH), see McnpConnection::set_max_chunk_length.
+    ///   Together with max_connections it bounds the memory clients can make the server allocate.
+    pub fn max_chunk_length(mut self, max_chunk_length:u64) -> McnpServerBuilder {
+        self.max_chunk_length = max_chunk_length;
+        self
+    }
+    /// How many streams a client may have open over one multiplexed connection (default DEFAULT_MAX_STREAMS_PER_CONNECTION), further ones are refused.
+    ///   The streams of a connection are handled by a pool of at most this many threads (see run_multiplexed_listener_loop).
+    pub fn max_streams_per_connection(mut self, max_streams_per_connection:usize) -> McnpServerBuilder {
+        self.max_streams_per_connection = max_streams_per_connection.max(1);
+        self
+    }
+    /// How much unread content each stream of a multiplexed connection buffers (default DEFAULT_STREAM_BUFFER_LIMIT), see McnpMultiplexer::set_max_buffered_per_stream.
+    ///   A stream whose client sends more than its handler reads in time is reset.
+    pub fn stream_buffer_limit(mut self, stream_buffer_limit:usize) -> McnpServerBuilder {
+        self.stream_buffer_limit = stream_buffer_limit;
+        self
+    }
+    /// Enables tcp keepalive for every accepted connection (default disabled), see set_tcp_keepalive.
+    pub fn keepalive(mut self, time:Duration) -> McnpServerBuilder {
+        self.keepalive = Some(time);
+        self
+    }
+
+    pub fn build(self) -> io::Result<McnpServer> {
+        let socket = Socket::new(Domain::for_address(self.addr), Type::STREAM, Some(Protocol::TCP))?;
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?; //allows binding again right after a shutdown (on windows it would allow binding a port in use)
+        socket.bind(&self.addr.into())?;
+        socket.listen(self.backlog)?;
+        let mut server = McnpServer::from_listener(socket.into())?;
+        server.max_connections = self.max_connections;
+        server.timeouts = self.timeouts;
+        server.keepalive = self.keepalive;
+        server.max_chunk_length = self.max_chunk_length;
+        server.max_streams_per_connection = self.max_streams_per_connection;
+        server.stream_buffer_limit = self.stream_buffer_limit;
+        Ok(server)
+    }
+}
+
+
 impl McnpServer {
     pub fn new(port:u16 ) -> McnpServer {
-        McnpServer {
-            server_socket:TcpListener::bind(SocketAddr::new(IpAddr::from_str("127.0.0.1").unwrap(), port)).unwrap()
+        McnpServer::builder().port(port).build().unwrap()
+    }
+    pub fn builder() -> McnpServerBuilder {
+        McnpServerBuilder {
+            addr:SocketAddr::new(IpAddr::from_str("127.0.0.1").unwrap(), 0),
+            backlog:128,
+            max_connections:None,
+            timeouts:McnpTimeouts::default(),
+            keepalive:None,
+            max_chunk_length:DEFAULT_MAX_CHUNK_LENGTH,
+            max_streams_per_connection:DEFAULT_MAX_STREAMS_PER_CONNECTION,
+            stream_buffer_limit:DEFAULT_STREAM_BUFFER_LIMIT
+        }
+    }
+    /// A server accepting connections from an already bound listener.
+    pub fn from_listener(server_socket:TcpListener) -> io::Result<McnpServer> {
+        let mut wake_addr = server_socket.local_addr()?;
+        if wake_addr.ip().is_unspecified() { //listening on all interfaces, loopback is one of them
+            wake_addr.set_ip(match wake_addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST)
+            });
+        }
+        Ok(McnpServer {
+            server_socket,
+            shutdown:Arc::new(ShutdownState::new(wake_addr)),
+            max_connections:None,
+            timeouts:McnpTimeouts::default(),
+            keepalive:None,
+            max_chunk_length:DEFAULT_MAX_CHUNK_LENGTH,
+            max_streams_per_connection:DEFAULT_MAX_STREAMS_PER_CONNECTION,
+            stream_buffer_limit:DEFAULT_STREAM_BUFFER_LIMIT
+        })
+    }
+
+    /// The address the server listens on, including the port chosen by the os when bound to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.server_socket.local_addr()
+    }
+    /// Stops the listener loops of this server from another thread, see ShutdownHandle::shutdown.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            state:self.shutdown.clone()
         }
     }
 
     //new_connection_handler:fn(con:&mut McnpConnection)
     pub fn run_server_listener_loop<CT:'static + ConnectionState>(&self, new_connection: fn(initial_cause:i32, con:&mut McnpConnection) -> CT, handle_interaction: fn(typed_cause:(i32, i32), con:&mut McnpConnection, state:CT) -> CT) {
+        self.run_listener_loop_over(Ok, new_connection, handle_interaction)
+    }
+
+    /// Like run_server_listener_loop, but every connection is a TLS session (with the certificate and key from config).
+    ///   Connections that fail the handshake are dropped before new_connection is called.
+    #[cfg(feature = "tls")]
+    pub fn run_tls_server_listener_loop<CT:'static + ConnectionState>(&self, config:Arc<ServerConfig>, new_connection: fn(initial_cause:i32, con:&mut McnpConnection<TlsStream>) -> CT, handle_interaction: fn(typed_cause:(i32, i32), con:&mut McnpConnection<TlsStream>, state:CT) -> CT) {
+        self.run_listener_loop_over(move |stream| TlsStream::accept(stream, config.clone()), new_connection, handle_interaction)
+    }
+
+    /// Runs the listener loop over any transport. establish turns each accepted socket into the transport, in the thread handling the connection.
+    pub fn run_listener_loop_over<T, E, CT>(&self, establish:E, new_connection: fn(initial_cause:i32, con:&mut McnpConnection<T>) -> CT, handle_interaction: fn(typed_cause:(i32, i32), con:&mut McnpConnection<T>, state:CT) -> CT)
+        where T:'static + McnpTransport, E:'static + Fn(TcpStream) -> io::Result<T> + Clone + Send, CT:'static + ConnectionState {
+        let (timeouts, max_chunk_length) = (self.timeouts, self.max_chunk_length);
+        self.run_accept_loop(move |stream, addr| {
+            match establish(stream).and_then(|transport| new_configured_connection(transport, timeouts, max_chunk_length)) {
+                Ok(con) => McnpServer::handle_connection(con, new_connection, handle_interaction),
+                Err(e) => println!("establishing connection with {} failed: {}", addr, e)
+            }
+        })
+    }
+
+    /// Like run_server_listener_loop, but clients multiplex several connections over one socket (see McnpMultiplexer and handle_multiplexed_connection).
+    ///   max_connections limits the sockets, max_streams_per_connection the streams multiplexed over each of them (and stream_buffer_limit what each stream buffers).
+    pub fn run_multiplexed_listener_loop<CT:'static + ConnectionState>(&self, new_connection: fn(initial_cause:i32, con:&mut McnpConnection<MultiplexedStream>) -> CT, handle_interaction: fn(typed_cause:(i32, i32), con:&mut McnpConnection<MultiplexedStream>, state:CT) -> CT) {
+        let (timeouts, max_chunk_length) = (self.timeouts, self.max_chunk_length);
+        let (max_streams, stream_buffer_limit) = (self.max_streams_per_connection, self.stream_buffer_limit);
+        self.run_accept_loop(move |stream, addr| {
+            match new_configured_connection(stream, timeouts, max_chunk_length) {
+                Ok(con) => McnpServer::handle_multiplexed_connection(con, max_streams, stream_buffer_limit, new_connection, handle_interaction),
+                Err(e) => println!("establishing connection with {} failed: {}", addr, e)
+            }
+        })
+    }
+
+    /// Accepts connections until shut down (see shutdown_handle). handle_stream is called for each connection in a thread of the pool (see McnpServerBuilder::max_connections).
+    ///   The listener loops are built on this, it is public for servers with their own notion of a connection (e.g. RbaeServer).
+    ///   The streams already have the read and write timeouts and keepalive of the server, the idle timeout and chunk limit are up to the McnpConnection.
+    pub fn run_accept_loop<H>(&self, handle_stream:H) where H:'static + Fn(TcpStream, SocketAddr) + Clone + Send {
         //shorter and cooler, but doesn't do "error handling" or show the addr
 //        for Ok(incoming_stream) in self.server_socket.incoming() {
 //          thread::spawn( move || (new_connection_handler) ( &mut McnpConnection::new_from_stream(stream) ));
 //        }
-        loop {
+        let _running = self.shutdown.loop_started();
+        let mut pool = WorkerPool::new(self.max_connections);
+        while !self.shutdown.is_shutting_down() {
+            if !pool.wait_for_idle_worker(&|| self.shutdown.is_shutting_down()) {
+                break
+            }
             let new_con = self.server_socket.accept();
+            if self.shutdown.is_shutting_down() {
+                break //new_con is (likely) the connection that woke the loop up
+            }
             match new_con {
                 Err(e) => println!("couldn't get client: {:?}", e),
                 Ok((stream, addr)) => {
-                    println!("new connection from: {} - spawning thread to handle", addr);
-                    thread::spawn( move || {
-                        let mut con = McnpConnection::new_from_stream(stream);
-                        let initial_cause = con.read_cause().expect("reading initial cause failed");
-                        let mut state = (new_connection)(initial_cause, &mut con);
-                        loop {
-                            let concrete_cause = match con.read_cause() {
-                                Ok(cause) => cause,
-                                Err(e) => {
-                                    println!("{}",e.to_string());
-                                    break;
-                                }
-                            };
-                            state = (handle_interaction)((initial_cause, concrete_cause), &mut con, state);
+                    println!("new connection from: {} - handing it to the worker pool", addr);
+                    let registration = match configure_accepted(&stream, self.timeouts, self.keepalive).and_then(|_| self.shutdown.register_connection(&stream)) {
+                        Ok(registration) => registration,
+                        Err(e) => {
+                            println!("couldn't get client: {:?}", e);
+                            continue
                         }
-                    });
+                    };
+                    let handle_stream = handle_stream.clone();
+                    pool.execute(Box::new(move || {
+                        let _registration = registration;
+                        handle_stream(stream, addr)
+                    }));
+                }
+            }
+        }
+
+        self.shutdown.close_connections();
+        pool.join();
+    }
+
+    /// Async counterpart of run_server_listener_loop, for programs already running a tokio runtime (with io and time enabled).
+    ///   Connections are accepted on the runtime, but handled in its blocking thread pool (a McnpConnection blocks), at most max_connections at a time.
+    ///   Returns once shut down, since ShutdownHandle::shutdown blocks it has to be called outside of the runtime's threads.
+    #[cfg(feature = "async")]
+    pub async fn run_async_listener_loop<CT:'static + ConnectionState>(self, new_connection: fn(initial_cause:i32, con:&mut McnpConnection) -> CT, handle_interaction: fn(typed_cause:(i32, i32), con:&mut McnpConnection, state:CT) -> CT) -> io::Result<()> {
+        let McnpServer { server_socket, shutdown, max_connections, timeouts, keepalive, max_chunk_length, .. } = self;
+        server_socket.set_nonblocking(true)?;
+        let listener = self::tokio::net::TcpListener::from_std(server_socket)?;
+        let _running = shutdown.loop_started();
+        let permits = Arc::new(Semaphore::new(max_connections.unwrap_or(Semaphore::MAX_PERMITS)));
+        let mut handlers = JoinSet::new();
+        while !shutdown.is_shutting_down() {
+            while handlers.try_join_next().is_some() {}
+            let permit = match self::tokio::time::timeout(Duration::from_millis(100), permits.clone().acquire_owned()).await {
+                Ok(permit) => permit.expect("semaphore is never closed"),
+                Err(_) => continue //all connections busy, check for shutdown again
+            };
+            let new_con = listener.accept().await;
+            if shutdown.is_shutting_down() {
+                break //new_con is (likely) the connection that woke the loop up
+            }
+            let (stream, addr) = match new_con.and_then(|(stream, addr)| Ok((stream.into_std()?, addr))) {
+                Ok(new_con) => new_con,
+                Err(e) => {
+                    println!("couldn't get client: {:?}", e);
+                    continue
+                }
+            };
+            println!("new connection from: {} - handing it to the blocking pool", addr);
+            let registration = match stream.set_nonblocking(false).and_then(|_| configure_accepted(&stream, timeouts, keepalive)).and_then(|_| shutdown.register_connection(&stream)) {
+                Ok(registration) => registration,
+                Err(e) => {
+                    println!("couldn't get client: {:?}", e);
+                    continue
+                }
+            };
+            handlers.spawn_blocking(move || {
+                let (_permit, _registration) = (permit, registration);
+                match new_configured_connection(stream, timeouts, max_chunk_length) {
+                    Ok(con) => McnpServer::handle_connection(con, new_connection, handle_interaction),
+                    Err(e) => println!("establishing connection with {} failed: {}", addr, e)
+                }
+            });
+        }
+
+        shutdown.close_connections();
+        while handlers.join_next().await.is_some() {}
+        Ok(())
+    }
+
+    /// Like run_server_listener_loop, but for clients connecting over a unix domain socket (see McnpClient::new_unix).
+    ///   The connections are handled by a pool like the ones of run_accept_loop (see McnpServerBuilder::max_connections) and get the timeouts and chunk limit of the server.
+    ///   Shutting the server down (see shutdown_handle) stops this loop too, as long as the listener is bound to a path (an unnamed listener cannot be woken up).
+    #[cfg(unix)]
+    pub fn run_unix_listener_loop<CT:'static + ConnectionState>(&self, listener:&UnixListener, new_connection: fn(initial_cause:i32, con:&mut McnpConnection<UnixStream>) -> CT, handle_interaction: fn(typed_cause:(i32, i32), con:&mut McnpConnection<UnixStream>, state:CT) -> CT) {
+        let wake_path = listener.local_addr().ok().and_then(|addr| addr.as_pathname().map(|path| path.to_path_buf()));
+        let _running = self.shutdown.unix_loop_started(wake_path);
+        let mut pool = WorkerPool::new(self.max_connections);
+        let (timeouts, max_chunk_length) = (self.timeouts, self.max_chunk_length);
+        while !self.shutdown.is_shutting_down() {
+            if !pool.wait_for_idle_worker(&|| self.shutdown.is_shutting_down()) {
+                break
+            }
+            let new_con = listener.accept();
+            if self.shutdown.is_shutting_down() {
+                break //new_con is (likely) the connection that woke the loop up
+            }
+            match new_con {
+                Err(e) => println!("couldn't get client: {:?}", e),
+                Ok((stream, _)) => {
+                    println!("new unix connection - handing it to the worker pool");
+                    let registration = match self.shutdown.register_unix_connection(&stream) {
+                        Ok(registration) => registration,
+                        Err(e) => {
+                            println!("couldn't get client: {:?}", e);
+                            continue
+                        }
+                    };
+                    pool.execute(Box::new(move || {
+                        let _registration = registration;
+                        match new_configured_connection(stream, timeouts, max_chunk_length) {
+                            Ok(con) => McnpServer::handle_connection(con, new_connection, handle_interaction),
+                            Err(e) => println!("establishing unix connection failed: {}", e)
+                        }
+                    }));
+                }
+            }
+        }
+
+        self.shutdown.close_connections();
+        pool.join();
+    }
+
+    /// Runs the server side of a single connection (in the calling thread), until the connection is closed.
+    ///   For connections not accepted by one of the listener loops, for example one side of a memory_pipe.
+    pub fn handle_connection<T:McnpTransport, CT:ConnectionState>(mut con:McnpConnection<T>, new_connection: fn(initial_cause:i32, con:&mut McnpConnection<T>) -> CT, handle_interaction: fn(typed_cause:(i32, i32), con:&mut McnpConnection<T>, state:CT) -> CT) {
+        let initial_cause = match con.read_cause() {
+            Ok(cause) => cause,
+            Err(ref e) if is_timeout(e) => {
+                println!("connection timed out waiting for the initial cause - closing it");
+                return
+            },
+            Err(e) => {
+                println!("reading initial cause failed: {}", e);
+                return
+            }
+        };
+        let mut state = (new_connection)(initial_cause, &mut con);
+        loop {
+            let concrete_cause = match con.read_cause() {
+                Ok(cause) => cause,
+                Err(ref e) if is_timeout(e) => {
+                    println!("connection timed out (idle or stalled) - closing it");
+                    break;
+                },
+                Err(e) => {
+                    println!("{}",e.to_string());
+                    break;
+                }
+            };
+            state = (handle_interaction)((initial_cause, concrete_cause), &mut con, state);
+            if let Some(length) = con.pending_oversized_chunk() {
+                //the next cause would be read from the middle of the chunk
+                println!("the handler left a chunk of {} bytes (over the limit) unread - closing connection", length);
+                break;
+            }
+        }
+    }
+
+    /// Runs the server side of a multiplexed connection (in the calling thread), until the client is done with it.
+    ///   The client has to ask for multiplexing (see McnpMultiplexer::negotiate), otherwise the connection is closed.
+    ///   Every stream the client opens is handled like a connection of its own (see handle_connection), by a pool of at most max_streams threads.
+    ///   The client may have at most max_streams streams open, further ones are refused. Each buffers at most stream_buffer_limit unread bytes, see McnpMultiplexer.
+    ///   The streams get the idle and read timeouts and the chunk limit of con, its write timeout applies to the whole connection.
+    pub fn handle_multiplexed_connection<T:'static + McnpConcurrentTransport, CT:'static + ConnectionState>(mut con:McnpConnection<T>, max_streams:usize, stream_buffer_limit:usize, new_connection: fn(initial_cause:i32, con:&mut McnpConnection<MultiplexedStream>) -> CT, handle_interaction: fn(typed_cause:(i32, i32), con:&mut McnpConnection<MultiplexedStream>, state:CT) -> CT) {
+        match con.read_cause() {
+            Ok(MULTIPLEX_INITIAL_CAUSE) => {},
+            Ok(initial_cause) => {
+                println!("client did not ask for multiplexing (initial cause {}) - closing connection", initial_cause);
+                return
+            },
+            Err(e) => {
+                println!("reading initial cause failed: {}", e);
+                return
+            }
+        }
+        let stream_timeouts = McnpTimeouts { write:None, ..con.timeouts() };
+        let max_chunk_length = con.max_chunk_length();
+        let multiplexer = match McnpMultiplexer::accept_negotiation(con) {
+            Ok(multiplexer) => multiplexer,
+            Err(e) => {
+                println!("agreeing to multiplexing failed: {}", e);
+                return
+            }
+        };
+
+        multiplexer.set_max_remote_streams(Some(max_streams));
+        multiplexer.set_max_buffered_per_stream(Some(stream_buffer_limit));
+
+        let mut pool = WorkerPool::new(Some(max_streams));
+        while pool.wait_for_idle_worker(&|| multiplexer.is_closed()) {
+            let stream = match multiplexer.accept() {
+                Some(stream) => stream,
+                None => break
+            };
+            pool.execute(Box::new(move || {
+                match new_configured_connection(stream, stream_timeouts, max_chunk_length) {
+                    Ok(con) => McnpServer::handle_connection(con, new_connection, handle_interaction),
+                    Err(e) => println!("configuring multiplexed stream failed: {}", e)
                 }
+            }));
+        }
+        pool.join();
+    }
+}
+
+
+
+/// Stops the listener loops of a McnpServer, obtained through McnpServer::shutdown_handle.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    state:Arc<ShutdownState>
+}
+impl ShutdownHandle {
+    /// Stops accepting connections, closes the open connections and waits until the threads handling them have finished.
+    ///   Must not be called from within a connection handler of the same server, it would wait for itself.
+    ///   A listener loop started after the shutdown returns immediately.
+    pub fn shutdown(&self) {
+        self.state.shutting_down.store(true, Ordering::SeqCst);
+        let mut running_loops = self.state.running_loops.lock().expect("obtaining lock failed");
+        while *running_loops > 0 {
+            //accept only returns for a new connection, so a loop blocked in it is woken up by connecting
+            let _ = TcpStream::connect_timeout(&self.state.wake_addr, Duration::from_secs(1));
+            #[cfg(unix)]
+            for wake_path in self.state.unix_wake_paths.lock().expect("obtaining lock failed").iter() {
+                let _ = UnixStream::connect(wake_path);
+            }
+            running_loops = self.state.loop_finished.wait_timeout(running_loops, Duration::from_millis(100)).expect("obtaining lock failed").0;
+        }
+    }
+    pub fn is_shut_down(&self) -> bool {
+        self.state.is_shutting_down()
+    }
+}
+
+struct ShutdownState {
+    shutting_down:AtomicBool,
+    wake_addr:SocketAddr,
+    running_loops:Mutex<usize>,
+    loop_finished:Condvar,
+    //the paths of the unix listeners of running loops, connected to like wake_addr
+    #[cfg(unix)]
+    unix_wake_paths:Mutex<Vec<PathBuf>>,
+    //clones of the sockets of open connections, to close them on shutdown
+    connections:Mutex<HashMap<usize, ConnectionSocket>>,
+    next_connection_id:AtomicUsize
+}
+impl ShutdownState {
+    fn new(wake_addr:SocketAddr) -> ShutdownState {
+        ShutdownState {
+            shutting_down:AtomicBool::new(false),
+            wake_addr,
+            running_loops:Mutex::new(0),
+            loop_finished:Condvar::new(),
+            #[cfg(unix)]
+            unix_wake_paths:Mutex::new(Vec::new()),
+            connections:Mutex::new(HashMap::new()),
+            next_connection_id:AtomicUsize::new(0)
+        }
+    }
+    fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    fn loop_started(self:&Arc<Self>) -> RunningLoop {
+        *self.running_loops.lock().expect("obtaining lock failed") += 1;
+        RunningLoop { state:self.clone(), #[cfg(unix)] wake_path:None }
+    }
+    #[cfg(unix)]
+    fn unix_loop_started(self:&Arc<Self>, wake_path:Option<PathBuf>) -> RunningLoop {
+        if let Some(ref wake_path) = wake_path {
+            self.unix_wake_paths.lock().expect("obtaining lock failed").push(wake_path.clone());
+        }
+        *self.running_loops.lock().expect("obtaining lock failed") += 1;
+        RunningLoop { state:self.clone(), wake_path }
+    }
+    fn register_connection(self:&Arc<Self>, stream:&TcpStream) -> io::Result<RegisteredConnection> {
+        Ok(self.register(ConnectionSocket::Tcp(stream.try_clone()?)))
+    }
+    #[cfg(unix)]
+    fn register_unix_connection(self:&Arc<Self>, stream:&UnixStream) -> io::Result<RegisteredConnection> {
+        Ok(self.register(ConnectionSocket::Unix(stream.try_clone()?)))
+    }
+    fn register(self:&Arc<Self>, socket:ConnectionSocket) -> RegisteredConnection {
+        let id = self.next_connection_id.fetch_add(1, Ordering::SeqCst);
+        self.connections.lock().expect("obtaining lock failed").insert(id, socket);
+        RegisteredConnection { state:self.clone(), id }
+    }
+    //blocked reads on the connections return, so their handlers finish
+    fn close_connections(&self) {
+        for connection in self.connections.lock().expect("obtaining lock failed").values() {
+            let _ = connection.shutdown();
+        }
+    }
+}
+
+//a clone of the socket of an open connection
+enum ConnectionSocket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream)
+}
+impl ConnectionSocket {
+    fn shutdown(&self) -> io::Result<()> {
+        match self {
+            ConnectionSocket::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            ConnectionSocket::Unix(stream) => stream.shutdown(Shutdown::Both)
+        }
+    }
+}
+
+struct RunningLoop {
+    state:Arc<ShutdownState>,
+    #[cfg(unix)]
+    wake_path:Option<PathBuf>
+}
+impl Drop for RunningLoop {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(ref wake_path) = self.wake_path {
+            self.state.unix_wake_paths.lock().expect("obtaining lock failed").retain(|path| path != wake_path);
+        }
+        *self.state.running_loops.lock().expect("obtaining lock failed") -= 1;
+        self.state.loop_finished.notify_all();
+    }
+}
+
+struct RegisteredConnection {
+    state:Arc<ShutdownState>,
+    id:usize
+}
+impl Drop for RegisteredConnection {
+    fn drop(&mut self) {
+        self.state.connections.lock().expect("obtaining lock failed").remove(&self.id);
+    }
+}
+
+
+
+//the per connection settings of the server, applied before the stream is handed on
+fn configure_accepted(stream:&TcpStream, timeouts:McnpTimeouts, keepalive:Option<Duration>) -> io::Result<()> {
+    stream.set_read_timeout(timeouts.read)?;
+    stream.set_write_timeout(timeouts.write)?;
+    if keepalive.is_some() {
+        set_tcp_keepalive(stream, keepalive)?;
+    }
+    Ok(())
+}
+
+//the settings of the server that the connection itself applies
+fn new_configured_connection<T:McnpTransport>(transport:T, timeouts:McnpTimeouts, max_chunk_length:u64) -> io::Result<McnpConnection<T>> {
+    let mut con = McnpConnection::new_from_stream(transport);
+    con.set_timeouts(timeouts)?;
+    con.set_max_chunk_length(max_chunk_length);
+    Ok(con)
+}
+
+
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads handling the connections of a listener loop. Idle threads are reused, new ones are only started while none is idle (up to max_workers).
+struct WorkerPool {
+    max_workers:Option<usize>,
+    jobs:Option<mpsc::Sender<Job>>,
+    job_receiver:Arc<Mutex<mpsc::Receiver<Job>>>,
+    //jobs handed to the pool and not yet finished
+    busy:Arc<(Mutex<usize>, Condvar)>,
+    workers:Vec<JoinHandle<()>>
+}
+impl WorkerPool {
+    fn new(max_workers:Option<usize>) -> WorkerPool {
+        let (jobs, job_receiver) = mpsc::channel();
+        WorkerPool {
+            max_workers,
+            jobs:Some(jobs),
+            job_receiver:Arc::new(Mutex::new(job_receiver)),
+            busy:Arc::new((Mutex::new(0), Condvar::new())),
+            workers:Vec::new()
+        }
+    }
+
+    //false if cancelled (e.g. the server is shut down) while waiting
+    fn wait_for_idle_worker(&self, cancelled:&dyn Fn() -> bool) -> bool {
+        let max_workers = match self.max_workers {
+            Some(max_workers) => max_workers,
+            None => return true
+        };
+        let (busy, job_finished) = &*self.busy;
+        let mut busy = busy.lock().expect("obtaining lock failed");
+        while *busy >= max_workers {
+            if cancelled() {
+                return false
             }
+            busy = job_finished.wait_timeout(busy, Duration::from_millis(100)).expect("obtaining lock failed").0;
+        }
+        true
+    }
+
+    fn execute(&mut self, job:Job) {
+        let busy = {
+            let mut busy = self.busy.0.lock().expect("obtaining lock failed");
+            *busy += 1;
+            *busy
+        };
+        if busy > self.workers.len() { //none idle
+            let (job_receiver, busy) = (self.job_receiver.clone(), self.busy.clone());
+            self.workers.push(thread::spawn(move || loop {
+                let job = match job_receiver.lock().expect("obtaining lock failed").recv() {
+                    Ok(job) => job,
+                    Err(_) => break //pool joined
+                };
+                //a panicking handler ends its connection, not the worker
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                let (busy, job_finished) = &*busy;
+                *busy.lock().expect("obtaining lock failed") -= 1;
+                job_finished.notify_all();
+            }));
         }
+        self.jobs.as_ref().expect("pool already joined").send(job).expect("all workers are gone");
     }
-}
\ No newline at end of file
+
+    //waits for the jobs to finish
+    fn join(&mut self) {
+        drop(self.jobs.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
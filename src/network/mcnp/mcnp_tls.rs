@@ -0,0 +1,174 @@
+pub extern crate rustls;
+
+use core::str::FromStr;
+use std::convert::TryFrom;
+use std::io::*;
+use std::net::*;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::time::Duration;
+
+use self::rustls::pki_types::ServerName;
+use self::rustls::ClientConfig;
+use self::rustls::ClientConnection;
+use self::rustls::Connection;
+use self::rustls::ServerConfig;
+use self::rustls::ServerConnection;
+
+use super::mcnp_connection::McnpConcurrentTransport;
+use super::mcnp_connection::McnpTransport;
+
+/// A TLS session over a TcpStream, usable as the transport of a McnpConnection.
+///   Clones (see McnpTransport::try_clone) share the session. They can read and write independently: a clone waiting in a read does not keep another from writing.
+///   Reads of different clones take turns, so do writes.
+///
+/// The handshake is completed when connecting or accepting, so certificate problems surface there and not at the first read.
+/// The crate of the configs is re-exported as mcnp_tls::rustls, to make sure the versions match.
+#[derive(Debug)]
+pub struct TlsStream {
+    session:Arc<Mutex<TlsSession>>,
+    reading:Arc<Mutex<TlsReading>>
+}
+
+#[derive(Debug)]
+struct TlsSession {
+    connection:Connection,
+    socket:TcpStream
+}
+//the socket is read from without holding the session (a clone of it), what was read is handed to the session afterwards
+#[derive(Debug)]
+struct TlsReading {
+    socket:TcpStream,
+    received:Vec<u8>,
+    eof:bool
+}
+
+impl TlsStream {
+    /// Connects to addr:port and verifies that the server presents a certificate for server_name (according to config).
+    pub fn connect(addr:&str, port:u16, config:Arc<ClientConfig>, server_name:&str) -> Result<TlsStream> {
+        let ip = IpAddr::from_str(addr).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        let socket = TcpStream::connect(SocketAddr::new(ip, port))?;
+        TlsStream::connect_over(socket, config, server_name)
+    }
+    /// Starts a TLS session as the client over an already connected socket.
+    pub fn connect_over(mut socket:TcpStream, config:Arc<ClientConfig>, server_name:&str) -> Result<TlsStream> {
+        let server_name = ServerName::try_from(server_name.to_string()).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        let mut connection = ClientConnection::new(config, server_name).map_err(Error::other)?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut socket)?;
+        }
+        TlsStream::new(Connection::Client(connection), socket)
+    }
+
+    /// Starts a TLS session as the server over a socket returned by TcpListener::accept.
+    pub fn accept(mut socket:TcpStream, config:Arc<ServerConfig>) -> Result<TlsStream> {
+        let mut connection = ServerConnection::new(config).map_err(Error::other)?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut socket)?;
+        }
+        TlsStream::new(Connection::Server(connection), socket)
+    }
+
+    fn new(connection:Connection, socket:TcpStream) -> Result<TlsStream> {
+        let reading = TlsReading { socket:socket.try_clone()?, received:Vec::new(), eof:false };
+        Ok(TlsStream {
+            session:Arc::new(Mutex::new(TlsSession { connection, socket })),
+            reading:Arc::new(Mutex::new(reading))
+        })
+    }
+    fn lock(&self) -> MutexGuard<'_, TlsSession> {
+        self.session.lock().expect("obtaining lock for tls session failed")
+    }
+
+    /// The address of the other side of the underlying socket.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.lock().socket.peer_addr()
+    }
+}
+
+impl TlsSession {
+    //hands what the session wants to send (content, alerts, answers to key updates) to the socket
+    fn write_pending(&mut self) -> Result<()> {
+        while self.connection.wants_write() {
+            self.connection.write_tls(&mut self.socket)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf:&mut [u8]) -> Result<usize> {
+        let mut reading = self.reading.lock().expect("obtaining lock for tls reading failed");
+        let reading = &mut *reading;
+        loop {
+            {
+                let mut session = self.lock();
+                while session.connection.wants_read() && !reading.received.is_empty() {
+                    let mut received = &reading.received[..];
+                    session.connection.read_tls(&mut received)?;
+                    let consumed = reading.received.len() - received.len();
+                    reading.received.drain(..consumed);
+                    let processed = session.connection.process_new_packets();
+                    session.write_pending()?;
+                    processed.map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                }
+                if reading.eof && session.connection.wants_read() {
+                    session.connection.read_tls(&mut &[][..])?; //the session decides whether the eof was expected (close_notify) or not
+                }
+                match session.connection.reader().read(buf) {
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}, //nothing decrypted yet
+                    result => return result
+                }
+            }
+            let mut chunk = [0u8; 16 * 1024];
+            let read = reading.socket.read(&mut chunk)?; //waits without holding the session
+            reading.eof = read == 0;
+            reading.received.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+impl Write for TlsStream {
+    fn write(&mut self, buf:&[u8]) -> Result<usize> {
+        let mut session = self.lock();
+        let written = session.connection.writer().write(buf)?;
+        session.write_pending()?;
+        Ok(written)
+    }
+    fn flush(&mut self) -> Result<()> {
+        let mut session = self.lock();
+        session.connection.writer().flush()?;
+        session.write_pending()?;
+        session.socket.flush()
+    }
+}
+
+impl McnpTransport for TlsStream {
+    fn try_clone(&self) -> Result<TlsStream> {
+        Ok(TlsStream {
+            session:self.session.clone(),
+            reading:self.reading.clone()
+        })
+    }
+    fn set_timeouts(&self, read:Option<Duration>, write:Option<Duration>) -> Result<()> {
+        let session = self.lock();
+        session.socket.set_read_timeout(read)?; //shared with the clone of the socket that is read from
+        session.socket.set_write_timeout(write)
+    }
+    //close_notify first, so the other side reads a regular eof
+    fn shutdown_write(&self) -> Result<()> {
+        let mut session = self.lock();
+        session.connection.send_close_notify();
+        session.write_pending()?;
+        session.socket.shutdown(Shutdown::Write)
+    }
+}
+impl McnpConcurrentTransport for TlsStream {}
+
+//tells the other side that the session ended on purpose (without it, the other side sees an unexpected eof)
+impl Drop for TlsSession {
+    fn drop(&mut self) {
+        self.connection.send_close_notify();
+        while self.connection.wants_write() && self.connection.write_tls(&mut self.socket).is_ok() {}
+    }
+}
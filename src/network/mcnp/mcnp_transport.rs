@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+use std::io::*;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+use super::mcnp_connection::McnpConcurrentTransport;
+use super::mcnp_connection::McnpTransport;
+
+/// Makes any duplex stream usable as the transport of a McnpConnection (see McnpConnection::new_from_read_write).
+///   Clones share the stream, they take turns using it.
+///   So a clone must not be read from in one thread while another thread waits for the original (and vice versa), which is why it cannot be multiplexed (see McnpConcurrentTransport).
+#[derive(Debug)]
+pub struct SharedStream<T:Read + Write + Send> {
+    stream:Arc<Mutex<T>>
+}
+impl<T:Read + Write + Send> SharedStream<T> {
+    pub fn new(stream:T) -> SharedStream<T> {
+        SharedStream {
+            stream:Arc::new(Mutex::new(stream))
+        }
+    }
+    /// Exclusive access to the wrapped stream.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.stream.lock().expect("obtaining lock for stream failed")
+    }
+}
+impl<T:Read + Write + Send> Read for SharedStream<T> {
+    fn read(&mut self, buf:&mut [u8]) -> Result<usize> {
+        self.lock().read(buf)
+    }
+}
+impl<T:Read + Write + Send> Write for SharedStream<T> {
+    fn write(&mut self, buf:&[u8]) -> Result<usize> {
+        self.lock().write(buf)
+    }
+    fn flush(&mut self) -> Result<()> {
+        self.lock().flush()
+    }
+}
+impl<T:Read + Write + Send> McnpTransport for SharedStream<T> {
+    fn try_clone(&self) -> Result<SharedStream<T>> {
+        Ok(SharedStream {
+            stream:self.stream.clone()
+        })
+    }
+}
+
+
+
+/// Creates two connected in memory streams. What is written to one, can be read from the other.
+///   Useful to run both sides of a mcnp conversation in one process, without sockets (for example in tests).
+///
+/// Writes never block, the written bytes are buffered until read.
+/// Once every handle (including clones) to one side is dropped, the other side reads eof and its writes fail with BrokenPipe.
+///   shutdown_write only ends one direction: the other side reads eof, but can still write.
+pub fn memory_pipe() -> (MemoryStream, MemoryStream) {
+    let a_to_b = Arc::new(PipeBuffer::default());
+    let b_to_a = Arc::new(PipeBuffer::default());
+    (
+        MemoryStream { end:Arc::new(PipeEnd { incoming:b_to_a.clone(), outgoing:a_to_b.clone() }) },
+        MemoryStream { end:Arc::new(PipeEnd { incoming:a_to_b, outgoing:b_to_a }) }
+    )
+}
+
+/// One side of a memory_pipe.
+#[derive(Debug, Clone)]
+pub struct MemoryStream {
+    end:Arc<PipeEnd>
+}
+
+#[derive(Debug, Default)]
+struct PipeBuffer {
+    state:Mutex<PipeState>,
+    readable:Condvar
+}
+#[derive(Debug, Default)]
+struct PipeState {
+    bytes:VecDeque<u8>,
+    writer_gone:bool,
+    reader_gone:bool
+}
+impl PipeBuffer {
+    fn lock(&self) -> MutexGuard<'_, PipeState> {
+        self.state.lock().expect("obtaining lock for pipe failed")
+    }
+}
+
+#[derive(Debug)]
+struct PipeEnd {
+    incoming:Arc<PipeBuffer>,
+    outgoing:Arc<PipeBuffer>
+}
+impl Drop for PipeEnd {
+    fn drop(&mut self) {
+        self.outgoing.lock().writer_gone = true;
+        self.outgoing.readable.notify_all();
+        self.incoming.lock().reader_gone = true;
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf:&mut [u8]) -> Result<usize> {
+        let incoming = &self.end.incoming;
+        let mut state = incoming.lock();
+        while state.bytes.is_empty() && !state.writer_gone && !buf.is_empty() {
+            state = incoming.readable.wait(state).expect("obtaining lock for pipe failed");
+        }
+        let read = buf.len().min(state.bytes.len());
+        for (target, byte) in buf.iter_mut().zip(state.bytes.drain(..read)) {
+            *target = byte;
+        }
+        Ok(read)
+    }
+}
+impl Write for MemoryStream {
+    fn write(&mut self, buf:&[u8]) -> Result<usize> {
+        let outgoing = &self.end.outgoing;
+        let mut state = outgoing.lock();
+        if state.writer_gone {
+            return Err(Error::new(ErrorKind::BrokenPipe, "the memory pipe was shut down for writing"))
+        }
+        if state.reader_gone {
+            return Err(Error::new(ErrorKind::BrokenPipe, "the other side of the memory pipe was dropped"))
+        }
+        state.bytes.extend(buf);
+        outgoing.readable.notify_all();
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+impl McnpTransport for MemoryStream {
+    fn try_clone(&self) -> Result<MemoryStream> {
+        Ok(self.clone())
+    }
+    fn shutdown_write(&self) -> Result<()> {
+        let outgoing = &self.end.outgoing;
+        outgoing.lock().writer_gone = true;
+        outgoing.readable.notify_all();
+        Ok(())
+    }
+}
+impl McnpConcurrentTransport for MemoryStream {}
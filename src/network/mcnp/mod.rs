@@ -1,8 +1,13 @@
 ///:author jokrey
 
 pub mod mcnp_connection;
+pub mod mcnp_transport;
 pub mod mcnp_server;
 pub mod mcnp_client;
+pub mod mcnp_rpc;
+pub mod mcnp_multiplex;
+#[cfg(feature = "tls")]
+pub mod mcnp_tls;
 
 #[cfg(test)]
-mod tests;
\ No newline at end of file
+pub(crate) mod tests;
\ No newline at end of file
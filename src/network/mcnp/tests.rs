@@ -1,10 +1,24 @@
+use std::io::Cursor;
+use std::io::ErrorKind;
 use std::io::Read;
+#[cfg(feature = "tls")]
+use std::net::TcpListener;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
+use std::time::Instant;
 
 use crate::network::mcnp::mcnp_client::*;
 use crate::network::mcnp::mcnp_connection::*;
+use crate::network::mcnp::mcnp_multiplex::*;
+use crate::network::mcnp::mcnp_rpc::*;
 use crate::network::mcnp::mcnp_server::*;
+use crate::network::mcnp::mcnp_transport::*;
+#[cfg(feature = "tls")]
+use crate::network::mcnp::mcnp_tls::rustls;
+#[cfg(feature = "tls")]
+use crate::network::mcnp::mcnp_tls::TlsStream;
 
 //Test is standardized:
 //
@@ -17,43 +31,45 @@ fn mcnp_test() {
 
         let server = McnpServer::new(PORT);
         println!("mcnp_test: ");
-        server.run_server_listener_loop(|initial_cause, con| {
-            if initial_cause == 1 {
-                let b = con.read_fixed_chunk_u8().unwrap();
-                con.send_fixed_chunk_u8(b).unwrap();
-            }
-            DefaultConnectionState{initial_cause}
-        }, |typed_cause, con, state| {
-            if typed_cause == (1, 12) { //yes, pattern matching :) :)
-                con.read_fixed_chunk_u8().unwrap();
-                let int32 = con.read_fixed_chunk_i32().unwrap();
-                con.read_fixed_chunk_i64().unwrap();
-                con.read_fixed_chunk_f64().unwrap();
-                con.send_fixed_chunk_i32(int32).unwrap();
-            } else if typed_cause == (1, 8) {
-                let bytes = con.read_variable_chunk().unwrap();
-                con.send_variable_chunk(&bytes).unwrap();
-                let str = String::from_utf8(con.read_variable_chunk().unwrap()).unwrap();
-                con.send_variable_chunk(str.as_bytes()).unwrap();
-                match con.read_variable_chunk() {
-                    Ok(_) => {},
-                    Err(_) => {}, // will throw error on unwrap - is "unsupported" none type
-                };
-            }
-            return state;
-        });
+        server.run_server_listener_loop(test_server_new_connection, test_server_handle_interaction);
 
     });
 
     thread::sleep(Duration::from_millis(2000));
 
-    run_client_test(PORT);
+    run_client_test(&mut McnpClient::new("127.0.0.1", PORT));
 
 //    server_thread.join().expect("woah. this never failed before");//if left out the thread is automatically dropped. Which can be cool, but also annoying.
 }
 
-fn run_client_test(port: u16) {
-    let mut client = McnpClient::new("127.0.0.1", port);
+fn test_server_new_connection<S:McnpTransport>(initial_cause:i32, con:&mut McnpConnection<S>) -> DefaultConnectionState {
+    if initial_cause == 1 {
+        let b = con.read_fixed_chunk_u8().unwrap();
+        con.send_fixed_chunk_u8(b).unwrap();
+    }
+    DefaultConnectionState{initial_cause}
+}
+fn test_server_handle_interaction<S:McnpTransport>(typed_cause:(i32, i32), con:&mut McnpConnection<S>, state:DefaultConnectionState) -> DefaultConnectionState {
+    if typed_cause == (1, 12) { //yes, pattern matching :) :)
+        con.read_fixed_chunk_u8().unwrap();
+        let int32 = con.read_fixed_chunk_i32().unwrap();
+        con.read_fixed_chunk_i64().unwrap();
+        con.read_fixed_chunk_f64().unwrap();
+        con.send_fixed_chunk_i32(int32).unwrap();
+    } else if typed_cause == (1, 8) {
+        let bytes = con.read_variable_chunk().unwrap();
+        con.send_variable_chunk(&bytes).unwrap();
+        let str = String::from_utf8(con.read_variable_chunk().unwrap()).unwrap();
+        con.send_variable_chunk(str.as_bytes()).unwrap();
+        match con.read_variable_chunk() {
+            Ok(_) => {},
+            Err(_) => {}, // will throw error on unwrap - is "unsupported" none type
+        };
+    }
+    return state;
+}
+
+fn run_client_test<S:McnpTransport>(client: &mut McnpConnection<S>) {
 
     client.send_cause(1).unwrap();
     let send_b = 133u8;
@@ -92,4 +108,826 @@ fn run_client_test(port: u16) {
     assert_eq!(send_string, &read_string);
 
     client.start_variable_chunk(-1).unwrap();
-}
\ No newline at end of file
+}
+
+#[test]
+fn mcnp_server_builder_and_shutdown_test() {
+    let server = McnpServer::builder().port(0).backlog(16).build().unwrap();
+    let addr = server.local_addr().unwrap();
+    assert_ne!(0, addr.port());
+    let shutdown_handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run_server_listener_loop(test_server_new_connection, test_server_handle_interaction));
+
+    let mut client = McnpClient::new("127.0.0.1", addr.port());
+    run_client_test(&mut client);
+    let mut idle_client = McnpClient::new("127.0.0.1", addr.port()); //its handler is blocked reading the initial cause
+
+    shutdown_handle.shutdown(); //returns once the connection threads are joined
+    assert!(shutdown_handle.is_shut_down());
+    server_thread.join().unwrap(); //the loop returned
+    assert!(client.read_fixed_chunk_u8().is_err());
+    assert!(idle_client.read_fixed_chunk_u8().is_err());
+    assert!(std::net::TcpStream::connect(addr).is_err()); //the listener is gone with the loop
+    shutdown_handle.shutdown(); //nothing left to stop
+
+    //ipv6, if the machine supports it
+    if let Ok(server) = McnpServer::builder().addr("[::1]:0".parse().unwrap()).build() {
+        let addr = server.local_addr().unwrap();
+        assert!(addr.is_ipv6());
+        let shutdown_handle = server.shutdown_handle();
+        let server_thread = thread::spawn(move || server.run_server_listener_loop(test_server_new_connection, test_server_handle_interaction));
+        run_client_test(&mut McnpConnection::new_from_stream(std::net::TcpStream::connect(addr).unwrap()));
+        shutdown_handle.shutdown();
+        server_thread.join().unwrap();
+    }
+}
+
+#[test]
+fn mcnp_server_max_connections_test() {
+    let server = McnpServer::builder().max_connections(2).build().unwrap();
+    let port = server.local_addr().unwrap().port();
+    let shutdown_handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run_server_listener_loop(test_server_new_connection, test_server_handle_interaction));
+
+    let first = connect_and_echo(port, 1);
+    let mut second = connect_and_echo(port, 2);
+    let mut third = connect_and_wait_for_echo(port, 3);
+    drop(first); //frees its worker for the third client
+    assert_eq!(3, third.read_fixed_chunk_u8().unwrap());
+
+    drop(third);
+    let mut fourth = connect_and_echo(port, 4); //a worker is reused
+    shutdown_handle.shutdown();
+    server_thread.join().unwrap();
+    assert!(second.read_fixed_chunk_u8().is_err());
+    assert!(fourth.read_fixed_chunk_u8().is_err());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn mcnp_async_server_test() {
+    let server = McnpServer::builder().max_connections(1).build().unwrap();
+    let port = server.local_addr().unwrap().port();
+    let shutdown_handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_io().enable_time().build().unwrap();
+        runtime.block_on(server.run_async_listener_loop(test_server_new_connection, test_server_handle_interaction)).unwrap();
+    });
+
+    run_client_test(&mut McnpClient::new("127.0.0.1", port));
+    let first = connect_and_echo(port, 1);
+    let mut second = connect_and_wait_for_echo(port, 2);
+    drop(first);
+    assert_eq!(2, second.read_fixed_chunk_u8().unwrap());
+
+    shutdown_handle.shutdown(); //called outside of the runtime
+    server_thread.join().unwrap();
+    assert!(second.read_fixed_chunk_u8().is_err());
+}
+
+#[test]
+fn mcnp_timeouts_test() {
+    let timeouts = McnpTimeouts { read:Some(Duration::from_millis(300)), write:Some(Duration::from_secs(1)), idle:Some(Duration::from_millis(600)) };
+    let server = McnpServer::builder().timeouts(timeouts).keepalive(Duration::from_secs(60)).build().unwrap();
+    let port = server.local_addr().unwrap().port();
+    let shutdown_handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run_server_listener_loop(|initial_cause, _| DefaultConnectionState{initial_cause}, echo_chunk_handle_interaction));
+
+    //idle after a conversation
+    let mut idle_client = McnpClient::new("127.0.0.1", port);
+    idle_client.send_cause(1).unwrap();
+    idle_client.send_cause(8).unwrap();
+    idle_client.send_variable_chunk(&[1, 2, 3]).unwrap();
+    assert_eq!(vec![1, 2, 3], idle_client.read_variable_chunk().unwrap());
+    assert_closed_by_server(&mut idle_client);
+
+    //stalled in the middle of a chunk
+    let mut stalled_client = McnpClient::new("127.0.0.1", port);
+    stalled_client.send_cause(1).unwrap();
+    stalled_client.send_cause(8).unwrap();
+    stalled_client.start_variable_chunk(100).unwrap();
+    stalled_client.send_variable_chunk_part(&[7u8; 10]).unwrap();
+    assert_closed_by_server(&mut stalled_client);
+
+    shutdown_handle.shutdown();
+    server_thread.join().unwrap();
+
+    let (pipe_end, _other_end) = memory_pipe();
+    let mut client = McnpConnection::new_from_stream(pipe_end);
+    assert!(client.set_timeouts(McnpTimeouts::default()).is_ok());
+    assert_eq!(ErrorKind::Unsupported, client.set_timeouts(timeouts).unwrap_err().kind()); //memory pipes never time out
+    assert_eq!(McnpTimeouts::default(), client.timeouts());
+    let only_idle = McnpTimeouts { idle:Some(Duration::from_millis(600)), ..McnpTimeouts::default() };
+    assert_eq!(ErrorKind::Unsupported, client.set_timeouts(only_idle).unwrap_err().kind());
+    assert_eq!(McnpTimeouts::default(), client.timeouts());
+}
+
+#[test]
+fn mcnp_keepalive_test() {
+    let server = McnpServer::builder().build().unwrap();
+    let client = McnpConnection::new_from_stream(std::net::TcpStream::connect(server.local_addr().unwrap()).unwrap());
+    client.set_keepalive(Some(Duration::from_secs(30))).unwrap();
+    assert!(socket2::SockRef::from(client.transport()).keepalive().unwrap());
+    client.set_keepalive(None).unwrap();
+    assert!(!socket2::SockRef::from(client.transport()).keepalive().unwrap());
+}
+
+fn echo_chunk_handle_interaction<S:McnpTransport>(_:(i32, i32), con:&mut McnpConnection<S>, state:DefaultConnectionState) -> DefaultConnectionState {
+    if let Ok(chunk) = con.read_variable_chunk() { //fails for a client that stalls in the middle of the chunk
+        con.send_variable_chunk(&chunk).unwrap();
+    }
+    state
+}
+fn assert_closed_by_server(client:&mut McnpConnection) {
+    let start = Instant::now();
+    client.transport().set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let e = client.read_fixed_chunk_u8().unwrap_err();
+    //reset if the server closed with unread bytes left
+    assert!(e.kind() == ErrorKind::UnexpectedEof || e.kind() == ErrorKind::ConnectionReset, "{:?}", e);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+//sends initial cause 1, the server echoes the byte as soon as it handles the connection
+fn connect_and_echo(port:u16, b:u8) -> McnpConnection {
+    let mut client = McnpClient::new("127.0.0.1", port);
+    client.send_cause(1).unwrap();
+    client.send_fixed_chunk_u8(b).unwrap();
+    assert_eq!(b, client.read_fixed_chunk_u8().unwrap());
+    client
+}
+//like connect_and_echo, but asserts that the server does not handle the connection yet (the echo is still to be read)
+fn connect_and_wait_for_echo(port:u16, b:u8) -> McnpConnection {
+    let mut client = McnpClient::new("127.0.0.1", port); //the os accepts it into the backlog
+    client.send_cause(1).unwrap();
+    client.send_fixed_chunk_u8(b).unwrap();
+    client.transport().set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    assert!(client.read_fixed_chunk_u8().is_err());
+    client.transport().set_read_timeout(None).unwrap();
+    client
+}
+
+#[test]
+fn mcnp_memory_pipe_test() {
+    let (client_end, server_end) = memory_pipe();
+    let server_thread = thread::spawn(move || {
+        McnpServer::handle_connection(McnpConnection::new_from_stream(server_end), test_server_new_connection, test_server_handle_interaction);
+    });
+
+    let mut client = McnpConnection::new_from_stream(client_end);
+    run_client_test(&mut client);
+    drop(client);
+    server_thread.join().unwrap(); //the server sees eof once the client is dropped
+}
+
+#[test]
+fn mcnp_multiplexed_test() {
+    let (client_end, server_end) = memory_pipe();
+    let server_thread = thread::spawn(move || {
+        McnpServer::handle_multiplexed_connection(McnpConnection::new_from_stream(server_end), DEFAULT_MAX_STREAMS_PER_CONNECTION, DEFAULT_STREAM_BUFFER_LIMIT, test_server_new_connection, test_server_handle_interaction);
+    });
+    let multiplexer = McnpMultiplexer::negotiate(McnpConnection::new_from_stream(client_end)).unwrap();
+
+    //a conversation stuck in the middle of a large chunk
+    let mut uploading = multiplexer.open(1).unwrap();
+    uploading.send_fixed_chunk_u8(11).unwrap();
+    assert_eq!(11, uploading.read_fixed_chunk_u8().unwrap());
+    uploading.send_cause(8).unwrap();
+    uploading.start_variable_chunk(100000).unwrap();
+    uploading.send_variable_chunk_part(&[5u8; 60000]).unwrap();
+
+    //does not keep others from having theirs, concurrently
+    let clients:Vec<_> = (0..4).map(|_| {
+        let mut con = McnpConnection::new_from_stream(multiplexer.open_stream().unwrap());
+        thread::spawn(move || run_client_test(&mut con))
+    }).collect();
+    for client in clients {
+        client.join().unwrap();
+    }
+
+    uploading.send_variable_chunk_part(&[5u8; 40000]).unwrap();
+    assert_eq!(vec![5u8; 100000], uploading.read_variable_chunk().unwrap());
+    uploading.send_variable_chunk(b"done").unwrap();
+    assert_eq!(b"done".to_vec(), uploading.read_variable_chunk().unwrap());
+    uploading.start_variable_chunk(-1).unwrap();
+
+    assert!(!multiplexer.is_closed());
+
+    drop(uploading);
+    drop(multiplexer); //tells the server it is done
+    server_thread.join().unwrap();
+
+    //a client not asking for multiplexing is closed
+    let (client_end, server_end) = memory_pipe();
+    let server_thread = thread::spawn(move || {
+        McnpServer::handle_multiplexed_connection(McnpConnection::new_from_stream(server_end), DEFAULT_MAX_STREAMS_PER_CONNECTION, DEFAULT_STREAM_BUFFER_LIMIT, test_server_new_connection, test_server_handle_interaction);
+    });
+    let mut plain_client = McnpConnection::new_from_stream(client_end);
+    plain_client.send_cause(1).unwrap();
+    server_thread.join().unwrap();
+    assert!(plain_client.read_fixed_chunk_u8().is_err());
+}
+
+#[test]
+fn mcnp_multiplexed_server_test() {
+    let timeouts = McnpTimeouts { read:None, write:Some(Duration::from_secs(1)), idle:Some(Duration::from_millis(500)) };
+    let server = McnpServer::builder().timeouts(timeouts).build().unwrap();
+    let port = server.local_addr().unwrap().port();
+    let shutdown_handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run_multiplexed_listener_loop(test_server_new_connection, test_server_handle_interaction));
+
+    let multiplexer = McnpMultiplexer::negotiate(McnpClient::new("127.0.0.1", port)).unwrap();
+    let mut first = McnpConnection::new_from_stream(multiplexer.open_stream().unwrap());
+    let mut second = McnpConnection::new_from_stream(multiplexer.open_stream().unwrap());
+    run_client_test(&mut first);
+    run_client_test(&mut second);
+
+    //the idle timeout applies to each stream, the connection outlives it
+    thread::sleep(Duration::from_millis(1000));
+    assert!(first.read_fixed_chunk_u8().is_err());
+    assert!(!multiplexer.is_closed());
+    run_client_test(&mut McnpConnection::new_from_stream(multiplexer.open_stream().unwrap()));
+
+    let mut idle = multiplexer.open(1).unwrap();
+    assert!(idle.transport().set_timeouts(None, Some(Duration::from_secs(1))).is_err()); //write timeouts are the connection's
+    shutdown_handle.shutdown();
+    server_thread.join().unwrap();
+    assert!(idle.read_fixed_chunk_u8().is_err());
+    assert!(multiplexer.is_closed());
+    assert!(multiplexer.open_stream().is_err());
+}
+
+#[test]
+fn mcnp_multiplexed_shutdown_write_test() {
+    let (client_end, server_end) = memory_pipe();
+    let server_thread = thread::spawn(move || {
+        let mut con = McnpConnection::new_from_stream(server_end);
+        assert_eq!(MULTIPLEX_INITIAL_CAUSE, con.read_cause().unwrap());
+        McnpMultiplexer::accept_negotiation(con).unwrap()
+    });
+    let client = McnpMultiplexer::negotiate(McnpConnection::new_from_stream(client_end)).unwrap();
+    let server = server_thread.join().unwrap();
+
+    //an aborted chunk ends the sending direction of its stream only
+    let mut sending = McnpConnection::new_from_stream(client.open_stream().unwrap());
+    assert_eq!(ErrorKind::UnexpectedEof, sending.send_variable_chunk_from_stream(&mut &[5u8; 10][..], 20).unwrap_err().kind());
+    let mut receiving = McnpConnection::new_from_stream(server.accept().unwrap());
+    assert_eq!(ErrorKind::UnexpectedEof, receiving.read_variable_chunk().unwrap_err().kind());
+    assert_eq!(ErrorKind::BrokenPipe, sending.send_fixed_chunk_u8(1).unwrap_err().kind());
+    receiving.send_fixed_chunk_u8(2).unwrap();
+    assert_eq!(2, sending.read_fixed_chunk_u8().unwrap());
+
+    let mut other = McnpConnection::new_from_stream(client.open_stream().unwrap());
+    other.send_fixed_chunk_u8(3).unwrap();
+    assert_eq!(3, McnpConnection::new_from_stream(server.accept().unwrap()).read_fixed_chunk_u8().unwrap());
+    assert!(!client.is_closed());
+}
+
+#[test]
+fn mcnp_multiplexed_stream_order_test() {
+    let (client_end, server_end) = memory_pipe();
+    let server_thread = thread::spawn(move || {
+        let mut con = McnpConnection::new_from_stream(server_end);
+        assert_eq!(MULTIPLEX_INITIAL_CAUSE, con.read_cause().unwrap());
+        McnpMultiplexer::accept_negotiation(con).unwrap()
+    });
+    let client = McnpMultiplexer::negotiate(McnpConnection::new_from_stream(client_end)).unwrap();
+    let server = server_thread.join().unwrap();
+
+    //streams are accepted in the order they first write, not the order they were opened in
+    let mut first = McnpConnection::new_from_stream(client.open_stream().unwrap());
+    let mut second = McnpConnection::new_from_stream(client.open_stream().unwrap());
+    second.send_fixed_chunk_u8(2).unwrap();
+    let mut second_accepted = McnpConnection::new_from_stream(server.accept().unwrap());
+    assert_eq!(2, second_accepted.read_fixed_chunk_u8().unwrap());
+    first.send_fixed_chunk_u8(1).unwrap();
+    let mut first_accepted = McnpConnection::new_from_stream(server.accept().unwrap());
+    assert_eq!(1, first_accepted.read_fixed_chunk_u8().unwrap());
+
+    first_accepted.send_fixed_chunk_u8(3).unwrap();
+    second_accepted.send_fixed_chunk_u8(4).unwrap();
+    assert_eq!(3, first.read_fixed_chunk_u8().unwrap());
+    assert_eq!(4, second.read_fixed_chunk_u8().unwrap());
+
+    //streams seen before are not accepted again, even once they are finished
+    drop(first);
+    drop(first_accepted);
+    second.send_fixed_chunk_u8(5).unwrap();
+    assert_eq!(5, second_accepted.read_fixed_chunk_u8().unwrap());
+    let mut third = McnpConnection::new_from_stream(client.open_stream().unwrap());
+    third.send_fixed_chunk_u8(6).unwrap();
+    assert_eq!(6, McnpConnection::new_from_stream(server.accept().unwrap()).read_fixed_chunk_u8().unwrap());
+}
+
+#[test]
+fn mcnp_multiplexed_stream_limits_test() {
+    let server = McnpServer::builder().max_streams_per_connection(2).build().unwrap();
+    let port = server.local_addr().unwrap().port();
+    let shutdown_handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run_multiplexed_listener_loop(test_server_new_connection, test_server_handle_interaction));
+    let multiplexer = McnpMultiplexer::negotiate(McnpClient::new("127.0.0.1", port)).unwrap();
+
+    let mut first = McnpConnection::new_from_stream(multiplexer.open_stream().unwrap());
+    run_client_test(&mut first);
+    let mut second = McnpConnection::new_from_stream(multiplexer.open_stream().unwrap());
+    run_client_test(&mut second);
+
+    //more streams than the cap are refused while the others are open
+    let mut refused = multiplexer.open(1).unwrap();
+    refused.send_fixed_chunk_u8(3).unwrap();
+    assert_eq!(ErrorKind::UnexpectedEof, refused.read_fixed_chunk_u8().unwrap_err().kind());
+    second.send_cause(12).unwrap(); //the others are unaffected
+    second.send_fixed_chunk_u8(1).unwrap();
+    second.send_fixed_chunk_i32(5).unwrap();
+    second.send_fixed_chunk_i64(6).unwrap();
+    second.send_fixed_chunk_f64(7.0).unwrap();
+    assert_eq!(5, second.read_fixed_chunk_i32().unwrap());
+    assert!(!multiplexer.is_closed());
+
+    //once one is closed, a new one is accepted
+    drop(first);
+    thread::sleep(Duration::from_millis(200));
+    let mut replacement = McnpConnection::new_from_stream(multiplexer.open_stream().unwrap());
+    run_client_test(&mut replacement);
+
+    shutdown_handle.shutdown();
+    server_thread.join().unwrap();
+
+    //a stream that would buffer more than the limit is reset, the connection and other streams remain usable
+    let (client_end, server_end) = memory_pipe();
+    let server_thread = thread::spawn(move || {
+        let mut con = McnpConnection::new_from_stream(server_end);
+        assert_eq!(MULTIPLEX_INITIAL_CAUSE, con.read_cause().unwrap());
+        McnpMultiplexer::accept_negotiation(con).unwrap()
+    });
+    let client = McnpMultiplexer::negotiate(McnpConnection::new_from_stream(client_end)).unwrap();
+    let server = server_thread.join().unwrap();
+    server.set_max_buffered_per_stream(Some(1000));
+
+    let mut flooding = McnpConnection::new_from_stream(client.open_stream().unwrap());
+    flooding.send_variable_chunk(&[7u8; 2000]).unwrap();
+    let mut flooded = McnpConnection::new_from_stream(server.accept().unwrap());
+    assert_eq!(ErrorKind::OutOfMemory, flooded.read_variable_chunk().unwrap_err().kind());
+    assert_eq!(ErrorKind::BrokenPipe, flooded.send_fixed_chunk_u8(1).unwrap_err().kind());
+    assert_eq!(ErrorKind::UnexpectedEof, flooding.read_fixed_chunk_u8().unwrap_err().kind());
+
+    let mut other = McnpConnection::new_from_stream(client.open_stream().unwrap());
+    other.send_variable_chunk(&[8u8; 900]).unwrap();
+    assert_eq!(vec![8u8; 900], McnpConnection::new_from_stream(server.accept().unwrap()).read_variable_chunk().unwrap());
+    assert!(!server.is_closed());
+}
+
+#[test]
+fn mcnp_chunk_stream_does_not_read_past_its_end_test() {
+    let (a, b) = memory_pipe();
+    let mut sender = McnpConnection::new_from_stream(a);
+    let mut receiver = McnpConnection::new_from_stream(b);
+    sender.send_variable_chunk(&[1u8; 1000]).unwrap();
+    sender.send_variable_chunk(&[2u8; 10]).unwrap();
+    sender.send_fixed_chunk_i32(77).unwrap();
+    drop(sender); //everything sent is still readable, then eof
+
+    let (mut first, first_length) = receiver.read_variable_chunk_as_stream().unwrap();
+    let mut first_content = Vec::new();
+    first.read_to_end(&mut first_content).unwrap();
+    assert_eq!(1000, first_length);
+    assert_eq!(vec![1u8; 1000], first_content);
+    assert_eq!(vec![2u8; 10], receiver.read_variable_chunk().unwrap());
+    assert_eq!(77, receiver.read_fixed_chunk_i32().unwrap());
+    assert!(receiver.read_fixed_chunk_u8().is_err());
+    assert!(receiver.send_fixed_chunk_u8(1).is_err()); //broken pipe
+}
+
+#[test]
+fn mcnp_chunk_stream_of_uncloneable_transport_test() {
+    let (a, b) = memory_pipe();
+    let mut sender = McnpConnection::new_from_stream(a);
+    let mut receiver = McnpConnection::new_from_stream(UncloneableStream(b));
+    sender.send_variable_chunk(&[1u8; 10]).unwrap();
+
+    assert_eq!(ErrorKind::Unsupported, receiver.read_variable_chunk_as_stream().err().expect("cloning cannot succeed").kind());
+    assert_eq!(vec![1u8; 10], receiver.read_variable_chunk().unwrap()); //nothing was read
+}
+
+struct UncloneableStream(MemoryStream);
+impl Read for UncloneableStream {
+    fn read(&mut self, buf:&mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+impl std::io::Write for UncloneableStream {
+    fn write(&mut self, buf:&[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+impl McnpTransport for UncloneableStream {
+    fn try_clone(&self) -> std::io::Result<UncloneableStream> {
+        Err(std::io::Error::new(ErrorKind::Unsupported, "cannot be cloned"))
+    }
+}
+
+#[test]
+fn mcnp_chunk_too_large_test() {
+    let (a, b) = memory_pipe();
+    let mut sender = McnpConnection::new_from_stream(a);
+    let mut receiver = McnpConnection::new_from_stream(b);
+    assert_eq!(DEFAULT_MAX_CHUNK_LENGTH, receiver.max_chunk_length());
+    receiver.set_max_chunk_length(10);
+    sender.send_variable_chunk(&[1u8; 20]).unwrap();
+    sender.send_variable_chunk(&[2u8; 10]).unwrap();
+    sender.send_variable_chunk(&[3u8; 30]).unwrap();
+    sender.send_fixed_chunk_i32(77).unwrap();
+
+    let e = receiver.read_variable_chunk().unwrap_err();
+    assert_eq!(ErrorKind::InvalidData, e.kind());
+    assert_eq!(Some(&ChunkTooLarge { length:20, limit:10 }), ChunkTooLarge::of(&e));
+    assert_eq!(Some(20), receiver.pending_oversized_chunk());
+    assert_eq!(20, receiver.skip_oversized_chunk().unwrap());
+    assert_eq!(None, receiver.pending_oversized_chunk());
+    assert_eq!(vec![2u8; 10], receiver.read_variable_chunk().unwrap()); //exactly the limit
+
+    assert!(receiver.read_variable_chunk().is_err());
+    let (mut stream, length) = receiver.read_oversized_chunk_as_stream().unwrap();
+    let mut content = Vec::new();
+    stream.read_to_end(&mut content).unwrap();
+    assert_eq!((30, vec![3u8; 30]), (length, content));
+    assert_eq!(77, receiver.read_fixed_chunk_i32().unwrap());
+
+    assert_eq!(0, receiver.skip_oversized_chunk().unwrap());
+    assert_eq!(ErrorKind::InvalidInput, receiver.read_oversized_chunk_as_stream().unwrap_err().kind());
+    assert!(ChunkTooLarge::of(&std::io::Error::new(ErrorKind::InvalidData, "other")).is_none());
+}
+
+#[test]
+fn mcnp_more_fixed_chunks_utf8_and_optionals_test() {
+    let (a, b) = memory_pipe();
+    let mut sender = McnpConnection::new_from_stream(a);
+    let mut receiver = McnpConnection::new_from_stream(b);
+
+    sender.send_fixed_chunk_u16(u16::MAX - 1).unwrap();
+    sender.send_fixed_chunk_u32(u32::MAX - 2).unwrap();
+    sender.send_fixed_chunk_u64(u64::MAX - 3).unwrap();
+    sender.send_fixed_chunk_u128(u128::MAX - 4).unwrap();
+    sender.send_fixed_chunk_i128(i128::MIN + 5).unwrap();
+    sender.send_fixed_chunk_bool(true).unwrap();
+    sender.send_fixed_chunk_bool(false).unwrap();
+    assert_eq!(u16::MAX - 1, receiver.read_fixed_chunk_u16().unwrap());
+    assert_eq!(u32::MAX - 2, receiver.read_fixed_chunk_u32().unwrap());
+    assert_eq!(u64::MAX - 3, receiver.read_fixed_chunk_u64().unwrap());
+    assert_eq!(u128::MAX - 4, receiver.read_fixed_chunk_u128().unwrap());
+    assert_eq!(i128::MIN + 5, receiver.read_fixed_chunk_i128().unwrap());
+    assert!(receiver.read_fixed_chunk_bool().unwrap());
+    assert!(!receiver.read_fixed_chunk_bool().unwrap());
+
+    //byte level: big endian, like the signed types
+    sender.send_fixed_chunk_u32(0x01020304).unwrap();
+    sender.send_fixed_chunk_i128(-2).unwrap();
+    sender.send_fixed_chunk_bool(true).unwrap();
+    assert_eq!(vec![1, 2, 3, 4], receiver.read_fixed_chunk_u8_arr(4).unwrap());
+    let mut minus_two = vec![0xffu8; 16];
+    minus_two[15] = 0xfe;
+    assert_eq!(minus_two, receiver.read_fixed_chunk_u8_arr(16).unwrap());
+    assert_eq!(1, receiver.read_fixed_chunk_u8().unwrap());
+    sender.send_fixed_chunk_u8(2).unwrap();
+    assert_eq!(ErrorKind::InvalidData, receiver.read_fixed_chunk_bool().unwrap_err().kind());
+
+    sender.send_variable_utf8("häßlich ✓").unwrap();
+    sender.send_variable_utf8("").unwrap();
+    sender.send_variable_chunk(&[0xff, 0xfe]).unwrap();
+    sender.send_fixed_chunk_i32(77).unwrap();
+    assert_eq!("häßlich ✓", receiver.read_variable_utf8().unwrap());
+    assert_eq!("", receiver.read_variable_utf8().unwrap());
+    assert_eq!(ErrorKind::InvalidData, receiver.read_variable_utf8().unwrap_err().kind());
+    assert_eq!(77, receiver.read_fixed_chunk_i32().unwrap()); //the invalid chunk was consumed
+
+    sender.send_variable_chunk_optional(Some(&[1, 2])).unwrap();
+    sender.send_variable_chunk_optional(None).unwrap();
+    sender.send_variable_utf8_optional(Some("x")).unwrap();
+    sender.send_variable_utf8_optional(None).unwrap();
+    sender.send_variable_chunk_optional(None).unwrap();
+    assert_eq!(Some(vec![1, 2]), receiver.read_variable_chunk_optional().unwrap());
+    assert_eq!(None, receiver.read_variable_chunk_optional().unwrap());
+    assert_eq!(Some("x".to_string()), receiver.read_variable_utf8_optional().unwrap());
+    assert_eq!(None, receiver.read_variable_utf8_optional().unwrap());
+    assert!(receiver.read_variable_chunk().is_err()); //the none chunk, as before
+
+    receiver.set_max_chunk_length(1);
+    sender.send_variable_chunk_optional(Some(&[1, 2])).unwrap();
+    assert!(ChunkTooLarge::of(&receiver.read_variable_chunk_optional().unwrap_err()).is_some());
+    assert_eq!(2, receiver.skip_oversized_chunk().unwrap());
+}
+
+crate::mcnp_service! {
+    /// Does math remotely.
+    service Calculator, client CalculatorClient {
+        Square = 3 => fn square(i32) -> i64;
+        Join = 4 => fn join(Vec<String>) -> String;
+        Halve = 5 => fn halve(i64) -> i64;
+    }
+}
+struct CalculatorState {
+    initial_cause:i32,
+    calls:usize
+}
+impl ConnectionState for CalculatorState {
+    fn get_initial_cause(self) -> i32 {self.initial_cause}
+}
+impl Calculator for CalculatorState {
+    fn square(&mut self, request:i32) -> std::io::Result<i64> {
+        self.calls += 1;
+        Ok(request as i64 * request as i64)
+    }
+    fn join(&mut self, request:Vec<String>) -> std::io::Result<String> {
+        self.calls += 1;
+        Ok(request.join(" "))
+    }
+    fn halve(&mut self, request:i64) -> std::io::Result<i64> {
+        self.calls += 1;
+        if request % 2 == 0 { Ok(request / 2) } else { Err(std::io::Error::new(ErrorKind::InvalidInput, "odd")) }
+    }
+}
+fn calculator_handle_interaction<S:McnpTransport>(typed_cause:(i32, i32), con:&mut McnpConnection<S>, mut state:CalculatorState) -> CalculatorState {
+    match state.dispatch(typed_cause.1, con) {
+        Ok(true) => {},
+        Ok(false) => { //not the calculator's, tells how many calls were made
+            con.send_fixed_chunk_u64(state.calls as u64).unwrap();
+        },
+        Err(e) => println!("calculator call failed: {}", e) //the client was told
+    }
+    state
+}
+
+#[test]
+fn mcnp_rpc_test() {
+    let (client_end, server_end) = memory_pipe();
+    let server_thread = thread::spawn(move || {
+        McnpServer::handle_connection(McnpConnection::new_from_stream(server_end), |initial_cause, _| CalculatorState{initial_cause, calls:0}, calculator_handle_interaction);
+    });
+
+    let mut con = McnpConnection::new_from_stream(client_end);
+    con.send_cause(1).unwrap();
+    let mut client = CalculatorClient::new(con);
+    assert_eq!(3, <Square as McnpProcedure>::CAUSE);
+    assert_eq!(1522756, client.square(&1234).unwrap());
+    assert_eq!("a b c", client.join(&vec!["a".to_string(), "b".to_string(), "c".to_string()]).unwrap());
+    assert_eq!(21, client.halve(&42).unwrap());
+    assert_eq!(ErrorKind::Other, client.halve(&3).unwrap_err().kind());
+    assert_eq!(-8, call::<Halve, _>(client.connection(), &-16).unwrap()); //still in sync
+
+    //outside of the service
+    client.connection().send_cause(99).unwrap();
+    assert_eq!(5, client.connection().read_fixed_chunk_u64().unwrap());
+
+    //a request that is not an encoding of the procedure's request (i32 is 4 bytes)
+    let con = client.connection();
+    con.send_cause(<Square as McnpProcedure>::CAUSE).unwrap();
+    send_message(con, "no i32").unwrap();
+    assert_eq!(None, con.read_variable_chunk_optional().unwrap());
+    assert_eq!(4, client.square(&2).unwrap());
+
+    drop(client);
+    server_thread.join().unwrap();
+}
+
+#[test]
+fn mcnp_send_chunk_from_stream_test() {
+    let (a, b) = memory_pipe();
+    let mut sender = McnpConnection::new_from_stream(a);
+    let mut receiver = McnpConnection::new_from_stream(b);
+    let content:Vec<u8> = (0..10000).map(|i| i as u8).collect();
+
+    //exactly as long as announced, longer than the send buffer
+    sender.send_variable_chunk_from_stream(&mut Cursor::new(&content), 10000).unwrap();
+    assert_eq!(content, receiver.read_variable_chunk().unwrap());
+
+    //longer than announced, the rest is not read
+    let mut longer = Cursor::new(&content);
+    sender.send_variable_chunk_from_stream(&mut longer, 60).unwrap();
+    assert_eq!(60, longer.position());
+    assert_eq!(&content[..60], &receiver.read_variable_chunk().unwrap()[..]);
+
+    //empty and none chunks have no content
+    sender.send_variable_chunk_from_stream(&mut Cursor::new(&content), 0).unwrap();
+    assert!(receiver.read_variable_chunk().unwrap().is_empty());
+    sender.send_variable_chunk_from_stream(&mut Cursor::new(&content), -1).unwrap();
+    assert!(receiver.read_variable_chunk().is_err());
+
+    sender.send_fixed_chunk_i32(77).unwrap();
+    assert_eq!(77, receiver.read_fixed_chunk_i32().unwrap()); //still in sync
+
+    //shorter than announced, the chunk is aborted: the receiver reads eof instead of the missing bytes, the sender cannot send anymore
+    let e = sender.send_variable_chunk_from_stream(&mut Cursor::new(&content[..30]), 50).unwrap_err();
+    assert_eq!(ErrorKind::UnexpectedEof, e.kind());
+    assert_eq!(ErrorKind::UnexpectedEof, receiver.read_variable_chunk().unwrap_err().kind());
+    assert_eq!(ErrorKind::BrokenPipe, sender.send_fixed_chunk_i32(77).unwrap_err().kind());
+    receiver.send_fixed_chunk_i32(78).unwrap(); //only the sending direction is shut down
+    assert_eq!(78, sender.read_fixed_chunk_i32().unwrap());
+
+    //failing in the middle, aborted as well
+    let (a, b) = memory_pipe();
+    let mut sender = McnpConnection::new_from_stream(a);
+    let mut receiver = McnpConnection::new_from_stream(b);
+    let e = sender.send_variable_chunk_from_stream(&mut FailingSource { content:vec![9u8; 5], interrupted:false }, 8).unwrap_err();
+    assert_eq!(ErrorKind::Other, e.kind());
+    let (mut chunk_stream, length) = receiver.read_variable_chunk_as_stream().unwrap();
+    assert_eq!(8, length);
+    let mut received = vec![0u8; length as usize];
+    assert_eq!(ErrorKind::UnexpectedEof, chunk_stream.read_exact(&mut received).unwrap_err().kind());
+
+    drop(receiver);
+    drop(chunk_stream);
+    let (a, b) = memory_pipe();
+    let mut sender = McnpConnection::new_from_stream(a);
+    drop(b);
+    assert_eq!(ErrorKind::BrokenPipe, sender.send_variable_chunk_from_stream(&mut Cursor::new(&content), 100).unwrap_err().kind());
+}
+
+//returns its content (after being interrupted once), then fails
+struct FailingSource {
+    content:Vec<u8>,
+    interrupted:bool
+}
+impl Read for FailingSource {
+    fn read(&mut self, buf:&mut [u8]) -> std::io::Result<usize> {
+        if !self.interrupted {
+            self.interrupted = true;
+            return Err(std::io::Error::from(ErrorKind::Interrupted))
+        }
+        if self.content.is_empty() {
+            return Err(std::io::Error::other("source failed"))
+        }
+        let read = buf.len().min(self.content.len());
+        buf[..read].copy_from_slice(&self.content[..read]);
+        self.content.drain(..read);
+        Ok(read)
+    }
+}
+
+#[test]
+fn mcnp_server_max_chunk_length_test() {
+    let server = McnpServer::builder().max_chunk_length(10).build().unwrap();
+    let port = server.local_addr().unwrap().port();
+    let shutdown_handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run_server_listener_loop(|initial_cause, _| DefaultConnectionState{initial_cause}, echo_chunk_handle_interaction));
+
+    let mut client = McnpClient::new("127.0.0.1", port);
+    client.send_cause(1).unwrap();
+    client.send_cause(8).unwrap();
+    client.send_variable_chunk(&[1u8; 10]).unwrap();
+    assert_eq!(vec![1u8; 10], client.read_variable_chunk().unwrap());
+    client.send_cause(8).unwrap();
+    client.send_variable_chunk(&[1u8; 11]).unwrap(); //the handler fails to read it and leaves it unread
+    assert_closed_by_server(&mut client);
+
+    shutdown_handle.shutdown();
+    server_thread.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn mcnp_unix_socket_test() {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    //as a transport of its own, over a listener
+    let socket_path = std::env::temp_dir().join(format!("mcnp_unix_socket_test_{}", std::process::id()));
+    let _ = std::fs::remove_file(&socket_path);
+    let listener = UnixListener::bind(&socket_path).unwrap();
+    let server = McnpServer::builder().max_connections(1).build().unwrap();
+    let shutdown_handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run_unix_listener_loop(&listener, test_server_new_connection, test_server_handle_interaction));
+    run_client_test(&mut McnpClient::new_unix(&socket_path).unwrap());
+
+    //the connections share the pool of the server
+    let mut first = McnpClient::new_unix(&socket_path).unwrap();
+    first.send_cause(1).unwrap();
+    first.send_fixed_chunk_u8(1).unwrap();
+    assert_eq!(1, first.read_fixed_chunk_u8().unwrap());
+    let mut second = McnpClient::new_unix(&socket_path).unwrap(); //waits in the backlog
+    second.send_cause(1).unwrap();
+    second.send_fixed_chunk_u8(2).unwrap();
+    second.transport().set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    assert!(second.read_fixed_chunk_u8().is_err());
+    second.transport().set_read_timeout(None).unwrap();
+    drop(first);
+    assert_eq!(2, second.read_fixed_chunk_u8().unwrap());
+
+    //and are closed once it shuts down
+    shutdown_handle.shutdown();
+    server_thread.join().unwrap();
+    assert!(second.read_fixed_chunk_u8().is_err());
+    std::fs::remove_file(&socket_path).unwrap();
+
+    //and wrapped like any other duplex stream
+    let (client_end, server_end) = UnixStream::pair().unwrap();
+    thread::spawn(move || {
+        McnpServer::handle_connection(McnpConnection::new_from_read_write(server_end), test_server_new_connection, test_server_handle_interaction);
+    });
+    run_client_test(&mut McnpConnection::new_from_read_write(client_end));
+}
+
+
+#[cfg(feature = "tls")]
+#[test]
+fn mcnp_tls_test() {
+    const PORT:u16 = 17732;
+    let (server_config, client_config) = self_signed_tls_configs("localhost");
+
+    thread::spawn(move || {
+        let server = McnpServer::new(PORT);
+        server.run_tls_server_listener_loop(server_config, |initial_cause, _con| {
+            DefaultConnectionState{initial_cause}
+        }, |_typed_cause, con, state| {
+            if let Ok(bytes) = con.read_variable_chunk() { //fails for an aborted chunk
+                con.send_variable_chunk(&bytes).unwrap();
+            }
+            state
+        });
+    });
+    thread::sleep(Duration::from_millis(500));
+
+    let mut client = McnpClient::new_tls("127.0.0.1", PORT, client_config.clone(), "localhost").unwrap();
+    client.send_cause(1).unwrap();
+    client.send_cause(2).unwrap();
+    let send_vec_stream = vec![3u8;100000];
+    client.send_variable_chunk(&send_vec_stream).unwrap();
+    let mut read_vec_stream_back = client.read_variable_chunk_as_stream().unwrap();
+    let mut stream_buf = vec![0u8; read_vec_stream_back.1 as usize];
+    read_vec_stream_back.0.read_exact(&mut stream_buf).unwrap();
+    assert_eq!(send_vec_stream, stream_buf);
+
+    //an aborted chunk is not echoed, the server reads eof and closes the connection
+    client.send_cause(2).unwrap();
+    assert!(client.send_variable_chunk_from_stream(&mut &send_vec_stream[..10], 20).is_err());
+    assert!(client.read_variable_chunk().is_err());
+
+    //the certificate is not valid for this name
+    assert!(McnpClient::new_tls("127.0.0.1", PORT, client_config, "not-localhost").is_err());
+    //and not trusted without being added to the roots
+    let untrusting_config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions().unwrap()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
+    assert!(McnpClient::new_tls("127.0.0.1", PORT, Arc::new(untrusting_config), "localhost").is_err());
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn mcnp_tls_multiplexed_test() {
+    let (server_config, client_config) = self_signed_tls_configs("localhost");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server_thread = thread::spawn(move || {
+        let tls = TlsStream::accept(listener.accept().unwrap().0, server_config).unwrap();
+        McnpServer::handle_multiplexed_connection(McnpConnection::new_from_stream(tls), DEFAULT_MAX_STREAMS_PER_CONNECTION, DEFAULT_STREAM_BUFFER_LIMIT, test_server_new_connection, test_server_handle_interaction);
+    });
+    //the demultiplexer waits in a read of the session all the time, streams still write through it
+    let multiplexer = McnpMultiplexer::negotiate(McnpClient::new_tls("127.0.0.1", port, client_config, "localhost").unwrap()).unwrap();
+
+    let mut uploading = multiplexer.open(1).unwrap();
+    uploading.send_fixed_chunk_u8(11).unwrap();
+    assert_eq!(11, uploading.read_fixed_chunk_u8().unwrap());
+    uploading.send_cause(8).unwrap();
+    uploading.start_variable_chunk(100000).unwrap();
+    uploading.send_variable_chunk_part(&[5u8; 60000]).unwrap();
+
+    let clients:Vec<_> = (0..4).map(|_| {
+        let mut con = McnpConnection::new_from_stream(multiplexer.open_stream().unwrap());
+        thread::spawn(move || run_client_test(&mut con))
+    }).collect();
+    for client in clients {
+        client.join().unwrap();
+    }
+
+    uploading.send_variable_chunk_part(&[5u8; 40000]).unwrap();
+    assert_eq!(vec![5u8; 100000], uploading.read_variable_chunk().unwrap());
+    assert!(!multiplexer.is_closed());
+
+    drop(uploading);
+    drop(multiplexer);
+    server_thread.join().unwrap();
+}
+
+/// Server and client config for a freshly generated self signed certificate for dns_name, trusted by the client config.
+#[cfg(feature = "tls")]
+pub(crate) fn self_signed_tls_configs(dns_name:&str) -> (Arc<rustls::ServerConfig>, Arc<rustls::ClientConfig>) {
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    let generated = rcgen::generate_simple_self_signed(vec![dns_name.to_string()]).unwrap();
+    let certificate = generated.cert.der().clone();
+    let private_key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(generated.key_pair.serialize_der()));
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let server_config = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions().unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![certificate.clone()], private_key).unwrap();
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(certificate).unwrap();
+    let client_config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions().unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    (Arc::new(server_config), Arc::new(client_config))
+}
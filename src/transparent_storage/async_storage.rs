@@ -0,0 +1,50 @@
+extern crate tokio;
+
+use std::io;
+use std::io::Cursor;
+
+use self::tokio::io::AsyncRead;
+use self::tokio::io::AsyncWrite;
+
+use crate::transparent_storage::StorageSystemError;
+
+/// Async counterpart of StorageSystem.
+///   Same semantics as the sync methods of the same name, see there.
+///   Only available with the "async" feature.
+#[allow(async_fn_in_trait)]
+pub trait AsyncStorageSystem {
+    async fn set_content(&mut self, bytes : &[u8]) -> Result<(), StorageSystemError>;
+    async fn get_content(&mut self) -> Result<Vec<u8>, StorageSystemError>;
+    /// Requires a mutable reference, because some implementations have to seek to find out.
+    async fn content_size(&mut self) -> Result<i64, StorageSystemError>;
+
+    async fn delete(&mut self, start:i64, end:i64) -> Result<(), StorageSystemError>;
+    async fn append(&mut self, bytes : &[u8]) -> Result<(), StorageSystemError>;
+    ///copies exactly stream_length bytes from stream to the end of storage.
+    /// If stream ends before stream_length is reached the remaining bytes are padded with 0's.
+    async fn append_stream<R:AsyncRead + Unpin + ?Sized>(&mut self, stream : &mut R, stream_length:i64) -> Result<(), StorageSystemError>;
+
+    ///returns a copy of the bytes between start(incl) and end(excl)
+    /// very large subarrays should not be read. (because memory and stuff)
+    async fn subarray(&mut self, start:i64, end:i64) -> Result<Vec<u8>, StorageSystemError>;
+    /// Alternative to subarray for very far apart start and end. Copies the bytes between start(incl) and end(excl) into target.
+    ///   (the async replacement for substream - a stream borrowing the storage system would block any other use of it anyways)
+    async fn copy_range_to<W:AsyncWrite + Unpin + ?Sized>(&mut self, start:i64, end:i64, target:&mut W) -> Result<(), StorageSystemError>;
+}
+
+/// Truncating or extending is not part of AsyncWrite/AsyncSeek, but required to delete or set content.
+#[allow(async_fn_in_trait)]
+pub trait AsyncSetLen {
+    async fn set_len(&mut self, len:u64) -> io::Result<()>;
+}
+impl AsyncSetLen for tokio::fs::File {
+    async fn set_len(&mut self, len:u64) -> io::Result<()> {
+        tokio::fs::File::set_len(self, len).await
+    }
+}
+impl AsyncSetLen for Cursor<Vec<u8>> {
+    async fn set_len(&mut self, len:u64) -> io::Result<()> {
+        self.get_mut().resize(len as usize, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+extern crate tokio;
+
+use std::cmp;
+use std::io::Cursor;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use self::tokio::fs::File;
+use self::tokio::fs::OpenOptions;
+use self::tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::transparent_storage::async_storage::AsyncSetLen;
+use crate::transparent_storage::async_storage::AsyncStorageSystem;
+use crate::transparent_storage::StorageSystemError;
+
+/// Async storage system over anything that can be read, written, seeked and truncated.
+///   Typically a tokio::fs::File (see open_leave_source_intact) or, mostly for tests, a Cursor<Vec<u8>>.
+pub struct AsyncStreamStorageSystem<F>
+    where F: AsyncRead + AsyncWrite + AsyncSeek + AsyncSetLen + Unpin {
+    inner:F,
+    copy_buf:Vec<u8>
+}
+impl<F> AsyncStreamStorageSystem<F>
+    where F: AsyncRead + AsyncWrite + AsyncSeek + AsyncSetLen + Unpin {
+    pub fn new(inner:F) -> AsyncStreamStorageSystem<F> {
+        AsyncStreamStorageSystem::new_with_custom_buf_size(inner, 8192)
+    }
+    pub fn new_with_custom_buf_size(inner:F, internal_copy_buf_size:usize) -> AsyncStreamStorageSystem<F> {
+        AsyncStreamStorageSystem {
+            inner,
+            copy_buf:vec![0u8; internal_copy_buf_size]
+        }
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+impl AsyncStreamStorageSystem<File> {
+    /// async version of FileStorageSystem::create_leave_source_intact
+    pub async fn open_leave_source_intact<P:AsRef<Path>>(path:P) -> Result<AsyncStreamStorageSystem<File>, StorageSystemError> {
+        let file = OpenOptions::new().create(true).truncate(false).read(true).write(true).open(path).await?;
+        Ok(AsyncStreamStorageSystem::new(file))
+    }
+}
+impl AsyncStreamStorageSystem<Cursor<Vec<u8>>> {
+    pub fn new_in_memory() -> AsyncStreamStorageSystem<Cursor<Vec<u8>>> {
+        AsyncStreamStorageSystem::new(Cursor::new(Vec::new()))
+    }
+}
+
+impl<F> AsyncStorageSystem for AsyncStreamStorageSystem<F>
+    where F: AsyncRead + AsyncWrite + AsyncSeek + AsyncSetLen + Unpin {
+    async fn set_content(&mut self, bytes: &[u8]) -> Result<(), StorageSystemError> {
+        self.inner.seek(SeekFrom::Start(0)).await?;
+        self.inner.write_all(bytes).await?;
+        self.inner.flush().await?;
+        self.inner.set_len(bytes.len() as u64).await?;
+        Ok(())
+    }
+
+    // returns the entire content, has to be copied into ram first. Not recommended for large files.
+    async fn get_content(&mut self) -> Result<Vec<u8>, StorageSystemError> {
+        self.inner.seek(SeekFrom::Start(0)).await?;
+        let mut content = Vec::new();
+        self.inner.read_to_end(&mut content).await?;
+        Ok(content)
+    }
+
+    async fn content_size(&mut self) -> Result<i64, StorageSystemError> {
+        Ok(self.inner.seek(SeekFrom::End(0)).await? as i64)
+    }
+
+    async fn delete(&mut self, start: i64, end: i64) -> Result<(), StorageSystemError> {
+        if start < 0 {
+            return Err(StorageSystemError::new("start smaller than 0"))
+        } else if end < start {
+            return Err(StorageSystemError::new("end < start"))
+        }
+        let content_size = self.content_size().await? as u64;
+        let start = start as u64;
+        let end = cmp::min(end as u64, content_size);
+        let tail_len = content_size - end;
+
+        let mut bytes_transferred_counter: u64 = 0;
+        while bytes_transferred_counter < tail_len {
+            let to_transfer = cmp::min(tail_len - bytes_transferred_counter, self.copy_buf.len() as u64) as usize;
+            self.inner.seek(SeekFrom::Start(end + bytes_transferred_counter)).await?;
+            self.inner.read_exact(&mut self.copy_buf[..to_transfer]).await?;
+            self.inner.seek(SeekFrom::Start(start + bytes_transferred_counter)).await?;
+            self.inner.write_all(&self.copy_buf[..to_transfer]).await?;
+            bytes_transferred_counter += to_transfer as u64;
+        }
+        self.inner.flush().await?;
+        self.inner.set_len(start + tail_len).await?;
+        Ok(())
+    }
+
+    async fn append(&mut self, bytes: &[u8]) -> Result<(), StorageSystemError> {
+        self.inner.seek(SeekFrom::End(0)).await?;
+        self.inner.write_all(bytes).await?;
+        self.inner.flush().await?;
+        Ok(())
+    }
+
+    async fn append_stream<R:AsyncRead + Unpin + ?Sized>(&mut self, stream: &mut R, stream_length: i64) -> Result<(), StorageSystemError> {
+        if stream_length < 0 {
+            return Err(StorageSystemError::new("negative stream length"))
+        }
+        self.inner.seek(SeekFrom::End(0)).await?;
+        let copied = tokio::io::copy(&mut stream.take(stream_length as u64), &mut self.inner).await?;
+        if copied < stream_length as u64 { //keeps the content decodable, see StorageSystem::append_stream
+            tokio::io::copy(&mut tokio::io::repeat(0).take(stream_length as u64 - copied), &mut self.inner).await?;
+        }
+        self.inner.flush().await?;
+        Ok(())
+    }
+
+    async fn subarray(&mut self, start: i64, end: i64) -> Result<Vec<u8>, StorageSystemError> {
+        if start > end {
+            return Err(StorageSystemError::new("start index greater than end index. That doesn't make much sense to this code"))
+        }
+        let end = cmp::min(end, self.content_size().await?);
+        if start >= end {
+            return Ok(Vec::new())
+        }
+        let mut subvec = vec![0u8; (end - start) as usize];
+        self.inner.seek(SeekFrom::Start(start as u64)).await?;
+        self.inner.read_exact(&mut subvec).await?;
+        Ok(subvec)
+    }
+
+    async fn copy_range_to<W:AsyncWrite + Unpin + ?Sized>(&mut self, start: i64, end: i64, target: &mut W) -> Result<(), StorageSystemError> {
+        if start > end {
+            return Err(StorageSystemError::new("start index greater than end index. That doesn't make much sense to this code"))
+        }
+        self.inner.seek(SeekFrom::Start(start as u64)).await?;
+        let copied = tokio::io::copy(&mut (&mut self.inner).take((end - start) as u64), target).await?;
+        if copied < (end - start) as u64 {
+            return Err(StorageSystemError::new("range reaches beyond the end of the content"))
+        }
+        Ok(())
+    }
+}
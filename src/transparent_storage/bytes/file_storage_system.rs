@@ -1,10 +1,12 @@
 use std::cmp;
 use std::fs::{File, OpenOptions};
+use std::io;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
 use std::io::Write;
 
+use crate::transparent_storage::PositionalStorageSystem;
 use crate::transparent_storage::StorageSystem;
 use crate::transparent_storage::StorageSystemError;
 use crate::transparent_storage::Substream;
@@ -126,7 +128,6 @@ impl StorageSystem for FileStorageSystem {
     }
 
     fn append_stream(&mut self, stream: &mut dyn Read, stream_length: i64) -> Result<(), StorageSystemError> {
-        println!("file storage system append_stream");
         let content_size = self.content_size()?;
         let cont_length = content_size as u64;
         let new_write_pos = self.file.seek(SeekFrom::Start(cont_length))?;
@@ -140,14 +141,18 @@ impl StorageSystem for FileStorageSystem {
         unsafe {self.copy_buf.set_len(buf_size)}
 
         while bytes_transferred_counter < stream_length {
-            let bytes_read = stream.read(&mut self.copy_buf)?;
+            let to_read = cmp::min(self.copy_buf.len() as i64, stream_length - bytes_transferred_counter) as usize;
+            let bytes_read = stream.read(&mut self.copy_buf[..to_read])?;
+            if bytes_read == 0 {
+                return Err(StorageSystemError::new("stream ended before stream_length was reached"))
+            }
             let bytes_written = self.file.write(&self.copy_buf[..bytes_read])?;
 
             if bytes_written != bytes_read {
                 return Err(StorageSystemError::new("Could not write correct amount of bytes"))
             }
 
-            bytes_transferred_counter += self.copy_buf.len() as i64;
+            bytes_transferred_counter += bytes_read as i64;
         }
 
         Ok(())
@@ -179,4 +184,41 @@ impl StorageSystem for FileStorageSystem {
             Ok(Substream::new(orig, start as u64, end as u64))
         }
     }
-}
\ No newline at end of file
+}
+impl PositionalStorageSystem for FileStorageSystem {
+    fn read_at(&self, start: i64, end: i64) -> Result<Vec<u8>, StorageSystemError> {
+        if start > end {
+            return Err(StorageSystemError::new("start index greater than end index. That doesn't make much sense to this code"))
+        }
+        let end = cmp::min(end, self.content_size()?);
+        if start >= end {
+            return Ok(Vec::new())
+        }
+        let mut subvec = vec![0u8; (end - start) as usize];
+        read_exact_at(&self.file, &mut subvec, start as u64)?;
+        Ok(subvec)
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file:&File, buf:&mut [u8], offset:u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buf, offset)
+}
+#[cfg(windows)]
+fn read_exact_at(file:&File, mut buf:&mut [u8], mut offset:u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    //seek_read does move the cursor on windows, but all other methods seek before using it anyways
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "failed to fill whole buffer")),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(())
+}
@@ -1,2 +1,4 @@
 pub mod file_storage_system;
-pub mod vec_storage_system;
\ No newline at end of file
+pub mod vec_storage_system;
+#[cfg(feature = "async")]
+pub mod async_stream_storage_system;
\ No newline at end of file
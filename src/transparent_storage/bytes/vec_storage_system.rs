@@ -4,6 +4,7 @@ use std::cmp;
 use std::fs::File;
 use std::io::Read;
 
+use crate::transparent_storage::PositionalStorageSystem;
 use crate::transparent_storage::StorageSystem;
 use crate::transparent_storage::StorageSystemError;
 use crate::transparent_storage::Substream;
@@ -121,4 +122,15 @@ impl StorageSystem for VecStorageSystem {
         //todo, also requires different trait type - can be done quite easily with generics, but looks pretty terrible
         unimplemented!()
     }
-}
\ No newline at end of file
+}
+impl PositionalStorageSystem for VecStorageSystem {
+    fn read_at(&self, start: i64, end: i64) -> Result<Vec<u8>, StorageSystemError> {
+        if start > end {
+            Err(StorageSystemError::new("start index greater than end index. That doesn't make much sense to this code"))
+        } else {
+            let end = cmp::min(end, self.content_size()?) as usize;
+            let start = cmp::min(start as usize, end);
+            Ok(self.data[start..end].to_vec())
+        }
+    }
+}
@@ -11,6 +11,8 @@ use std::io::Seek;
 use std::io::SeekFrom;
 
 pub mod bytes;
+#[cfg(feature = "async")]
+pub mod async_storage;
 
 //todo do a substream of a read(not a way to specific "File" type) of sorts.. Looks ugly with generics
 pub trait StorageSystem {
@@ -34,7 +36,7 @@ pub trait StorageSystem {
     ///appends provided bytes to the end of storage.
     fn append(&mut self, bytes : &[u8]) -> Result<(), StorageSystemError>;
     ///copies all bytes from stream to the end of storage until stream_length is reached.
-    /// If stream ends before stream_length is reached behaviour is undefined, though storage MAY pad the remaining bytes with something.
+    /// If stream ends before stream_length is reached behaviour is undefined, the storage MAY pad the remaining bytes with something or return Err (having appended part of the stream).
     fn append_stream(&mut self, stream : &mut dyn Read, stream_length:i64) -> Result<(), StorageSystemError>;
 
     ///returns a copy of the bytes between start(incl) and end(excl)
@@ -49,6 +51,14 @@ pub trait StorageSystem {
 }
 
 
+/// Storage systems that can be read without exclusive access, i.e. from multiple threads at the same time.
+///   Used for concurrent readers (see SharedUbae). Writing still requires &mut.
+pub trait PositionalStorageSystem : StorageSystem {
+    /// same as subarray, but does not move any shared cursor (pread on files)
+    fn read_at(&self, start:i64, end:i64) -> Result<Vec<u8>, StorageSystemError>;
+}
+
+
 
 #[derive(Debug)]
 pub struct StorageSystemError {
@@ -86,6 +96,11 @@ impl From<std::io::Error> for StorageSystemError {
         StorageSystemError::new(&our_str)
     }
 }
+impl From<StorageSystemError> for std::io::Error {
+    fn from(storage_err: StorageSystemError) -> Self {
+        std::io::Error::other(storage_err)
+    }
+}
 
 
 
@@ -122,11 +137,12 @@ impl<R:Read> Read for Substream<R> {
         if self.cur_pos >= self.end_pos {
             return Ok(0)
         }
-        let remaining_len = self.end_pos-self.cur_pos;
-        match self.orig_file.read(buf) {
+        //never reads past the end, on a connection the bytes after it belong to whatever is sent next
+        let remaining_len = cmp::min(self.end_pos-self.cur_pos, buf.len() as u64) as usize;
+        match self.orig_file.read(&mut buf[..remaining_len]) {
             Ok(bytes_read) => {
                 self.cur_pos += bytes_read as u64;
-                return Ok(cmp::min(remaining_len as usize, bytes_read));
+                return Ok(bytes_read);
             },
             Err(e) => {
                 return Err(e);
@@ -0,0 +1,138 @@
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+
+//a fresh directory within the temp directory, removed again once dropped (also if the test fails)
+struct TestDir {
+    path:PathBuf
+}
+impl TestDir {
+    fn new(name:&str) -> TestDir {
+        let path = env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path); //left over by an aborted run
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+}
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+fn ubae(args:&[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ubae")).args(args).output().expect("running ubae failed")
+}
+fn ubae_ok(args:&[&Path]) -> Vec<u8> {
+    let output = ubae(args);
+    assert!(output.status.success(), "ubae {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+fn ubae_ok_with_stdin(args:&[&Path], stdin:&[u8]) -> Vec<u8> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ubae")).args(args)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        .spawn().expect("running ubae failed");
+    child.stdin.take().unwrap().write_all(stdin).unwrap(); //closed once dropped, so ubae reads eof
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "ubae {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+#[test]
+fn pack_ls_cat_unpack_round_trip_test() {
+    let test_root = TestDir::new("ubae_cli_round_trip");
+    let orig_dir = test_root.path.join("orig");
+    let archive = test_root.path.join("orig.ubae");
+    let out_dir = test_root.path.join("out");
+    fs::create_dir_all(orig_dir.join("sub/empty")).unwrap();
+    fs::write(orig_dir.join("a.txt"), b"content of a").unwrap();
+    fs::write(orig_dir.join("sub/b.bin"), vec![7u8; 70_000]).unwrap();
+
+    ubae_ok(&[Path::new("pack"), &orig_dir, &archive]);
+
+    let listed = String::from_utf8(ubae_ok(&[Path::new("ls"), &archive])).unwrap();
+    assert!(listed.lines().any(|line| line == "12\ta.txt"), "{}", listed);
+    assert!(listed.lines().any(|line| line == "70000\tsub/b.bin"), "{}", listed);
+
+    assert_eq!(b"content of a".to_vec(), ubae_ok(&[Path::new("cat"), &archive, Path::new("a.txt")]));
+    assert_eq!(vec![7u8; 70_000], ubae_ok(&[Path::new("cat"), &archive, Path::new("sub/b.bin")]));
+    assert_eq!(Some(1), ubae(&[Path::new("cat"), &archive, Path::new("missing")]).status.code());
+
+    ubae_ok(&[Path::new("unpack"), &archive, &out_dir]);
+    assert_eq!(b"content of a".to_vec(), fs::read(out_dir.join("a.txt")).unwrap());
+    assert_eq!(vec![7u8; 70_000], fs::read(out_dir.join("sub/b.bin")).unwrap());
+    assert!(out_dir.join("sub/empty").is_dir());
+
+    //the target of unpack must not exist, wrong arguments print the usage
+    assert_eq!(Some(1), ubae(&[Path::new("unpack"), &archive, &out_dir]).status.code());
+    assert_eq!(Some(2), ubae(&[Path::new("pack"), &orig_dir]).status.code());
+}
+
+#[test]
+fn put_rm_fsck_test() {
+    let test_root = TestDir::new("ubae_cli_put_rm_fsck");
+    let container = test_root.path.join("container.ubae");
+    let source = test_root.path.join("source.bin");
+    fs::write(&source, vec![3u8; 50_000]).unwrap();
+
+    ubae_ok(&[Path::new("put"), &container, Path::new("from_file"), &source]);
+    ubae_ok_with_stdin(&[Path::new("put"), &container, Path::new("from_stdin"), Path::new("-")], b"content of stdin");
+    assert_eq!(vec![3u8; 50_000], ubae_ok(&[Path::new("cat"), &container, Path::new("from_file")]));
+    assert_eq!(b"content of stdin".to_vec(), ubae_ok(&[Path::new("cat"), &container, Path::new("from_stdin")]));
+
+    //put replaces an existing entry
+    ubae_ok_with_stdin(&[Path::new("put"), &container, Path::new("from_stdin"), Path::new("-")], b"replaced");
+    assert_eq!(b"replaced".to_vec(), ubae_ok(&[Path::new("cat"), &container, Path::new("from_stdin")]));
+    let fsck = String::from_utf8(ubae_ok(&[Path::new("fsck"), &container])).unwrap();
+    assert!(fsck.contains("2 entries, no problems found"), "{}", fsck);
+
+    ubae_ok(&[Path::new("rm"), &container, Path::new("from_stdin")]);
+    assert_eq!(Some(1), ubae(&[Path::new("cat"), &container, Path::new("from_stdin")]).status.code());
+    assert_eq!(Some(1), ubae(&[Path::new("rm"), &container, Path::new("from_stdin")]).status.code());
+    assert_eq!(vec![3u8; 50_000], ubae_ok(&[Path::new("cat"), &container, Path::new("from_file")]));
+
+    //a container cut off within its last entry
+    let length = fs::metadata(&container).unwrap().len();
+    OpenOptions::new().write(true).open(&container).unwrap().set_len(length - 100).unwrap();
+    let truncated = ubae(&[Path::new("fsck"), &container]);
+    assert_eq!(Some(1), truncated.status.code());
+    assert!(String::from_utf8_lossy(&truncated.stderr).contains("truncated"), "{}", String::from_utf8_lossy(&truncated.stderr));
+}
+
+#[test]
+fn export_import_tar_round_trip_test() {
+    let test_root = TestDir::new("ubae_cli_tar");
+    let orig_dir = test_root.path.join("orig");
+    let archive = test_root.path.join("orig.ubae");
+    let tar = test_root.path.join("orig.tar");
+    fs::create_dir_all(orig_dir.join("sub")).unwrap();
+    fs::write(orig_dir.join("a.txt"), b"content of a").unwrap();
+    fs::write(orig_dir.join("sub/b.bin"), vec![7u8; 70_000]).unwrap();
+    ubae_ok(&[Path::new("pack"), &orig_dir, &archive]);
+
+    //to and from a file
+    ubae_ok(&[Path::new("export-tar"), &archive, &tar]);
+    let imported = test_root.path.join("imported.ubae");
+    ubae_ok(&[Path::new("import-tar"), &tar, &imported]);
+    assert_eq!(b"content of a".to_vec(), ubae_ok(&[Path::new("cat"), &imported, Path::new("a.txt")]));
+    let out_dir = test_root.path.join("out");
+    ubae_ok(&[Path::new("unpack"), &imported, &out_dir]);
+    assert_eq!(b"content of a".to_vec(), fs::read(out_dir.join("a.txt")).unwrap());
+    assert_eq!(vec![7u8; 70_000], fs::read(out_dir.join("sub/b.bin")).unwrap());
+
+    //through stdout and stdin, the same stream as written to the file
+    let tar_stream = ubae_ok(&[Path::new("export-tar"), &archive, Path::new("-")]);
+    assert_eq!(fs::read(&tar).unwrap(), tar_stream);
+    let piped = test_root.path.join("piped.ubae");
+    ubae_ok_with_stdin(&[Path::new("import-tar"), Path::new("-"), &piped], &tar_stream);
+    assert_eq!(vec![7u8; 70_000], ubae_ok(&[Path::new("cat"), &piped, Path::new("sub/b.bin")]));
+
+    //an existing tar is not overwritten
+    assert_eq!(Some(1), ubae(&[Path::new("export-tar"), &archive, &tar]).status.code());
+}